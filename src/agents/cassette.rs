@@ -0,0 +1,402 @@
+//! Record-and-replay completion model for offline tests
+//!
+//! In record mode, every request is forwarded to a live model and its
+//! response written to `<dir>/<request hash>.json`. In replay mode, responses
+//! are read back from those files and no provider is contacted, so the whole
+//! pipeline runs deterministically without network access. A request that
+//! was never recorded fails with a [`CompletionError::ProviderError`] naming
+//! the missing file.
+
+use std::path::{Path, PathBuf};
+
+use rig::{
+    OneOrMany,
+    completion::{
+        AssistantContent, CompletionError, CompletionModel, CompletionRequest, CompletionResponse,
+    },
+    streaming::{RawStreamingChoice, StreamingCompletionResponse},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use super::model::{Model, restream};
+use crate::{
+    prompts::fnv1a,
+    usage::{TokenCount, TokenUsage},
+};
+
+/// Whether a cassette records live responses or replays recorded ones
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CassetteMode {
+    Record,
+    Replay,
+}
+
+/// Raw response of a cassette: the usage recorded with the response
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CassetteResponse {
+    pub tokens: Option<TokenCount>,
+}
+
+impl TokenUsage for CassetteResponse {
+    fn token_usage(&self) -> Option<TokenCount> {
+        self.tokens
+    }
+}
+
+/// A recorded request and its response
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    request: Value,
+    choice: Vec<AssistantContent>,
+    tokens: Option<TokenCount>,
+}
+
+/// Completion model recording to or replaying from a cassette directory
+#[derive(Clone)]
+pub struct CassetteModel<M = Offline> {
+    inner: M,
+    dir: PathBuf,
+    mode: CassetteMode,
+}
+
+impl CassetteModel {
+    /// Replay the responses recorded in `dir`, without a live model
+    pub fn replay(dir: impl Into<PathBuf>) -> Self {
+        Self::new(Offline, dir, CassetteMode::Replay)
+    }
+}
+
+impl<M: Model> CassetteModel<M> {
+    /// Record the responses of `inner` to `dir`
+    pub fn record(inner: M, dir: impl Into<PathBuf>) -> Self {
+        Self::new(inner, dir, CassetteMode::Record)
+    }
+
+    pub fn new(inner: M, dir: impl Into<PathBuf>, mode: CassetteMode) -> Self {
+        Self {
+            inner,
+            dir: dir.into(),
+            mode,
+        }
+    }
+
+    async fn load(&self, key: &str) -> Result<Entry, CompletionError> {
+        let path = self.dir.join(format!("{key}.json"));
+
+        let bytes = tokio::fs::read(&path).await.map_err(|e| {
+            CompletionError::ProviderError(format!(
+                "No recorded response at {}: {e}",
+                path.display()
+            ))
+        })?;
+
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+}
+
+impl<M: Model> CompletionModel for CassetteModel<M> {
+    type Response = CassetteResponse;
+    type StreamingResponse = CassetteResponse;
+
+    async fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<CompletionResponse<Self::Response>, CompletionError> {
+        let (key, request_json) = request_key(&request);
+
+        let (choice, tokens) = match self.mode {
+            CassetteMode::Replay => {
+                let entry = self.load(&key).await?;
+                (to_choice(entry.choice)?, entry.tokens)
+            }
+            CassetteMode::Record => {
+                let response = self.inner.completion(request).await?;
+                let tokens = response.raw_response.token_usage();
+
+                save(&self.dir, &key, request_json, &response.choice, tokens)?;
+                (response.choice, tokens)
+            }
+        };
+
+        Ok(CompletionResponse {
+            choice,
+            raw_response: CassetteResponse { tokens },
+        })
+    }
+
+    async fn stream(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<StreamingCompletionResponse<Self::StreamingResponse>, CompletionError> {
+        let (key, request_json) = request_key(&request);
+
+        match self.mode {
+            CassetteMode::Replay => {
+                let entry = self.load(&key).await?;
+
+                let chunks = entry
+                    .choice
+                    .into_iter()
+                    .map(|content| {
+                        Ok(match content {
+                            AssistantContent::Text(text) => RawStreamingChoice::Message(text.text),
+                            AssistantContent::ToolCall(tool_call) => RawStreamingChoice::ToolCall {
+                                id: tool_call.id,
+                                call_id: tool_call.call_id,
+                                name: tool_call.function.name,
+                                arguments: tool_call.function.arguments,
+                            },
+                        })
+                    })
+                    .chain(std::iter::once(Ok(RawStreamingChoice::FinalResponse(
+                        CassetteResponse {
+                            tokens: entry.tokens,
+                        },
+                    ))));
+
+                Ok(StreamingCompletionResponse::stream(Box::pin(
+                    futures::stream::iter(chunks.collect::<Vec<_>>()),
+                )))
+            }
+            CassetteMode::Record => {
+                let response = self.inner.stream(request).await?;
+                let dir = self.dir.clone();
+
+                Ok(restream(response, move |choice, last| {
+                    let tokens = last.as_ref().and_then(TokenUsage::token_usage);
+
+                    if let Err(e) = save(&dir, &key, request_json, choice, tokens) {
+                        tracing::warn!(error = %e, "failed to record streamed response");
+                    }
+                    Some(CassetteResponse { tokens })
+                }))
+            }
+        }
+    }
+}
+
+/// Model of a replaying cassette; fails every request it receives
+#[derive(Clone, Debug)]
+pub struct Offline;
+
+impl CompletionModel for Offline {
+    type Response = CassetteResponse;
+    type StreamingResponse = CassetteResponse;
+
+    async fn completion(
+        &self,
+        _request: CompletionRequest,
+    ) -> Result<CompletionResponse<Self::Response>, CompletionError> {
+        Err(offline_error())
+    }
+
+    async fn stream(
+        &self,
+        _request: CompletionRequest,
+    ) -> Result<StreamingCompletionResponse<Self::StreamingResponse>, CompletionError> {
+        Err(offline_error())
+    }
+}
+
+fn offline_error() -> CompletionError {
+    CompletionError::ProviderError("No live model available while replaying".to_string())
+}
+
+/// Hash of everything the model sees in a request, and the request as recorded
+fn request_key(request: &CompletionRequest) -> (String, Value) {
+    let mut tools = request.tools.clone();
+    tools.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let request_json = canonical(json!({
+        "preamble": request.preamble,
+        "chat_history": request.chat_history,
+        "documents": request.documents,
+        "tools": tools,
+        "temperature": request.temperature,
+        "max_tokens": request.max_tokens,
+        "additional_params": request.additional_params,
+    }));
+
+    let key = format!("{:016x}", fnv1a(request_json.to_string().as_bytes()));
+    (key, request_json)
+}
+
+/// `value` with the keys of every object sorted, so equal requests hash equally
+fn canonical(value: Value) -> Value {
+    match value {
+        Value::Object(fields) => {
+            let mut fields = fields.into_iter().collect::<Vec<_>>();
+            fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(key, field)| (key, canonical(field)))
+                    .collect(),
+            )
+        }
+        Value::Array(items) => Value::Array(items.into_iter().map(canonical).collect()),
+        other => other,
+    }
+}
+
+fn save(
+    dir: &Path,
+    key: &str,
+    request: Value,
+    choice: &OneOrMany<AssistantContent>,
+    tokens: Option<TokenCount>,
+) -> Result<(), CompletionError> {
+    let entry = Entry {
+        request,
+        choice: choice.iter().cloned().collect(),
+        tokens,
+    };
+
+    std::fs::create_dir_all(dir)
+        .and_then(|_| {
+            std::fs::write(
+                dir.join(format!("{key}.json")),
+                serde_json::to_vec_pretty(&entry)?,
+            )
+        })
+        .map_err(|e| CompletionError::ProviderError(format!("Failed to record response: {e}")))
+}
+
+fn to_choice(
+    choice: Vec<AssistantContent>,
+) -> Result<OneOrMany<AssistantContent>, CompletionError> {
+    OneOrMany::many(choice)
+        .map_err(|_| CompletionError::ResponseError("Recorded response is empty".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use futures::StreamExt;
+    use rig::{agent::AgentBuilder, completion::Prompt, streaming::StreamingPrompt};
+
+    use super::*;
+
+    /// Live model stand-in answering every request with the same text
+    #[derive(Clone, Default)]
+    struct Stub {
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl CompletionModel for Stub {
+        type Response = CassetteResponse;
+        type StreamingResponse = CassetteResponse;
+
+        async fn completion(
+            &self,
+            _request: CompletionRequest,
+        ) -> Result<CompletionResponse<Self::Response>, CompletionError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+
+            Ok(CompletionResponse {
+                choice: OneOrMany::one(AssistantContent::text("Acme Corp pays the most.")),
+                raw_response: CassetteResponse {
+                    tokens: Some(TokenCount {
+                        prompt_tokens: 10,
+                        completion_tokens: 5,
+                    }),
+                },
+            })
+        }
+
+        async fn stream(
+            &self,
+            _request: CompletionRequest,
+        ) -> Result<StreamingCompletionResponse<Self::StreamingResponse>, CompletionError> {
+            Err(offline_error())
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("cassette-{name}-{}", std::process::id()))
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let dir = temp_dir("replay");
+        let stub = Stub::default();
+
+        let recorded = AgentBuilder::new(CassetteModel::record(stub.clone(), &dir))
+            .preamble("Answer briefly.")
+            .build()
+            .prompt("Who pays the most?")
+            .await
+            .unwrap();
+
+        let replayed = AgentBuilder::new(CassetteModel::replay(&dir))
+            .preamble("Answer briefly.")
+            .build()
+            .prompt("Who pays the most?")
+            .await
+            .unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(recorded, "Acme Corp pays the most.");
+        assert_eq!(replayed, recorded);
+        assert_eq!(stub.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_replay_stream() {
+        let dir = temp_dir("stream");
+
+        AgentBuilder::new(CassetteModel::record(Stub::default(), &dir))
+            .build()
+            .prompt("Who pays the most?")
+            .await
+            .unwrap();
+
+        let mut stream = AgentBuilder::new(CassetteModel::replay(&dir))
+            .build()
+            .stream_prompt("Who pays the most?")
+            .await
+            .unwrap();
+
+        let mut text = String::new();
+        while let Some(chunk) = stream.next().await {
+            if let AssistantContent::Text(chunk) = chunk.unwrap() {
+                text.push_str(&chunk.text);
+            }
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(text, "Acme Corp pays the most.");
+        assert_eq!(stream.response.unwrap().tokens.unwrap().total(), 15);
+    }
+
+    #[tokio::test]
+    async fn test_replay_miss_fails() {
+        let result = AgentBuilder::new(CassetteModel::replay(temp_dir("empty")))
+            .build()
+            .prompt("Never recorded")
+            .await;
+
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("No recorded response")
+        );
+    }
+
+    #[test]
+    fn test_request_key_ignores_key_order() {
+        assert_eq!(
+            canonical(json!({ "b": 1, "a": { "d": 2, "c": 3 } })).to_string(),
+            canonical(json!({ "a": { "c": 3, "d": 2 }, "b": 1 })).to_string()
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! Sub-agents answering from a local data file
+//!
+//! The file is loaded into a table of its own embedded in-memory database,
+//! with an inferred schema, the first time the sub-agent is asked a
+//! question; from then on it is queried with the same SurrealDB tools as
+//! any other table.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use futures::future::BoxFuture;
+use tokio::sync::OnceCell;
+
+use super::{
+    AgentError, SubAgentResult,
+    model::Model,
+    query::SurrealSubAgent,
+    sub_agent::{SubAgent, SubAgentConfig, SubAgentContext},
+};
+use crate::{
+    config::SurrealConfig,
+    import::{ImportOptions, import_file},
+    mcp::client::McpConnections,
+    surreal::{self, SurrealDbConfig},
+};
+
+/// Sub-agent answering from a CSV, JSONL, JSON or Parquet file
+pub(crate) struct FileSubAgent {
+    agent: SurrealSubAgent,
+    path: PathBuf,
+    db: SurrealDbConfig,
+    options: ImportOptions,
+    loaded: OnceCell<()>,
+}
+
+impl FileSubAgent {
+    /// Answer from the rows of `path`, in the table named by the sub-agent,
+    /// or after the file when it names none
+    pub(crate) fn new(mut config: SubAgentConfig, path: PathBuf, mcp: Arc<McpConnections>) -> Self {
+        static DATABASES: AtomicUsize = AtomicUsize::new(0);
+
+        if config.table.is_empty() {
+            config.table = table_name(&path);
+        }
+
+        // Each sub-agent gets a database of its own, so files never share a table
+        let surreal_config = SurrealConfig {
+            host: format!("mem://file-{}", DATABASES.fetch_add(1, Ordering::Relaxed)),
+            username: String::new(),
+            password: String::new(),
+            namespace: "files".to_string(),
+            database: "files".to_string(),
+        };
+
+        Self {
+            db: SurrealDbConfig::from(&surreal_config),
+            // A retry after a failed load starts from an empty table
+            options: ImportOptions::new(&config.table).define(true).replace(true),
+            agent: SurrealSubAgent::new(config, surreal_config, mcp),
+            path,
+            loaded: OnceCell::new(),
+        }
+    }
+
+    async fn load(&self) -> Result<(), AgentError> {
+        let report = import_file(&self.db, &self.path, &self.options).await?;
+        tracing::info!(
+            path = %self.path.display(),
+            table = %report.table,
+            rows = report.rows,
+            "loaded data file"
+        );
+
+        Ok(())
+    }
+}
+
+impl Drop for FileSubAgent {
+    fn drop(&mut self) {
+        surreal::release(&self.db);
+    }
+}
+
+impl<M: Model> SubAgent<M> for FileSubAgent {
+    fn name(&self) -> &str {
+        SubAgent::<M>::name(&self.agent)
+    }
+
+    fn description(&self) -> &str {
+        SubAgent::<M>::description(&self.agent)
+    }
+
+    fn answer<'a>(
+        &'a self,
+        question: &'a str,
+        context: SubAgentContext<'a, M>,
+    ) -> BoxFuture<'a, Result<SubAgentResult, AgentError>> {
+        Box::pin(async move {
+            self.loaded.get_or_try_init(|| self.load()).await?;
+            self.agent.answer(question, context).await
+        })
+    }
+}
+
+/// Table name for the rows of a file: its name without the extension, with
+/// anything but letters, digits and underscores replaced by underscores
+fn table_name(path: &std::path::Path) -> String {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+
+    let name: String = stem
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    match name.chars().next() {
+        Some(c) if !c.is_ascii_digit() => name,
+        _ => format!("rows_{name}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        agents::{mock::ScriptedModel, model::Llm, query::MAX_TURNS},
+        prompts::Prompts,
+        surreal::QueryLedger,
+    };
+
+    #[test]
+    fn test_table_name() {
+        assert_eq!(table_name("data/Q3 pipeline.csv".as_ref()), "Q3_pipeline");
+        assert_eq!(
+            table_name("2024-renewals.jsonl".as_ref()),
+            "rows_2024_renewals"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_answers_from_loaded_file() {
+        let dir = std::env::temp_dir().join(format!("file-agent-{}", std::process::id()));
+        let path = dir.join("renewals.csv");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            &path,
+            "customer,renews_at,seats\nAcme Corp,2025-03-01T00:00:00Z,120\nGlobex,2025-06-01T00:00:00Z,40\n",
+        )
+        .unwrap();
+
+        let agent = FileSubAgent::new(
+            SubAgentConfig {
+                name: "renewals".to_string(),
+                description: "Finds upcoming contract renewals.".to_string(),
+                table: String::new(),
+                table_context: "Renewal dates and seats, one row per contract.".to_string(),
+                mcp: None,
+                file: Some(path.clone()),
+            },
+            path.clone(),
+            Arc::default(),
+        );
+        let model = ScriptedModel::new()
+            .tool_call(
+                "surreal_select",
+                json!({ "query": "SELECT id, customer FROM renewals WHERE seats > 100" }),
+            )
+            .text("Acme Corp renews 120 seats.");
+
+        let result = agent
+            .answer(
+                "Which renewals have more than 100 seats?",
+                SubAgentContext {
+                    llm: &Llm::new(model.clone(), "mock"),
+                    prompts: &Prompts::embedded(),
+                    ledger: QueryLedger::new(),
+                    max_turns: MAX_TURNS,
+                    embedder: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let preamble = model.requests()[0].preamble.clone().unwrap();
+        assert!(preamble.contains("renewals"));
+        assert_eq!(result.rows.len(), 1);
+        assert_eq!(result.rows[0]["customer"], "Acme Corp");
+        assert_eq!(result.rows_used[0].table(), "renewals");
+    }
+}
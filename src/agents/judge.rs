@@ -0,0 +1,46 @@
+//! LLM-as-judge scoring of answers against rubric criteria
+
+use rig::completion::Prompt;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    AgentError,
+    model::{Llm, Model},
+    parse_json,
+};
+use crate::{
+    prompts::{self, Prompts},
+    usage::Stage,
+};
+
+/// Verdict of the judge on a single rubric criterion
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Verdict {
+    pub criterion: String,
+    pub pass: bool,
+    pub reason: String,
+}
+
+/// Judge `answer` against each of the `criteria`
+#[tracing::instrument(name = "judge", skip_all, fields(criteria = criteria.len()))]
+pub async fn judge<M: Model>(
+    llm: &Llm<M>,
+    prompts: &Prompts,
+    question: &str,
+    answer: &str,
+    criteria: &[String],
+) -> Result<Vec<Verdict>, AgentError> {
+    let preamble = prompts.render(
+        prompts::JUDGE,
+        serde_json::json!({ "question": question, "answer": answer, "criteria": criteria }),
+    )?;
+
+    let response = llm
+        .agent(Stage::Judge, None)
+        .preamble(&preamble)
+        .build()
+        .prompt("Grade the answer against every criterion.")
+        .await?;
+
+    parse_json(&response).map_err(|source| AgentError::InvalidVerdict { response, source })
+}
@@ -0,0 +1,86 @@
+//! Labels of clusters of similar rows, written by an LLM
+
+use std::collections::BTreeMap;
+
+use rig::completion::Prompt;
+
+use super::{
+    AgentError,
+    model::{Llm, Model},
+    parse_json,
+};
+use crate::{
+    prompts::{self, Prompts},
+    usage::Stage,
+};
+
+/// Label each cluster of texts from `table.field`, given some of its texts
+/// with the most typical first
+///
+/// The model answers with the label of each cluster keyed by its number,
+/// from 1; clusters it left out get an empty label.
+#[tracing::instrument(name = "label", skip_all, fields(clusters = clusters.len()))]
+pub async fn label_clusters<M: Model>(
+    llm: &Llm<M>,
+    prompts: &Prompts,
+    table: &str,
+    field: &str,
+    clusters: &[Vec<String>],
+) -> Result<Vec<String>, AgentError> {
+    let preamble = prompts.render(
+        prompts::LABEL,
+        serde_json::json!({ "table": table, "field": field, "clusters": clusters }),
+    )?;
+
+    let response = llm
+        .agent(Stage::Label, None)
+        .preamble(&preamble)
+        .build()
+        .prompt("Label every group.")
+        .await?;
+
+    let labels: BTreeMap<String, String> =
+        parse_json(&response).map_err(|source| AgentError::InvalidLabels { response, source })?;
+
+    Ok((1..=clusters.len())
+        .map(|number| {
+            labels
+                .get(&number.to_string())
+                .map_or("", |label| label.trim())
+                .to_string()
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::mock::ScriptedModel;
+
+    #[tokio::test]
+    async fn test_label_clusters_by_number() {
+        let model = ScriptedModel::new().text(
+            r#"```json
+{"2": " Dark mode ", "3": "Unknown group"}
+```"#,
+        );
+        let clusters = vec![
+            vec!["We need SSO.".to_string(), "SAML login please".to_string()],
+            vec!["Dark mode?".to_string()],
+        ];
+
+        let labels = label_clusters(
+            &Llm::new(model.clone(), "mock"),
+            &Prompts::embedded(),
+            "feature_requests",
+            "message",
+            &clusters,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(labels, ["", "Dark mode"]);
+        let preamble = model.requests()[0].preamble.clone().unwrap();
+        assert!(preamble.contains("Group 2:\n- Dark mode?"));
+    }
+}
@@ -1,46 +1,149 @@
 use std::{collections::BTreeMap, fmt::Display};
 
-use rig::{client::CompletionClient, completion::Prompt, providers::xai::Client};
+use rig::completion::Prompt;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SubQuestions {
-    pub feature_requests: String,
-    pub customers: String,
+use super::{
+    AgentError,
+    model::{Llm, Model},
+    parse_json,
+};
+use crate::{
+    prompts::{self, Prompts},
+    usage::Stage,
+};
+
+/// Sub-questions keyed by the name of the sub-agent they are addressed to
+pub type SubQuestions = BTreeMap<String, String>;
+
+/// A question answered earlier in a conversation
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Turn {
+    pub question: String,
+    /// Plan the question was answered with
+    pub sub_questions: SubQuestions,
+    pub answer: String,
 }
 
-pub async fn map<S: Display>(
-    client: &Client,
+/// Split `question` into sub-questions for the given sub-agents, resolving
+/// references to the earlier turns of `history`
+#[tracing::instrument(name = "map", skip_all)]
+pub async fn map<M: Model, S: Display>(
+    llm: &Llm<M>,
+    prompts: &Prompts,
     question: &str,
     sub_agents: &BTreeMap<S, S>,
-) -> SubQuestions {
-    let sub_agents_string = sub_agents
-        .into_iter()
-        .map(|(name, desc)| format!("- \"{}\": \"{}\"", name, desc))
-        .collect::<Vec<String>>()
-        .join("\n");
-
-    let agent1 = client
-            .agent("grok-3-mini")
-            .preamble(&format!(
-                r#"
-                You are a helpful assistant that can answer questions by delagating sub-questions to a sub-agent.
-                Sub-agents are specialized in answering questions from a specific dataset.
-                Sub-agents do not have access to data that they do not specialize in.
-
-                You have access to the following sub-agents:
-                {sub_agents_string}
-
-                Please respond with a JSON object map with a key being the name of the sub-agent and a value being the sub-question to ask the sub-agent.
-                "#,
-            ))
-            .build();
-
-    // Prompt the model and print its response
-    let response = agent1
-        .prompt(question)
+    history: &[Turn],
+) -> Result<SubQuestions, AgentError> {
+    let sub_agents = sub_agents
+        .iter()
+        .map(|(name, description)| {
+            serde_json::json!({ "name": name.to_string(), "description": description.to_string() })
+        })
+        .collect::<Vec<_>>();
+
+    let preamble = prompts.render(
+        prompts::MAP,
+        serde_json::json!({ "sub_agents": sub_agents, "history": history }),
+    )?;
+
+    let agent1 = llm.agent(Stage::Map, None).preamble(&preamble).build();
+
+    let response = agent1.prompt(question).await?;
+
+    parse_json(&response).map_err(|source| AgentError::InvalidPlan { response, source })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::mock::ScriptedModel;
+
+    fn sub_agents() -> BTreeMap<&'static str, &'static str> {
+        BTreeMap::from([("customers", "Finds customers and their ARR.")])
+    }
+
+    #[tokio::test]
+    async fn test_map_parses_plan() {
+        let model = ScriptedModel::new()
+            .text("```json\n{\"customers\": \"Which customers pay the most?\"}\n```");
+
+        let plan = map(
+            &Llm::new(model, "mock"),
+            &Prompts::embedded(),
+            "Who pays the most?",
+            &sub_agents(),
+            &[],
+        )
         .await
-        .expect("Failed to prompt grok-3-mini");
+        .unwrap();
+
+        assert_eq!(plan["customers"], "Which customers pay the most?");
+    }
+
+    #[tokio::test]
+    async fn test_map_sees_history() {
+        let model = ScriptedModel::new()
+            .text(r#"{"customers": "Which customers above $100k ARR pay the most?"}"#);
+        let history = [Turn {
+            question: "Who pays the most?".to_string(),
+            sub_questions: [(
+                "customers".to_string(),
+                "Which customers pay the most?".to_string(),
+            )]
+            .into(),
+            answer: "Acme Corp and Globex.".to_string(),
+        }];
+
+        map(
+            &Llm::new(model.clone(), "mock"),
+            &Prompts::embedded(),
+            "Now only above $100k ARR",
+            &sub_agents(),
+            &history,
+        )
+        .await
+        .unwrap();
+
+        let preamble = model.requests()[0].preamble.clone().unwrap();
+        assert!(preamble.contains("Question: Who pays the most?"));
+        assert!(preamble.contains("- \"customers\" was asked: Which customers pay the most?"));
+        assert!(preamble.contains("Answer: Acme Corp and Globex."));
+    }
+
+    #[tokio::test]
+    async fn test_map_rejects_malformed_json() {
+        let model = ScriptedModel::new().text("Ask the customers agent about ARR.");
+
+        let result = map(
+            &Llm::new(model, "mock"),
+            &Prompts::embedded(),
+            "Who pays the most?",
+            &sub_agents(),
+            &[],
+        )
+        .await;
+
+        assert!(matches!(
+            result,
+            Err(AgentError::InvalidPlan { response, .. }) if response == "Ask the customers agent about ARR."
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_map_reports_model_error() {
+        let model = ScriptedModel::new().error("rate limited");
+
+        let result = map(
+            &Llm::new(model, "mock"),
+            &Prompts::embedded(),
+            "Who pays the most?",
+            &sub_agents(),
+            &[],
+        )
+        .await;
 
-    serde_json::from_str::<SubQuestions>(&response).unwrap()
+        assert!(matches!(result, Err(AgentError::Prompt(_))));
+        assert!(result.unwrap_err().to_string().contains("rate limited"));
+    }
 }
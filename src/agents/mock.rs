@@ -0,0 +1,188 @@
+//! Scripted completion model for unit tests of agent logic
+//!
+//! Tests queue the replies the model gives, in order: text, tool calls or
+//! errors. Every request the model receives is kept, so tests can assert
+//! what the agent sent, e.g. the tool results of a previous turn. Running
+//! past the end of the script fails the request.
+//!
+//! [`TopicEmbedding`] stands in for an embedding model the same way.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use rig::{
+    OneOrMany,
+    completion::{
+        AssistantContent, CompletionError, CompletionModel, CompletionRequest, CompletionResponse,
+    },
+    embeddings::{Embedding, EmbeddingError, EmbeddingModel},
+    streaming::{RawStreamingChoice, StreamingCompletionResponse},
+};
+use serde_json::Value;
+
+/// A scripted reply of a [`ScriptedModel`]
+#[derive(Clone, Debug)]
+pub enum Reply {
+    Text(String),
+    ToolCall { name: String, arguments: Value },
+    Error(String),
+}
+
+/// Completion model answering with scripted replies
+#[derive(Clone, Default)]
+pub struct ScriptedModel {
+    replies: Arc<Mutex<VecDeque<Reply>>>,
+    requests: Arc<Mutex<Vec<CompletionRequest>>>,
+}
+
+impl ScriptedModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a reply
+    pub fn reply(self, reply: Reply) -> Self {
+        self.replies.lock().unwrap().push_back(reply);
+        self
+    }
+
+    /// Queue a text answer
+    pub fn text(self, text: impl Into<String>) -> Self {
+        self.reply(Reply::Text(text.into()))
+    }
+
+    /// Queue a call of the tool `name`
+    pub fn tool_call(self, name: impl Into<String>, arguments: Value) -> Self {
+        self.reply(Reply::ToolCall {
+            name: name.into(),
+            arguments,
+        })
+    }
+
+    /// Queue a provider error
+    pub fn error(self, message: impl Into<String>) -> Self {
+        self.reply(Reply::Error(message.into()))
+    }
+
+    /// Requests received so far, in order
+    pub fn requests(&self) -> Vec<CompletionRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Replies not yet given
+    pub fn remaining(&self) -> usize {
+        self.replies.lock().unwrap().len()
+    }
+
+    fn next(&self, request: CompletionRequest) -> Result<AssistantContent, CompletionError> {
+        let mut requests = self.requests.lock().unwrap();
+        requests.push(request);
+        let turn = requests.len();
+
+        match self.replies.lock().unwrap().pop_front() {
+            Some(Reply::Text(text)) => Ok(AssistantContent::text(text)),
+            Some(Reply::ToolCall { name, arguments }) => Ok(AssistantContent::tool_call(
+                format!("call_{turn}"),
+                name,
+                arguments,
+            )),
+            Some(Reply::Error(message)) => Err(CompletionError::ProviderError(message)),
+            None => Err(CompletionError::ProviderError(format!(
+                "No scripted reply for request {turn}"
+            ))),
+        }
+    }
+}
+
+impl CompletionModel for ScriptedModel {
+    type Response = ();
+    type StreamingResponse = ();
+
+    async fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<CompletionResponse<Self::Response>, CompletionError> {
+        Ok(CompletionResponse {
+            choice: OneOrMany::one(self.next(request)?),
+            raw_response: (),
+        })
+    }
+
+    async fn stream(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<StreamingCompletionResponse<Self::StreamingResponse>, CompletionError> {
+        let chunk = match self.next(request)? {
+            AssistantContent::Text(text) => RawStreamingChoice::Message(text.text),
+            AssistantContent::ToolCall(tool_call) => RawStreamingChoice::ToolCall {
+                id: tool_call.id,
+                call_id: tool_call.call_id,
+                name: tool_call.function.name,
+                arguments: tool_call.function.arguments,
+            },
+        };
+
+        Ok(StreamingCompletionResponse::stream(Box::pin(
+            futures::stream::iter([Ok(chunk), Ok(RawStreamingChoice::FinalResponse(()))]),
+        )))
+    }
+}
+
+/// Embedding model with one dimension per topic, counting the keywords of
+/// the topic a text mentions, so tests can tell which texts are close in
+/// meaning without a model
+#[derive(Clone)]
+pub struct TopicEmbedding {
+    topics: Arc<Vec<Vec<String>>>,
+}
+
+impl TopicEmbedding {
+    /// Embed texts by `topics`, each a list of lowercase keywords
+    pub fn new(topics: &[&[&str]]) -> Self {
+        let topics = topics
+            .iter()
+            .map(|keywords| keywords.iter().map(ToString::to_string).collect())
+            .collect();
+
+        Self {
+            topics: Arc::new(topics),
+        }
+    }
+}
+
+impl EmbeddingModel for TopicEmbedding {
+    const MAX_DOCUMENTS: usize = 2;
+
+    fn ndims(&self) -> usize {
+        self.topics.len()
+    }
+
+    async fn embed_texts(
+        &self,
+        texts: impl IntoIterator<Item = String> + Send,
+    ) -> Result<Vec<Embedding>, EmbeddingError> {
+        Ok(texts
+            .into_iter()
+            .map(|text| {
+                let lowercase = text.to_lowercase();
+                let vec = self
+                    .topics
+                    .iter()
+                    .map(|keywords| {
+                        let mentions = keywords
+                            .iter()
+                            .filter(|keyword| lowercase.contains(keyword.as_str()))
+                            .count();
+                        // Never all zeros, which has no cosine similarity
+                        0.01 + mentions as f64
+                    })
+                    .collect();
+
+                Embedding {
+                    document: text,
+                    vec,
+                }
+            })
+            .collect())
+    }
+}
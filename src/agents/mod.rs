@@ -1,3 +1,212 @@
+pub mod cassette;
+pub mod file;
+pub mod judge;
+pub mod label;
 pub mod map;
+pub mod mock;
+pub mod model;
 pub mod query;
 pub mod reduce;
+pub mod sub_agent;
+
+use std::error::Error as StdError;
+use std::fmt;
+
+use rig::completion::{CompletionError, PromptError};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+
+use crate::{
+    citations::CitationReport, import::ImportError, mcp::McpError, prompts::TemplateError,
+    surreal::QueryLedger,
+};
+
+/// Error of an agent of the pipeline
+#[derive(Debug)]
+pub enum AgentError {
+    /// The preamble of the agent could not be rendered
+    Template(TemplateError),
+    /// The model failed, or the agent ran out of turns
+    Prompt(Box<PromptError>),
+    /// The map agent answered with something other than a JSON object of sub-questions
+    InvalidPlan {
+        response: String,
+        source: serde_json::Error,
+    },
+    /// The judge agent answered with something other than a JSON array of verdicts
+    InvalidVerdict {
+        response: String,
+        source: serde_json::Error,
+    },
+    /// The label agent answered with something other than a JSON object of labels
+    InvalidLabels {
+        response: String,
+        source: serde_json::Error,
+    },
+    /// The MCP server of a sub-agent could not be reached
+    Mcp(McpError),
+    /// The data file of a sub-agent could not be loaded
+    Import(ImportError),
+    /// The token budget cannot hold the prompts of the reduce and summarize agents
+    BudgetTooSmall { budget: usize, overhead: usize },
+    /// The sub-agent results did not fit in the token budget, even summarized
+    BudgetExceeded { budget: usize, tokens: usize },
+}
+
+impl fmt::Display for AgentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AgentError::Template(err) => write!(f, "Failed to render prompt: {err}"),
+            AgentError::Prompt(err) => write!(f, "Failed to prompt model: {err}"),
+            AgentError::InvalidPlan { response, source } => {
+                write!(
+                    f,
+                    "Invalid sub-questions from map agent ({source}): {response}"
+                )
+            }
+            AgentError::InvalidVerdict { response, source } => {
+                write!(
+                    f,
+                    "Invalid verdicts from judge agent ({source}): {response}"
+                )
+            }
+            AgentError::InvalidLabels { response, source } => {
+                write!(f, "Invalid labels from label agent ({source}): {response}")
+            }
+            AgentError::Mcp(err) => write!(f, "{err}"),
+            AgentError::Import(err) => write!(f, "Failed to load data file: {err}"),
+            AgentError::BudgetTooSmall { budget, overhead } => write!(
+                f,
+                "Token budget of {budget} is too small for the prompts, which take {overhead} tokens"
+            ),
+            AgentError::BudgetExceeded { budget, tokens } => write!(
+                f,
+                "Sub-agent results still take {tokens} tokens after summarizing, over the token budget of {budget}"
+            ),
+        }
+    }
+}
+
+impl StdError for AgentError {}
+
+impl From<TemplateError> for AgentError {
+    fn from(err: TemplateError) -> Self {
+        AgentError::Template(err)
+    }
+}
+
+impl From<PromptError> for AgentError {
+    fn from(err: PromptError) -> Self {
+        AgentError::Prompt(Box::new(err))
+    }
+}
+
+impl From<McpError> for AgentError {
+    fn from(err: McpError) -> Self {
+        AgentError::Mcp(err)
+    }
+}
+
+impl From<ImportError> for AgentError {
+    fn from(err: ImportError) -> Self {
+        AgentError::Import(err)
+    }
+}
+
+impl From<CompletionError> for AgentError {
+    fn from(err: CompletionError) -> Self {
+        PromptError::CompletionError(err).into()
+    }
+}
+
+/// Parse a JSON answer of a model, which models sometimes wrap in a
+/// Markdown code block
+pub(crate) fn parse_json<T: DeserializeOwned>(response: &str) -> Result<T, serde_json::Error> {
+    let json = response.trim();
+    let json = json
+        .strip_prefix("```json")
+        .or_else(|| json.strip_prefix("```"))
+        .and_then(|json| json.strip_suffix("```"))
+        .unwrap_or(json);
+
+    serde_json::from_str(json)
+}
+
+/// ID of a database record, e.g. `customers:n85php1nd6yiq7xhjwzi`
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct RecordId(String);
+
+impl RecordId {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self(id.into())
+    }
+
+    /// Table part of the ID
+    pub fn table(&self) -> &str {
+        self.0.split_once(':').map_or(&self.0, |(table, _)| table)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for RecordId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<String> for RecordId {
+    fn from(id: String) -> Self {
+        Self(id)
+    }
+}
+
+/// Structured answer of a query sub-agent
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SubAgentResult {
+    /// Name of the sub-agent
+    pub agent: String,
+    /// Sub-question the agent was asked
+    pub question: String,
+    /// Prose answer of the agent
+    pub answer: String,
+    /// IDs of the records returned by the agent's queries
+    pub rows_used: Vec<RecordId>,
+    /// Queries the agent executed, in order
+    pub queries_run: Vec<String>,
+    /// Raw rows returned by the agent's queries
+    #[serde(default)]
+    pub rows: Vec<Value>,
+    /// Verification of the record IDs cited in the answer
+    #[serde(default)]
+    pub citations: CitationReport,
+}
+
+impl SubAgentResult {
+    /// Result of `agent` answering `question`, with the queries and rows
+    /// recorded in `ledger` and the citations of the answer checked against them
+    pub fn from_ledger(agent: &str, question: &str, answer: String, ledger: &QueryLedger) -> Self {
+        let citations = CitationReport::verify(&answer, &ledger.record_ids());
+
+        Self {
+            agent: agent.to_string(),
+            question: question.to_string(),
+            answer,
+            rows_used: ledger
+                .record_ids()
+                .into_iter()
+                .map(RecordId::from)
+                .collect(),
+            queries_run: ledger
+                .records()
+                .into_iter()
+                .map(|record| record.query)
+                .collect(),
+            rows: ledger.rows(),
+            citations,
+        }
+    }
+}
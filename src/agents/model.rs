@@ -0,0 +1,257 @@
+//! Completion model wrapper tracing and metering every LLM turn
+
+use std::time::Instant;
+
+use futures::StreamExt;
+use rig::{
+    OneOrMany,
+    agent::AgentBuilder,
+    client::CompletionClient,
+    completion::{
+        AssistantContent, CompletionError, CompletionModel, CompletionRequest, CompletionResponse,
+    },
+    providers::xai::{self, Client},
+    streaming::{RawStreamingChoice, StreamingCompletionResponse},
+};
+use serde::Serialize;
+use serde_json::Value;
+use tracing::{Instrument, field};
+
+use crate::usage::{LlmCall, Stage, TokenUsage, UsageMeter};
+
+/// Model used by every agent of the pipeline
+pub const MODEL: &str = xai::GROK_3_MINI;
+
+/// A completion model whose responses report their token usage
+pub trait Model:
+    CompletionModel<Response: TokenUsage, StreamingResponse: TokenUsage> + 'static
+{
+}
+
+impl<M> Model for M where
+    M: CompletionModel<Response: TokenUsage, StreamingResponse: TokenUsage> + 'static
+{
+}
+
+/// The model the agents of a run are built from, and the meter of the run
+#[derive(Clone)]
+pub struct Llm<M = xai::completion::CompletionModel> {
+    model: M,
+    name: String,
+    meter: UsageMeter,
+}
+
+impl Llm {
+    /// The default model of an xAI client
+    pub fn xai(client: &Client) -> Self {
+        Self::new(client.completion_model(MODEL), MODEL)
+    }
+}
+
+impl<M: Model> Llm<M> {
+    /// `name` is the model name used to look up its price
+    pub fn new(model: M, name: impl Into<String>) -> Self {
+        Self {
+            model,
+            name: name.into(),
+            meter: UsageMeter::new(),
+        }
+    }
+
+    /// Record the usage of every call in the given meter
+    pub fn with_meter(mut self, meter: UsageMeter) -> Self {
+        self.meter = meter;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn meter(&self) -> &UsageMeter {
+        &self.meter
+    }
+
+    /// Builder for an agent of `stage`, tracing and metering its LLM turns
+    pub fn agent(&self, stage: Stage, agent: Option<&str>) -> AgentBuilder<TracedModel<M>> {
+        AgentBuilder::new(TracedModel {
+            inner: self.model.clone(),
+            model: self.name.clone(),
+            stage,
+            agent: agent.map(str::to_string),
+            meter: self.meter.clone(),
+        })
+    }
+}
+
+/// A completion model recording a `llm_turn` span for every request, and
+/// the request, response and token usage in the meter of the run
+///
+/// Multi-turn prompts send one request per turn, so tool calls show up
+/// between the turns that issued them.
+#[derive(Clone)]
+pub struct TracedModel<M> {
+    inner: M,
+    model: String,
+    stage: Stage,
+    agent: Option<String>,
+    meter: UsageMeter,
+}
+
+impl<M> TracedModel<M> {
+    fn span(&self, streaming: bool) -> tracing::Span {
+        tracing::info_span!(
+            "llm_turn",
+            stage = %self.stage,
+            agent = self.agent.as_deref(),
+            model = %self.model,
+            streaming,
+            prompt_tokens = field::Empty,
+            completion_tokens = field::Empty,
+            duration_ms = field::Empty,
+            error = field::Empty,
+        )
+    }
+
+    /// Log entry for `request`, completed once the response is known
+    fn call(&self, request: &CompletionRequest) -> LlmCall {
+        LlmCall {
+            stage: self.stage,
+            agent: self.agent.clone(),
+            model: self.model.clone(),
+            preamble: request.preamble.clone(),
+            messages: to_values(request.chat_history.iter()),
+            ..Default::default()
+        }
+    }
+
+    fn record(&self, span: &tracing::Span, mut call: LlmCall, started: Instant) {
+        call.duration_ms = started.elapsed().as_millis() as u64;
+
+        if let Some(tokens) = call.tokens {
+            span.record("prompt_tokens", tokens.prompt_tokens);
+            span.record("completion_tokens", tokens.completion_tokens);
+        }
+
+        self.meter.record(call);
+    }
+}
+
+impl<M: Model> CompletionModel for TracedModel<M> {
+    type Response = M::Response;
+    type StreamingResponse = M::StreamingResponse;
+
+    async fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<CompletionResponse<Self::Response>, CompletionError> {
+        let span = self.span(false);
+        let mut call = self.call(&request);
+        let started = Instant::now();
+
+        let response = self
+            .inner
+            .completion(request)
+            .instrument(span.clone())
+            .await;
+
+        record_outcome(&span, started, response.as_ref().err());
+        match &response {
+            Ok(response) => {
+                call.tokens = response.raw_response.token_usage();
+                call.response = to_values(response.choice.iter());
+            }
+            Err(err) => call.error = Some(err.to_string()),
+        }
+        self.record(&span, call, started);
+
+        response
+    }
+
+    async fn stream(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<StreamingCompletionResponse<Self::StreamingResponse>, CompletionError> {
+        let span = self.span(true);
+        let mut call = self.call(&request);
+        let started = Instant::now();
+
+        let response = self.inner.stream(request).instrument(span.clone()).await;
+
+        record_outcome(&span, started, response.as_ref().err());
+        let response = match response {
+            Ok(response) => response,
+            Err(err) => {
+                call.error = Some(err.to_string());
+                self.record(&span, call, started);
+                return Err(err);
+            }
+        };
+
+        // Usage arrives with the final response, once the caller consumed the stream
+        let model = self.clone();
+        Ok(restream(response, move |choice, last| {
+            call.tokens = last.as_ref().and_then(TokenUsage::token_usage);
+            call.response = to_values(choice.iter());
+            model.record(&span, call, started);
+            last
+        }))
+    }
+}
+
+/// Forward a streamed response, calling `on_end` with the complete choice
+/// and the final raw response once the stream is consumed
+///
+/// The raw response `on_end` returns is the one passed on to the caller.
+pub(crate) fn restream<R, S>(
+    response: StreamingCompletionResponse<R>,
+    on_end: impl FnOnce(&OneOrMany<AssistantContent>, Option<R>) -> Option<S> + Send + 'static,
+) -> StreamingCompletionResponse<S>
+where
+    R: Clone + Unpin + Send + 'static,
+    S: Clone + Unpin + Send + 'static,
+{
+    let chunks = futures::stream::unfold(Some((response, on_end)), |state| async move {
+        let (mut response, on_end) = state?;
+
+        let chunk = match response.next().await {
+            Some(Ok(AssistantContent::Text(text))) => Ok(RawStreamingChoice::Message(text.text)),
+            Some(Ok(AssistantContent::ToolCall(tool_call))) => Ok(RawStreamingChoice::ToolCall {
+                id: tool_call.id,
+                call_id: tool_call.call_id,
+                name: tool_call.function.name,
+                arguments: tool_call.function.arguments,
+            }),
+            Some(Err(err)) => Err(err),
+            None => {
+                let last = response.response.take();
+                let last = on_end(&response.choice, last);
+
+                return Some((Ok(RawStreamingChoice::FinalResponse(last?)), None));
+            }
+        };
+
+        Some((chunk, Some((response, on_end))))
+    });
+
+    StreamingCompletionResponse::stream(Box::pin(chunks))
+}
+
+fn to_values<'a, T: Serialize + 'a>(items: impl Iterator<Item = &'a T>) -> Vec<Value> {
+    items
+        .filter_map(|item| serde_json::to_value(item).ok())
+        .collect()
+}
+
+fn record_outcome(span: &tracing::Span, started: Instant, error: Option<&CompletionError>) {
+    let _entered = span.enter();
+    span.record("duration_ms", started.elapsed().as_millis() as u64);
+
+    match error {
+        Some(err) => {
+            span.record("error", field::display(err));
+            tracing::warn!(error = %err, "completion failed");
+        }
+        None => tracing::debug!("completion finished"),
+    }
+}
@@ -1,62 +1,370 @@
-use rig::{client::CompletionClient, completion::Prompt, providers::xai::Client};
+use std::sync::Arc;
 
+use futures::future::BoxFuture;
+use rig::completion::Prompt;
+use serde_json::json;
+
+use super::{
+    AgentError, SubAgentResult,
+    model::{Llm, Model},
+    sub_agent::{SubAgent, SubAgentConfig, SubAgentContext},
+};
 use crate::{
-    SurrealSelectTool,
+    SurrealSelectTool, cluster,
     config::SurrealConfig,
-    surreal::{SurrealDbConfig, SurrealSchemaTool},
+    mcp::{McpConnection, client::McpConnections},
+    prompts::{self, Prompts},
+    surreal::{
+        Embedder, QueryLedger, SurrealDbConfig, SurrealSchemaTool, SurrealSearchTool,
+        SurrealVectorSearchTool,
+    },
+    usage::Stage,
 };
 
-pub async fn question(
-    xai_client: &Client,
-    question: &str,
-    table: &str,
-    table_context: &str,
+/// Default maximum number of tool-calling turns before a sub-agent gives up
+pub const MAX_TURNS: usize = 10;
+
+/// A sub-question for a query sub-agent, and the tools it answers with
+pub struct QueryInput<'a> {
+    pub sub_agent: &'a SubAgentConfig,
+    pub question: &'a str,
+    /// Records the queries of the SurrealDB tools
+    pub ledger: QueryLedger,
+    /// Connection to the MCP server of the sub-agent, when it has one
+    pub mcp: Option<&'a McpConnection>,
+    /// Embedding model for `surreal_vector_search`, when the pipeline has one
+    pub embedder: Option<&'a Embedder>,
+}
+
+/// Answer a sub-question with the SurrealDB tools on the table of the
+/// sub-agent and the tools of its MCP server, giving up after `max_turns`
+/// tool-calling turns
+#[tracing::instrument(name = "sub_agent", skip_all, fields(agent = %input.sub_agent.name))]
+pub async fn question<M: Model>(
+    llm: &Llm<M>,
+    prompts: &Prompts,
+    input: QueryInput<'_>,
     surreal_config: &SurrealConfig,
-) -> String {
-    let surreal_db_config = SurrealDbConfig::new(
-        surreal_config.host.clone(),
-        surreal_config.username.clone(),
-        surreal_config.password.clone(),
-        surreal_config.namespace.clone(),
-        surreal_config.database.clone(),
-    );
+    max_turns: usize,
+) -> Result<SubAgentResult, AgentError> {
+    let QueryInput {
+        sub_agent,
+        question,
+        ledger,
+        mcp,
+        embedder,
+    } = input;
 
-    // Create tools
-    let schema_tool = SurrealSchemaTool::new(surreal_db_config.clone());
-    let select_tool = SurrealSelectTool::new(surreal_db_config);
+    let mcp_tools = mcp
+        .map(|mcp| {
+            mcp.tools()
+                .iter()
+                .map(|tool| json!({ "name": tool.name, "description": tool.description }))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
 
-    let agent_builder = xai_client
-        .agent("grok-3-mini")
-        .preamble(&format!(r#"
-            You are a helpful assistant that can answer questions from the {table} table.
-            {table_context}
+    let clusters = if sub_agent.table.is_empty() {
+        Vec::new()
+    } else {
+        // A table without clusters still gets an answer
+        cluster::clustered_fields(&SurrealDbConfig::from(surreal_config), &sub_agent.table)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::warn!(error = %e, "failed to look up clusters");
+                Vec::new()
+            })
+    };
 
-            You have access to tools for database operations:
-            - surreal_schema: Get schema information for database tables
-            - surreal_select: Execute SELECT queries to retrieve data
+    let preamble = prompts.render(
+        prompts::QUERY,
+        json!({
+            "table": sub_agent.table,
+            "table_context": sub_agent.table_context,
+            "mcp_tools": mcp_tools,
+            "vector_search": embedder.is_some(),
+            "clusters": clusters,
+        }),
+    )?;
 
-            Use the surreal_select tool to retrieve data from the {table} table.
+    let mut agent_builder = llm
+        .agent(Stage::Query, Some(&sub_agent.name))
+        .preamble(&preamble);
 
-            IMPORTANT: If a query fails with a syntax error or other issue, the tool will return an error message instead of failing. Read the error message carefully and correct your query syntax before trying again. Common issues include:
-            - Missing quotes around string values
-            - Incorrect parentheses matching
-            - Invalid SQL syntax
+    // Sub-agents without a table answer from their MCP tools alone
+    if !sub_agent.table.is_empty() {
+        let surreal_db_config = SurrealDbConfig::from(surreal_config);
+        agent_builder = agent_builder
+            .tool(SurrealSchemaTool::new(surreal_db_config.clone()))
+            .tool(SurrealSelectTool::new(surreal_db_config.clone()).with_ledger(ledger.clone()))
+            .tool(SurrealSearchTool::new(surreal_db_config.clone()).with_ledger(ledger.clone()));
 
-            Use CONTAINS operator in WHERE clause to partial match on string values.
+        if let Some(embedder) = embedder {
+            agent_builder = agent_builder.tool(
+                SurrealVectorSearchTool::new(surreal_db_config, embedder.clone())
+                    .with_ledger(ledger.clone()),
+            );
+        }
+    }
+    if let Some(mcp) = mcp {
+        agent_builder = mcp.register(agent_builder);
+    }
 
-            The tools connect to a SurrealDB instance. See SQL syntax here https://surrealdb.com/docs/surrealql/statements/select, https://surrealdb.com/docs/surrealql/clauses/where, https://surrealdb.com/docs/surrealql/datamodel/strings.
+    let answer = agent_builder
+        .build()
+        .prompt(question)
+        .multi_turn(max_turns)
+        .await?;
 
-            Mention the IDs of which rows were used to generate the response.
-            "#));
+    Ok(SubAgentResult::from_ledger(
+        &sub_agent.name,
+        question,
+        answer,
+        &ledger,
+    ))
+}
 
-    let agent2 = agent_builder
-        .tool(schema_tool.clone())
-        .tool(select_tool.clone())
-        .build();
+/// Sub-agent answering from a SurrealDB table, and from the tools of an MCP
+/// server when it has one
+pub(crate) struct SurrealSubAgent {
+    config: SubAgentConfig,
+    surreal_config: SurrealConfig,
+    mcp: Arc<McpConnections>,
+}
 
-    agent2
-        .prompt(question)
-        .multi_turn(10)
+impl SurrealSubAgent {
+    pub(crate) fn new(
+        config: SubAgentConfig,
+        surreal_config: SurrealConfig,
+        mcp: Arc<McpConnections>,
+    ) -> Self {
+        Self {
+            config,
+            surreal_config,
+            mcp,
+        }
+    }
+}
+
+impl<M: Model> SubAgent<M> for SurrealSubAgent {
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn description(&self) -> &str {
+        &self.config.description
+    }
+
+    fn answer<'a>(
+        &'a self,
+        question: &'a str,
+        context: SubAgentContext<'a, M>,
+    ) -> BoxFuture<'a, Result<SubAgentResult, AgentError>> {
+        Box::pin(async move {
+            let mcp = match &self.config.mcp {
+                Some(server) => Some(self.mcp.get(server).await?),
+                None => None,
+            };
+
+            self::question(
+                context.llm,
+                context.prompts,
+                QueryInput {
+                    sub_agent: &self.config,
+                    question,
+                    ledger: context.ledger,
+                    mcp: mcp.as_deref(),
+                    embedder: context.embedder,
+                },
+                &self.surreal_config,
+                context.max_turns,
+            )
+            .await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rig::completion::PromptError;
+
+    use super::*;
+    use crate::{
+        agents::{
+            RecordId,
+            mock::{ScriptedModel, TopicEmbedding},
+        },
+        surreal::execute_query,
+        testing::{customers_sub_agent, seeded_db},
+    };
+
+    async fn ask(model: &ScriptedModel, db: &str) -> Result<SubAgentResult, AgentError> {
+        question(
+            &Llm::new(model.clone(), "mock"),
+            &Prompts::embedded(),
+            QueryInput {
+                sub_agent: &customers_sub_agent(),
+                question: "Which customer pays the most?",
+                ledger: QueryLedger::new(),
+                mcp: None,
+                embedder: None,
+            },
+            &seeded_db(db).await,
+            MAX_TURNS,
+        )
+        .await
+    }
+
+    /// Everything the model was sent in its `turn`th request
+    fn sent(model: &ScriptedModel, turn: usize) -> String {
+        serde_json::to_string(&model.requests()[turn].chat_history).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_question_runs_scripted_query() {
+        let model = ScriptedModel::new()
+            .tool_call(
+                "surreal_select",
+                json!({ "query": "SELECT id, name, arr FROM customers ORDER BY arr DESC LIMIT 1" }),
+            )
+            .text("Acme Corp (customers:acme) pays the most.");
+
+        let result = ask(&model, "query-scripted").await.unwrap();
+
+        assert!(sent(&model, 1).contains("Acme Corp"));
+        assert_eq!(result.answer, "Acme Corp (customers:acme) pays the most.");
+        assert_eq!(result.rows_used, [RecordId::new("customers:acme")]);
+        assert_eq!(result.queries_run.len(), 1);
+        assert_eq!(result.rows[0]["arr"], 250000);
+        assert!(result.citations.unverified.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_question_shows_query_errors_to_model() {
+        let model = ScriptedModel::new()
+            .tool_call(
+                "surreal_select",
+                json!({ "query": "SELECT FROM customers WHERE" }),
+            )
+            .text("The customers could not be queried.");
+
+        let result = ask(&model, "query-error").await.unwrap();
+
+        assert!(sent(&model, 1).contains("Query execution error"));
+        assert_eq!(result.answer, "The customers could not be queried.");
+        assert!(result.rows_used.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_question_fails_on_invalid_tool_arguments() {
+        let model = ScriptedModel::new()
+            .tool_call(
+                "surreal_select",
+                json!({ "sql": "SELECT * FROM customers" }),
+            )
+            .text("Unreachable");
+
+        let result = ask(&model, "query-arguments").await;
+
+        assert!(matches!(result, Err(AgentError::Prompt(_))));
+        assert_eq!(model.remaining(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_question_respects_turn_limit() {
+        let mut model = ScriptedModel::new();
+        for _ in 0..MAX_TURNS * 2 {
+            model = model.tool_call(
+                "surreal_select",
+                json!({ "query": "SELECT name FROM customers" }),
+            );
+        }
+
+        let result = ask(&model, "query-turns").await;
+
+        assert!(matches!(
+            result,
+            Err(AgentError::Prompt(err)) if matches!(*err, PromptError::MaxDepthError { .. })
+        ));
+        // rig allows the first prompt and a final answer on top of the tool-calling turns
+        assert_eq!(model.requests().len(), MAX_TURNS + 2);
+    }
+
+    #[tokio::test]
+    async fn test_question_offers_vector_search_with_embedder() {
+        let model = ScriptedModel::new().text("Acme Corp (customers:acme) pays the most.");
+        let embedder = Embedder::new("topics", TopicEmbedding::new(&[&["revenue", "arr"]]));
+
+        question(
+            &Llm::new(model.clone(), "mock"),
+            &Prompts::embedded(),
+            QueryInput {
+                sub_agent: &customers_sub_agent(),
+                question: "Which customer pays the most?",
+                ledger: QueryLedger::new(),
+                mcp: None,
+                embedder: Some(&embedder),
+            },
+            &seeded_db("query-vector").await,
+            MAX_TURNS,
+        )
+        .await
+        .unwrap();
+
+        let request = &model.requests()[0];
+        let tools = request
+            .tools
+            .iter()
+            .map(|tool| tool.name.as_str())
+            .collect::<Vec<_>>();
+        assert!(tools.contains(&"surreal_vector_search"));
+        assert!(
+            request
+                .preamble
+                .as_ref()
+                .unwrap()
+                .contains("use surreal_vector_search")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_question_explains_clusters_of_table() {
+        let model = ScriptedModel::new()
+            .text("Acme Corp (customers:acme) pays the most.")
+            .text("Acme Corp (customers:acme) pays the most.");
+        ask(&model, "query-unclustered").await.unwrap();
+
+        let surreal_config = seeded_db("query-clusters").await;
+        execute_query(
+            &SurrealDbConfig::from(&surreal_config),
+            "CREATE cluster:customers_name_1 SET table = 'customers', field = 'name', label = 'Acme'",
+        )
         .await
-        .expect("Failed to prompt grok-3-mini")
+        .unwrap();
+        question(
+            &Llm::new(model.clone(), "mock"),
+            &Prompts::embedded(),
+            QueryInput {
+                sub_agent: &customers_sub_agent(),
+                question: "Which customer pays the most?",
+                ledger: QueryLedger::new(),
+                mcp: None,
+                embedder: None,
+            },
+            &surreal_config,
+            MAX_TURNS,
+        )
+        .await
+        .unwrap();
+
+        let preambles = model
+            .requests()
+            .iter()
+            .map(|request| request.preamble.clone().unwrap())
+            .collect::<Vec<_>>();
+        assert!(!preambles[0].contains("in_cluster"));
+        assert!(preambles[1].contains(
+            "FROM cluster WHERE table = 'customers' AND field = 'name' ORDER BY size DESC"
+        ));
+    }
 }
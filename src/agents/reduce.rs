@@ -1,23 +1,496 @@
-use rig::{client::CompletionClient, completion::Prompt, providers::xai::Client};
+use futures::{StreamExt, future::join_all};
+use rig::{
+    completion::{AssistantContent, Prompt},
+    streaming::StreamingPrompt,
+};
 
-pub async fn reduce(client: &Client, question: &str, data: Vec<String>) -> String {
-    let data_string = data.join("\n\n");
+use super::{
+    AgentError, SubAgentResult,
+    map::Turn,
+    model::{Llm, Model},
+};
+use crate::{
+    join::JoinedTable,
+    prompts::{self, Prompts},
+    tokens::Tokenizer,
+    usage::Stage,
+};
 
-    let agent1 = client
-            .agent("grok-3-mini")
-            .preamble(&format!(
-                r#"
-                You are a helpful assistant that can answer questions by based on data provided below.
-               Use only the provided data, but use your own knowledge to analyze and determine what it means and come up with conclusions that would be useful to a business decision maker.
+/// Maximum number of summarization rounds before the reduce gives up
+const MAX_SUMMARY_DEPTH: usize = 4;
 
-                {data_string}
+/// Summaries requested at once during a summarization round
+const SUMMARY_CONCURRENCY: usize = 4;
 
-                "#
-            ))
-            .build();
+/// Options controlling what the reduce agent sees of each sub-agent result
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ReduceOptions {
+    /// Include the raw rows returned by each sub-agent's queries
+    pub include_rows: bool,
+    /// Maximum estimated tokens per LLM call; larger inputs are summarized
+    /// in chunks and the summaries reduced recursively
+    pub token_budget: Option<usize>,
+    /// Token estimate for the provider serving the reduce model
+    pub tokenizer: Tokenizer,
+}
+
+/// What the reduce agent answers from
+#[derive(Clone, Copy, Debug)]
+pub struct ReduceInput<'a> {
+    pub question: &'a str,
+    pub results: &'a [SubAgentResult],
+    /// Rows of the joined sub-agents, when a join is configured
+    pub joined: Option<&'a JoinedTable>,
+    /// Earlier turns of the conversation the question follows up on
+    pub history: &'a [Turn],
+}
+
+/// Callback receiving the answer as it is streamed by the model
+pub type OnDelta<'a> = &'a (dyn Fn(&str) + Send + Sync);
+
+/// Answer the question from the sub-agent results
+///
+/// With `on_delta`, the answer is streamed and every piece of text passed to
+/// the callback as it arrives.
+#[tracing::instrument(name = "reduce", skip_all, fields(results = input.results.len()))]
+pub async fn reduce<M: Model>(
+    llm: &Llm<M>,
+    prompts: &Prompts,
+    input: ReduceInput<'_>,
+    options: ReduceOptions,
+    on_delta: Option<OnDelta<'_>>,
+) -> Result<String, AgentError> {
+    let question = input.question;
+    let mut sections = input
+        .results
+        .iter()
+        .map(|result| format_section(result, options))
+        .collect::<Vec<String>>();
+
+    if let Some(joined) = input.joined {
+        sections.push(format_joined_section(joined));
+    }
+
+    if let Some(budget) = options.token_budget {
+        sections = condense(
+            llm,
+            prompts,
+            question,
+            input.history,
+            sections,
+            budget,
+            options.tokenizer,
+        )
+        .await?;
+    }
+
+    let data_string = sections.join("\n\n");
+
+    let agent_builder = llm.agent(Stage::Reduce, None).preamble(&reduce_preamble(
+        prompts,
+        &data_string,
+        input.history,
+    )?);
+
+    let Some(on_delta) = on_delta else {
+        return Ok(agent_builder.build().prompt(question).await?);
+    };
+
+    // OpenAI-compatible providers only report the usage of a stream on request
+    let agent1 = agent_builder
+        .additional_params(serde_json::json!({ "stream_options": { "include_usage": true } }))
+        .build();
+
+    let mut stream = agent1.stream_prompt(question).await?;
+
+    let mut answer = String::new();
+    while let Some(chunk) = stream.next().await {
+        if let AssistantContent::Text(text) = chunk? {
+            on_delta(&text.text);
+            answer.push_str(&text.text);
+        }
+    }
+
+    Ok(answer)
+}
+
+fn reduce_preamble(
+    prompts: &Prompts,
+    data_string: &str,
+    history: &[Turn],
+) -> Result<String, AgentError> {
+    Ok(prompts.render(
+        prompts::REDUCE,
+        serde_json::json!({ "data": data_string, "history": history }),
+    )?)
+}
+
+fn summarize_preamble(
+    prompts: &Prompts,
+    question: &str,
+    data_string: &str,
+) -> Result<String, AgentError> {
+    Ok(prompts.render(
+        prompts::SUMMARIZE,
+        serde_json::json!({ "question": question, "data": data_string }),
+    )?)
+}
+
+/// Tree reduce: summarize the sections in budget-sized chunks, a few at a
+/// time, until they fit in a single reduce call
+///
+/// Fails if the budget cannot hold the prompts themselves, or if the
+/// summaries still do not fit after [`MAX_SUMMARY_DEPTH`] rounds.
+async fn condense<M: Model>(
+    llm: &Llm<M>,
+    prompts: &Prompts,
+    question: &str,
+    history: &[Turn],
+    mut sections: Vec<String>,
+    budget: usize,
+    tokenizer: Tokenizer,
+) -> Result<Vec<String>, AgentError> {
+    let reduce_overhead =
+        tokenizer.estimate(&reduce_preamble(prompts, "", history)?) + tokenizer.estimate(question);
+    let summarize_overhead = tokenizer.estimate(&summarize_preamble(prompts, question, "")?);
+    let overhead = reduce_overhead.max(summarize_overhead);
+    if budget <= overhead {
+        return Err(AgentError::BudgetTooSmall { budget, overhead });
+    }
+    let chunk_capacity = budget - summarize_overhead;
+
+    for _ in 0..MAX_SUMMARY_DEPTH {
+        let total = tokenizer.estimate(&sections.join("\n\n"));
+        if reduce_overhead + total <= budget {
+            return Ok(sections);
+        }
+
+        let chunks = chunk_sections(&sections, chunk_capacity, tokenizer);
+
+        let mut summaries = Vec::with_capacity(chunks.len());
+        for batch in chunks.chunks(SUMMARY_CONCURRENCY) {
+            for summary in join_all(
+                batch
+                    .iter()
+                    .map(|chunk| summarize(llm, prompts, question, chunk)),
+            )
+            .await
+            {
+                summaries.push(summary?);
+            }
+        }
+        sections = summaries;
+    }
+
+    let total = tokenizer.estimate(&sections.join("\n\n"));
+    if reduce_overhead + total <= budget {
+        return Ok(sections);
+    }
+
+    // Answering from the part that fits would silently ignore the rest
+    Err(AgentError::BudgetExceeded {
+        budget,
+        tokens: reduce_overhead + total,
+    })
+}
+
+#[tracing::instrument(name = "summarize", skip_all)]
+async fn summarize<M: Model>(
+    llm: &Llm<M>,
+    prompts: &Prompts,
+    question: &str,
+    chunk: &str,
+) -> Result<String, AgentError> {
+    let agent = llm
+        .agent(Stage::Summarize, None)
+        .preamble(&summarize_preamble(prompts, question, chunk)?)
+        .build();
+
+    Ok(agent.prompt("Condense the data above.").await?)
+}
+
+/// Group sections into chunks of at most `capacity` estimated tokens,
+/// splitting sections that do not fit on their own
+fn chunk_sections(sections: &[String], capacity: usize, tokenizer: Tokenizer) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+
+    for piece in sections
+        .iter()
+        .flat_map(|section| split_to_fit(section, capacity, tokenizer))
+    {
+        let candidate = if current.is_empty() {
+            piece.clone()
+        } else {
+            format!("{current}\n\n{piece}")
+        };
+
+        if tokenizer.estimate(&candidate) <= capacity || current.is_empty() {
+            current = candidate;
+        } else {
+            chunks.push(std::mem::replace(&mut current, piece));
+        }
+    }
+
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+
+    chunks
+}
+
+/// Split a section by lines (and overlong lines by characters) into pieces
+/// of at most `capacity` estimated tokens
+fn split_to_fit(section: &str, capacity: usize, tokenizer: Tokenizer) -> Vec<String> {
+    if tokenizer.estimate(section) <= capacity {
+        return vec![section.to_string()];
+    }
+
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+
+    for line in section.lines() {
+        let line_pieces = if tokenizer.estimate(line) <= capacity {
+            vec![line.to_string()]
+        } else {
+            let chars: Vec<char> = line.chars().collect();
+            let step = (chars.len() * capacity / tokenizer.estimate(line)).max(1);
+            chars
+                .chunks(step)
+                .map(|chunk| chunk.iter().collect())
+                .collect()
+        };
+
+        for line_piece in line_pieces {
+            let candidate = if current.is_empty() {
+                line_piece.clone()
+            } else {
+                format!("{current}\n{line_piece}")
+            };
 
-    agent1
-        .prompt(question)
+            if tokenizer.estimate(&candidate) <= capacity || current.is_empty() {
+                current = candidate;
+            } else {
+                pieces.push(std::mem::replace(&mut current, line_piece));
+            }
+        }
+    }
+
+    if !current.is_empty() {
+        pieces.push(current);
+    }
+
+    pieces
+}
+
+/// Format the joined rows of two sub-agents as a section of the reduce preamble
+fn format_joined_section(joined: &JoinedTable) -> String {
+    let mut section = String::from(
+        "## Joined table\nRows of the sub-agents above matched on their join keys. Use this table, not the sub-agent answers, to relate records across sub-agents.\n",
+    );
+
+    if joined.unmatched > 0 {
+        section.push_str(&format!(
+            "{} row(s) had no match and are not listed.\n",
+            joined.unmatched
+        ));
+    }
+
+    section.push_str(&joined.to_markdown());
+    section
+}
+
+/// Format a sub-agent result as a labeled section of the reduce preamble
+fn format_section(result: &SubAgentResult, options: ReduceOptions) -> String {
+    let mut section = format!(
+        "## Sub-agent: {}\nQuestion: {}\n",
+        result.agent, result.question
+    );
+
+    if !result.queries_run.is_empty() {
+        section.push_str("Queries run:\n");
+        for query in &result.queries_run {
+            section.push_str(&format!("- {query}\n"));
+        }
+    }
+
+    if !result.rows_used.is_empty() {
+        let ids = result
+            .rows_used
+            .iter()
+            .map(|id| id.as_str())
+            .collect::<Vec<&str>>()
+            .join(", ");
+        section.push_str(&format!("Rows used: {ids}\n"));
+    }
+
+    section.push_str(&format!("Answer:\n{}\n", result.answer.trim()));
+
+    if options.include_rows && !result.rows.is_empty() {
+        let rows = serde_json::to_string_pretty(&result.rows).unwrap_or_default();
+        section.push_str(&format!("Raw rows:\n{rows}\n"));
+    }
+
+    section
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::{RecordId, mock::ScriptedModel};
+
+    async fn reduce_within(model: &ScriptedModel, budget: usize) -> Result<String, AgentError> {
+        reduce(
+            &Llm::new(model.clone(), "mock"),
+            &Prompts::embedded(),
+            ReduceInput {
+                question: "Who pays the most?",
+                results: &[result()],
+                joined: None,
+                history: &[],
+            },
+            ReduceOptions {
+                token_budget: Some(budget),
+                tokenizer: Tokenizer::new(1.0),
+                ..Default::default()
+            },
+            None,
+        )
         .await
-        .expect("Failed to prompt grok-3-mini")
+    }
+
+    fn result() -> SubAgentResult {
+        SubAgentResult {
+            agent: "customers".to_string(),
+            question: "List customers by ARR".to_string(),
+            answer: "Acme Corp has the highest ARR (customers:abc).".to_string(),
+            rows_used: vec![RecordId::new("customers:abc")],
+            queries_run: vec!["SELECT * FROM customers ORDER BY arr DESC".to_string()],
+            rows: vec![serde_json::json!({ "id": "customers:abc", "arr": 150000 })],
+            citations: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_format_section() {
+        let section = format_section(&result(), ReduceOptions::default());
+
+        assert!(section.starts_with("## Sub-agent: customers\nQuestion: List customers by ARR\n"));
+        assert!(section.contains("- SELECT * FROM customers ORDER BY arr DESC\n"));
+        assert!(section.contains("Rows used: customers:abc\n"));
+        assert!(section.contains("Answer:\nAcme Corp has the highest ARR (customers:abc).\n"));
+        assert!(!section.contains("Raw rows"));
+    }
+
+    #[test]
+    fn test_format_section_with_rows() {
+        let options = ReduceOptions {
+            include_rows: true,
+            ..Default::default()
+        };
+        let section = format_section(&result(), options);
+
+        assert!(section.contains("Raw rows:\n"));
+        assert!(section.contains("\"arr\": 150000"));
+    }
+
+    #[test]
+    fn test_format_joined_section() {
+        let joined = JoinedTable {
+            columns: vec!["customers.id".to_string()],
+            rows: vec![
+                [(
+                    "customers.id".to_string(),
+                    serde_json::json!("customers:abc"),
+                )]
+                .into(),
+            ],
+            unmatched: 2,
+        };
+
+        let section = format_joined_section(&joined);
+
+        assert!(section.starts_with("## Joined table\n"));
+        assert!(section.contains("2 row(s) had no match"));
+        assert!(section.ends_with("| customers:abc |\n"));
+    }
+
+    #[test]
+    fn test_chunk_sections_within_capacity() {
+        let tokenizer = Tokenizer::new(1.0);
+        let sections = vec!["a".repeat(4), "b".repeat(4), "c".repeat(4)];
+
+        let chunks = chunk_sections(&sections, 10, tokenizer);
+
+        assert_eq!(
+            chunks,
+            vec![
+                format!("{}\n\n{}", "a".repeat(4), "b".repeat(4)),
+                "c".repeat(4)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_chunk_sections_splits_large_section() {
+        let tokenizer = Tokenizer::new(1.0);
+        let section = (0..10)
+            .map(|i| format!("line {i}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let chunks = chunk_sections(std::slice::from_ref(&section), 20, tokenizer);
+
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|chunk| tokenizer.estimate(chunk) <= 20));
+        assert_eq!(chunks.join("\n"), section);
+    }
+
+    #[tokio::test]
+    async fn test_reduce_rejects_budget_below_prompts() {
+        let model = ScriptedModel::new();
+
+        let result = reduce_within(&model, 10).await;
+
+        assert!(matches!(
+            result,
+            Err(AgentError::BudgetTooSmall { budget: 10, .. })
+        ));
+        assert!(model.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reduce_fails_when_summaries_do_not_fit() {
+        let tokenizer = Tokenizer::new(1.0);
+        let prompts = Prompts::embedded();
+        let question = "Who pays the most?";
+        let summarize_overhead =
+            tokenizer.estimate(&summarize_preamble(&prompts, question, "").unwrap());
+        let reduce_overhead = tokenizer.estimate(&reduce_preamble(&prompts, "", &[]).unwrap())
+            + tokenizer.estimate(question);
+        let budget = summarize_overhead.max(reduce_overhead) + 50;
+        // Every summary fills its chunk, so the data never shrinks
+        let summary = "x".repeat(budget - summarize_overhead);
+        let mut model = ScriptedModel::new();
+        for _ in 0..100 {
+            model = model.text(summary.clone());
+        }
+
+        let result = reduce_within(&model, budget).await;
+
+        assert!(
+            matches!(result, Err(AgentError::BudgetExceeded { .. })),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn test_split_to_fit_long_line() {
+        let tokenizer = Tokenizer::new(1.0);
+
+        let pieces = split_to_fit(&"x".repeat(25), 10, tokenizer);
+
+        assert_eq!(pieces.len(), 3);
+        assert!(pieces.iter().all(|piece| piece.len() <= 10));
+    }
 }
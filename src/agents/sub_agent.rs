@@ -0,0 +1,114 @@
+//! Sub-agents the map agent routes sub-questions to
+//!
+//! The pipeline only sees a sub-agent through the [`SubAgent`] trait: its
+//! name and description for the map agent, and an answer to a sub-question
+//! for the reduce agent. The SurrealDB sub-agents registered with
+//! [`Pipeline::sub_agent`](crate::pipeline::Pipeline::sub_agent) are one
+//! implementation; other data sources plug in with
+//! [`Pipeline::custom_sub_agent`](crate::pipeline::Pipeline::custom_sub_agent).
+
+use std::path::PathBuf;
+
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    AgentError, SubAgentResult,
+    model::{Llm, Model},
+};
+use crate::{
+    config::ConfigError,
+    mcp::McpServerConfig,
+    prompts::Prompts,
+    surreal::{Embedder, QueryLedger},
+};
+
+/// A sub-agent answering questions from a single table, a local data file,
+/// or the tools of an MCP server
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SubAgentConfig {
+    /// Name the map agent uses to address the sub-agent
+    pub name: String,
+    /// Description shown to the map agent
+    pub description: String,
+    /// Table the sub-agent queries; empty for a sub-agent answering from its
+    /// MCP tools alone
+    #[serde(default)]
+    pub table: String,
+    /// Context about the table, or the service, shown to the sub-agent
+    pub table_context: String,
+    /// MCP server whose tools the sub-agent may call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mcp: Option<McpServerConfig>,
+    /// CSV, JSONL, JSON or Parquet file the sub-agent answers from instead of
+    /// the database of the pipeline; `table` defaults to the file name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+}
+
+impl SubAgentConfig {
+    /// Check that the sub-agent has something to answer from: a table, an
+    /// MCP server or a file
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.table.trim().is_empty() && self.mcp.is_none() && self.file.is_none() {
+            return Err(ConfigError::InvalidValue(
+                "sub-agent needs a table, an MCP server or a file",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// What a sub-agent may use from the run it answers in
+pub struct SubAgentContext<'a, M> {
+    /// Model of the run, metering the usage of the sub-agent
+    pub llm: &'a Llm<M>,
+    pub prompts: &'a Prompts,
+    /// Ledger of the sub-agent; the rows recorded in it are stored with the
+    /// run, streamed as events and count as seen when citations are verified
+    pub ledger: QueryLedger,
+    /// Maximum tool-calling turns of the sub-agent
+    pub max_turns: usize,
+    /// Embedding model of the pipeline, when it has one
+    pub embedder: Option<&'a Embedder>,
+}
+
+/// A data source the pipeline can ask sub-questions
+pub trait SubAgent<M: Model>: Send + Sync {
+    /// Name the map agent uses to address the sub-agent
+    fn name(&self) -> &str;
+
+    /// Description shown to the map agent
+    fn description(&self) -> &str;
+
+    /// Answer a sub-question
+    fn answer<'a>(
+        &'a self,
+        question: &'a str,
+        context: SubAgentContext<'a, M>,
+    ) -> BoxFuture<'a, Result<SubAgentResult, AgentError>>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_needs_a_source() {
+        let mut config = SubAgentConfig {
+            name: "crm".to_string(),
+            description: "Finds accounts in the CRM.".to_string(),
+            table: String::new(),
+            table_context: String::new(),
+            mcp: None,
+            file: None,
+        };
+        assert!(config.validate().is_err());
+
+        config.mcp = Some(McpServerConfig::Sse {
+            url: "http://localhost:3001/sse".to_string(),
+        });
+        assert!(config.validate().is_ok());
+    }
+}
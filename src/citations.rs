@@ -0,0 +1,220 @@
+//! Verification of the record IDs cited in agent answers
+//!
+//! Agents are asked to mention the IDs of the rows they used. This module
+//! extracts those IDs from free text, checks them against the IDs actually
+//! returned by the select tool, and maps each claim in an answer to the
+//! records it cites.
+
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+/// A claim in an answer together with the record IDs it cites
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Provenance {
+    pub claim: String,
+    pub record_ids: Vec<String>,
+}
+
+/// Result of checking the citations of a single answer
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CitationReport {
+    /// Every record ID cited in the answer, in order of first appearance
+    pub cited: Vec<String>,
+    /// Cited record IDs that were never returned by a query
+    pub unverified: Vec<String>,
+    /// Claims in the answer mapped to the record IDs they cite
+    pub provenance: Vec<Provenance>,
+}
+
+impl CitationReport {
+    /// Check the citations in `answer` against the record IDs seen in tool output
+    pub fn verify(answer: &str, seen: &BTreeSet<String>) -> Self {
+        let cited = extract_record_ids(answer);
+        let unverified = cited
+            .iter()
+            .filter(|id| !seen.contains(*id))
+            .cloned()
+            .collect();
+
+        let provenance = split_claims(answer)
+            .into_iter()
+            .filter_map(|claim| {
+                let record_ids = extract_record_ids(&claim);
+                (!record_ids.is_empty()).then_some(Provenance { claim, record_ids })
+            })
+            .collect();
+
+        Self {
+            cited,
+            unverified,
+            provenance,
+        }
+    }
+
+    /// Whether every cited record ID was seen in tool output
+    pub fn is_verified(&self) -> bool {
+        self.unverified.is_empty()
+    }
+}
+
+/// Extract SurrealDB record IDs (`table:id`) from free text
+///
+/// Recognizes plain IDs (`customers:n85php1nd6yiq7xhjwzi`) as well as IDs
+/// with angle-bracket or backtick escaped keys (`customers:⟨acme-corp⟩`).
+/// Duplicates are removed, keeping the order of first appearance.
+pub fn extract_record_ids(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut ids: Vec<String> = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        // A table name must start a word
+        let at_word_start = i == 0 || !is_ident_char(chars[i - 1]);
+        if !at_word_start || !(chars[i].is_ascii_alphabetic() || chars[i] == '_') {
+            i += 1;
+            continue;
+        }
+
+        let table_start = i;
+        while i < chars.len() && is_ident_char(chars[i]) {
+            i += 1;
+        }
+        let table_end = i;
+
+        if i + 1 >= chars.len() || chars[i] != ':' {
+            continue;
+        }
+
+        let key_start = i + 1;
+        let key_end = match chars[key_start] {
+            '⟨' => find_closing(&chars, key_start, '⟩'),
+            '`' => find_closing(&chars, key_start, '`'),
+            c if is_ident_char(c) => {
+                let mut end = key_start;
+                while end < chars.len() && is_ident_char(chars[end]) {
+                    end += 1;
+                }
+                Some(end)
+            }
+            _ => None,
+        };
+
+        match key_end {
+            Some(key_end) => {
+                let table: String = chars[table_start..table_end].iter().collect();
+                let key: String = chars[key_start..key_end].iter().collect();
+                let id = format!("{table}:{key}");
+                if !ids.contains(&id) {
+                    ids.push(id);
+                }
+                i = key_end;
+            }
+            None => i = key_start,
+        }
+    }
+
+    ids
+}
+
+/// Split an answer into claims: paragraphs, list items (with their
+/// continuation lines) and headings
+fn split_claims(text: &str) -> Vec<String> {
+    let mut claims = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+
+    for line in text.lines() {
+        let trimmed = line.trim();
+        let is_indented = line.starts_with(' ') || line.starts_with('\t');
+        let starts_claim = trimmed.is_empty()
+            || trimmed.starts_with('#')
+            || (!is_indented && is_list_item(trimmed));
+
+        if starts_claim && !current.is_empty() {
+            claims.push(current.join("\n"));
+            current.clear();
+        }
+        if !trimmed.is_empty() {
+            current.push(trimmed);
+        }
+    }
+
+    if !current.is_empty() {
+        claims.push(current.join("\n"));
+    }
+
+    claims
+}
+
+fn is_list_item(line: &str) -> bool {
+    if line.starts_with("- ") || line.starts_with("* ") || line.starts_with("+ ") {
+        return true;
+    }
+
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    digits > 0 && line[digits..].starts_with(". ")
+}
+
+fn find_closing(chars: &[char], open: usize, close: char) -> Option<usize> {
+    chars[open + 1..]
+        .iter()
+        .position(|&c| c == close)
+        .map(|pos| open + 1 + pos + 1)
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_record_ids() {
+        let text = "The IDs are: customers:n85php1nd6yiq7xhjwzi, feature_requests:3pqtglyqw2hz0fxyvdx6 \
+                    and customers:⟨acme-corp⟩. ARR: 150000 USD, see https://surrealdb.com at 10:30.";
+
+        assert_eq!(
+            extract_record_ids(text),
+            vec![
+                "customers:n85php1nd6yiq7xhjwzi",
+                "feature_requests:3pqtglyqw2hz0fxyvdx6",
+                "customers:⟨acme-corp⟩",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_record_ids_deduplicates() {
+        let text = "customers:abc has the highest ARR (customers:abc)";
+        assert_eq!(extract_record_ids(text), vec!["customers:abc"]);
+    }
+
+    #[test]
+    fn test_verify_flags_unseen_ids() {
+        let seen: BTreeSet<String> = ["customers:abc".to_string()].into();
+        let answer = "1. **ID**: customers:abc\n   **ARR**: 150000 USD\n2. **ID**: customers:fake\n   **ARR**: 90000 USD";
+
+        let report = CitationReport::verify(answer, &seen);
+
+        assert_eq!(report.cited, vec!["customers:abc", "customers:fake"]);
+        assert_eq!(report.unverified, vec!["customers:fake"]);
+        assert!(!report.is_verified());
+        assert_eq!(report.provenance.len(), 2);
+        assert_eq!(
+            report.provenance[0].claim,
+            "1. **ID**: customers:abc\n**ARR**: 150000 USD"
+        );
+        assert_eq!(report.provenance[1].record_ids, vec!["customers:fake"]);
+    }
+
+    #[test]
+    fn test_verify_without_citations() {
+        let report = CitationReport::verify("No rows matched the question.", &BTreeSet::new());
+
+        assert!(report.cited.is_empty());
+        assert!(report.provenance.is_empty());
+        assert!(report.is_verified());
+    }
+}
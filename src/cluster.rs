@@ -0,0 +1,588 @@
+//! Clusters of near-duplicate rows, grouped by the embeddings of a text field
+//!
+//! [`cluster_field`] embeds the changed rows of a field, groups the rows
+//! whose embeddings are at least [`ClusterOptions::threshold`] similar to
+//! the centroid of a group, and has the label agent name every group. The
+//! groups are written back as records of the `cluster` table, each row
+//! linked to its cluster by an `in_cluster` edge, so queries count
+//! "2FA" and "two-factor authentication" together:
+//!
+//! ```sql
+//! SELECT id, label, count(<-in_cluster) AS size, <-in_cluster<-feature_requests.{ id } AS rows
+//!     FROM cluster WHERE table = 'feature_requests' AND field = 'message' ORDER BY size DESC
+//! ```
+//!
+//! Rows are grouped in a single pass in the order of their IDs, each
+//! joining the most similar group or starting a new one, so running again
+//! on unchanged rows gives the same clusters.
+
+use std::error::Error as StdError;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::{
+    agents::{AgentError, RecordId, label::label_clusters, model::Model},
+    pipeline::Pipeline,
+    prompts::fnv1a,
+    surreal::{
+        SearchField, SurrealDbConfig, SurrealError, embed_field, escape, execute_all,
+        execute_bound, execute_query, vector::embedding_field,
+    },
+    usage::UsageMeter,
+};
+
+/// Cosine similarity to the centroid of a cluster a row needs to join it
+pub const DEFAULT_THRESHOLD: f64 = 0.85;
+
+/// Texts of each cluster shown to the label agent
+pub const DEFAULT_SAMPLES: usize = 5;
+
+/// Clusters labelled per request to the label agent
+const LABEL_BATCH: usize = 20;
+
+/// Characters of a text shown to the label agent
+const SAMPLE_CHARS: usize = 300;
+
+/// Characters of the text of its most typical row a cluster is labelled
+/// with when the label agent gave it none
+const FALLBACK_LABEL_CHARS: usize = 60;
+
+/// Error clustering the rows of a field
+#[derive(Debug)]
+pub enum ClusterError {
+    /// The pipeline has no embedding model
+    NoEmbedder,
+    Surreal(SurrealError),
+    Agent(AgentError),
+}
+
+impl fmt::Display for ClusterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClusterError::NoEmbedder => write!(f, "Clustering needs an embedding model"),
+            ClusterError::Surreal(err) => write!(f, "{err}"),
+            ClusterError::Agent(err) => write!(f, "Failed to label clusters: {err}"),
+        }
+    }
+}
+
+impl StdError for ClusterError {}
+
+impl From<SurrealError> for ClusterError {
+    fn from(err: SurrealError) -> Self {
+        ClusterError::Surreal(err)
+    }
+}
+
+impl From<AgentError> for ClusterError {
+    fn from(err: AgentError) -> Self {
+        ClusterError::Agent(err)
+    }
+}
+
+/// How the rows of a field are clustered
+#[derive(Clone, Debug)]
+pub struct ClusterOptions {
+    field: SearchField,
+    threshold: f64,
+    samples: usize,
+}
+
+impl ClusterOptions {
+    /// Cluster the rows of `field` with the default threshold
+    pub fn new(field: SearchField) -> Self {
+        Self {
+            field,
+            threshold: DEFAULT_THRESHOLD,
+            samples: DEFAULT_SAMPLES,
+        }
+    }
+
+    /// Cosine similarity a row needs to join a cluster, between 0 and 1;
+    /// higher values give more, tighter clusters
+    pub fn threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Number of texts of each cluster the label agent reads
+    pub fn samples(mut self, samples: usize) -> Self {
+        self.samples = samples.max(1);
+        self
+    }
+}
+
+/// A cluster written by [`cluster_field`]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClusterSummary {
+    pub id: RecordId,
+    pub label: String,
+    pub size: usize,
+    /// Row closest to the centroid of the cluster
+    pub representative: RecordId,
+}
+
+/// What [`cluster_field`] did
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClusterReport {
+    pub field: SearchField,
+    /// Rows with a text in the field
+    pub rows: usize,
+    /// Clusters from the largest to the smallest
+    pub clusters: Vec<ClusterSummary>,
+    /// Cost of labelling the clusters
+    pub cost_usd: f64,
+}
+
+impl fmt::Display for ClusterReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Clustered {} row(s) of {} into {} cluster(s), {:.4} USD",
+            self.rows,
+            self.field,
+            self.clusters.len(),
+            self.cost_usd
+        )?;
+
+        for cluster in &self.clusters {
+            writeln!(
+                f,
+                "  {:>5}  {} ({})",
+                cluster.size, cluster.label, cluster.id
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Rows of a cluster with their similarity to its centroid, most similar first
+#[derive(Debug, PartialEq)]
+struct Group {
+    members: Vec<(usize, f64)>,
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+/// `vector` scaled to a length of 1, or unchanged if it has none
+fn unit(vector: &[f64]) -> Vec<f64> {
+    let norm = dot(vector, vector).sqrt();
+    if norm == 0.0 {
+        return vector.to_vec();
+    }
+
+    vector.iter().map(|x| x / norm).collect()
+}
+
+/// ID of the cluster of `field` whose representative row is
+/// `representative`, so clustering again keeps the IDs of unchanged clusters,
+/// e.g. `cluster:feature_requests_message_sso_<hash>` for
+/// `feature_requests:sso`. The readable part is lossy, `sso-login` and
+/// `sso_login` giving the same, so it ends with a hash of the table, field
+/// and representative
+fn cluster_id(field: &SearchField, representative: &RecordId) -> RecordId {
+    let key: String = representative
+        .as_str()
+        .split_once(':')
+        .map_or("", |(_, key)| key)
+        .chars()
+        .filter(|c| !matches!(c, '⟨' | '⟩' | '`'))
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    let hash = fnv1a(
+        json!([field.table, field.field, representative])
+            .to_string()
+            .as_bytes(),
+    );
+
+    RecordId::new(format!(
+        "cluster:{}_{}_{key}_{hash:016x}",
+        field.table, field.field
+    ))
+}
+
+/// Group the indexes of `vectors`, each joining the group whose centroid is
+/// most similar to it if it is at least `threshold` similar, from the
+/// largest group to the smallest
+fn group(vectors: &[Vec<f64>], threshold: f64) -> Vec<Group> {
+    let units = vectors
+        .iter()
+        .map(|vector| unit(vector))
+        .collect::<Vec<_>>();
+    let mut sums: Vec<Vec<f64>> = Vec::new();
+    let mut members: Vec<Vec<usize>> = Vec::new();
+
+    for (index, vector) in units.iter().enumerate() {
+        let nearest = sums
+            .iter()
+            .map(|sum| dot(vector, &unit(sum)))
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b));
+
+        match nearest {
+            Some((group, similarity)) if similarity >= threshold => {
+                sums[group]
+                    .iter_mut()
+                    .zip(vector)
+                    .for_each(|(sum, x)| *sum += x);
+                members[group].push(index);
+            }
+            _ => {
+                sums.push(vector.clone());
+                members.push(vec![index]);
+            }
+        }
+    }
+
+    let mut groups = sums
+        .iter()
+        .zip(members)
+        .map(|(sum, members)| {
+            let centroid = unit(sum);
+            let mut members = members
+                .into_iter()
+                .map(|index| (index, dot(&units[index], &centroid)))
+                .collect::<Vec<_>>();
+            members.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+            Group { members }
+        })
+        .collect::<Vec<_>>();
+    // Stable, so groups of the same size stay in the order they started
+    groups.sort_by_key(|group| std::cmp::Reverse(group.members.len()));
+
+    groups
+}
+
+/// The first `chars` characters of `text`
+fn truncate(text: &str, chars: usize) -> String {
+    match text.char_indices().nth(chars) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// Embed the rows of the field of `options` with the embedding model of
+/// `pipeline`, group them, label the groups with its model and replace the
+/// clusters stored for the field, in one transaction so a failure keeps the
+/// previous clusters
+#[tracing::instrument(name = "cluster", skip_all, fields(field = %options.field))]
+pub async fn cluster_field<M: Model>(
+    pipeline: &Pipeline<M>,
+    options: &ClusterOptions,
+) -> Result<ClusterReport, ClusterError> {
+    let embedder = pipeline.embedder().ok_or(ClusterError::NoEmbedder)?;
+    let config = SurrealDbConfig::from(pipeline.surreal_config());
+    let field = &options.field;
+
+    embed_field(&config, embedder, field).await?;
+
+    let query = format!(
+        "SELECT id, {text} AS text, {embedding} AS embedding FROM {table} WHERE type::is::string({text}) AND type::is::array({embedding}) ORDER BY id",
+        text = escape(&field.field),
+        embedding = escape(&embedding_field(&field.field)),
+        table = escape(&field.table),
+    );
+    let rows = execute_query(&config, &query).await?;
+    let rows = rows.as_array().map(Vec::as_slice).unwrap_or_default();
+
+    let vectors = rows
+        .iter()
+        .map(|row| serde_json::from_value(row["embedding"].clone()).unwrap_or_default())
+        .collect::<Vec<Vec<f64>>>();
+    let groups = group(&vectors, options.threshold);
+    let text = |index: usize| rows[index]["text"].as_str().unwrap_or_default();
+
+    let llm = pipeline.llm().clone().with_meter(UsageMeter::new());
+    let mut labels = Vec::with_capacity(groups.len());
+    for batch in groups.chunks(LABEL_BATCH) {
+        let samples = batch
+            .iter()
+            .map(|group| {
+                group
+                    .members
+                    .iter()
+                    .take(options.samples)
+                    .map(|(index, _)| truncate(text(*index), SAMPLE_CHARS))
+                    .collect()
+            })
+            .collect::<Vec<_>>();
+        labels.extend(
+            label_clusters(
+                &llm,
+                pipeline.templates(),
+                &field.table,
+                &field.field,
+                &samples,
+            )
+            .await?,
+        );
+    }
+
+    let id = |index: usize| RecordId::new(rows[index]["id"].as_str().unwrap_or_default());
+    let summaries = groups
+        .iter()
+        .zip(labels)
+        .map(|(group, label)| {
+            let representative = group.members[0].0;
+            let label = if label.is_empty() {
+                truncate(text(representative), FALLBACK_LABEL_CHARS)
+            } else {
+                label
+            };
+
+            ClusterSummary {
+                id: cluster_id(field, &id(representative)),
+                label,
+                size: group.members.len(),
+                representative: id(representative),
+            }
+        })
+        .collect::<Vec<_>>();
+    let clusters = summaries
+        .iter()
+        .zip(&groups)
+        .map(|(summary, group)| {
+            json!({
+                "id": summary.id,
+                "label": summary.label,
+                "size": summary.size,
+                "representative": summary.representative,
+                "members": group
+                    .members
+                    .iter()
+                    .map(|(index, similarity)| json!({ "id": id(*index), "similarity": similarity }))
+                    .collect::<Vec<_>>(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    execute_all(
+        &config,
+        "DEFINE TABLE IF NOT EXISTS cluster SCHEMALESS;
+        DEFINE TABLE IF NOT EXISTS in_cluster TYPE RELATION;
+        BEGIN TRANSACTION;
+        DELETE in_cluster WHERE out.table = $table AND out.field = $field;
+        DELETE cluster WHERE table = $table AND field = $field;
+        FOR $cluster IN $clusters {
+            LET $node = type::record($cluster.id);
+            CREATE $node CONTENT {
+                table: $table,
+                field: $field,
+                label: $cluster.label,
+                size: $cluster.size,
+                representative: type::record($cluster.representative),
+                threshold: $threshold,
+            };
+            FOR $member IN $cluster.members {
+                LET $row = type::record($member.id);
+                RELATE $row->in_cluster->$node SET similarity = $member.similarity;
+            };
+        };
+        COMMIT TRANSACTION;",
+        json!({
+            "table": field.table,
+            "field": field.field,
+            "threshold": options.threshold,
+            "clusters": clusters,
+        }),
+    )
+    .await?;
+
+    let report = ClusterReport {
+        field: field.clone(),
+        rows: rows.len(),
+        clusters: summaries,
+        cost_usd: llm.meter().report(pipeline.price_table()).total.cost_usd,
+    };
+    tracing::info!(
+        rows = report.rows,
+        clusters = report.clusters.len(),
+        "clusters stored"
+    );
+
+    Ok(report)
+}
+
+/// Fields of `table` with stored clusters
+pub async fn clustered_fields(
+    config: &SurrealDbConfig,
+    table: &str,
+) -> Result<Vec<String>, SurrealError> {
+    let fields = execute_bound(
+        config,
+        "SELECT VALUE field FROM cluster WHERE table = $table",
+        json!({ "table": table }),
+    )
+    .await?;
+
+    let mut fields = fields
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(Value::as_str)
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    fields.sort();
+    fields.dedup();
+
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        agents::{
+            mock::{ScriptedModel, TopicEmbedding},
+            model::Llm,
+        },
+        testing::seeded_db,
+    };
+
+    #[test]
+    fn test_group_by_similarity_to_centroid() {
+        let vectors = vec![
+            vec![1.0, 0.0],
+            vec![0.0, 1.0],
+            vec![0.9, 0.1],
+            vec![0.95, 0.05],
+        ];
+
+        let groups = group(&vectors, 0.9);
+
+        let members = groups
+            .iter()
+            .map(|group| group.members.iter().map(|(index, _)| *index).collect())
+            .collect::<Vec<Vec<usize>>>();
+        assert_eq!(members, [vec![3, 0, 2], vec![1]]);
+        assert!(
+            groups[0]
+                .members
+                .iter()
+                .all(|(_, similarity)| *similarity > 0.9)
+        );
+    }
+
+    #[test]
+    fn test_cluster_id_follows_representative() {
+        let field: SearchField = "feature_requests.message".parse().unwrap();
+
+        let id = cluster_id(&field, &RecordId::new("feature_requests:⟨sso-login⟩"));
+        assert!(
+            id.as_str()
+                .starts_with("cluster:feature_requests_message_sso_login_")
+        );
+        assert_eq!(
+            id,
+            cluster_id(&field, &RecordId::new("feature_requests:⟨sso-login⟩"))
+        );
+    }
+
+    #[test]
+    fn test_cluster_id_keeps_distinct_keys_apart() {
+        let field: SearchField = "feature_requests.message".parse().unwrap();
+        assert_ne!(
+            cluster_id(&field, &RecordId::new("feature_requests:⟨sso-login⟩")),
+            cluster_id(&field, &RecordId::new("feature_requests:sso_login"))
+        );
+
+        let representative = RecordId::new("a_b:x");
+        assert_ne!(
+            cluster_id(&"a_b.c".parse().unwrap(), &representative),
+            cluster_id(&"a.b_c".parse().unwrap(), &representative)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cluster_field_writes_labelled_clusters() {
+        let surreal_config = seeded_db("cluster-field").await;
+        let config = SurrealDbConfig::from(&surreal_config);
+        execute_query(
+            &config,
+            "CREATE feature_requests:two_factor SET customer_identifier = 'Globex', message = 'Please add 2FA to the login page.'",
+        )
+        .await
+        .unwrap();
+
+        let model = ScriptedModel::new()
+            .text(r#"{"1": "Login security", "2": "Dark mode", "3": "CSV export"}"#)
+            .text(r#"{"1": "Login security"}"#);
+        let pipeline = Pipeline::with_llm(Llm::new(model, "mock"), surreal_config).embeddings(
+            "topics",
+            TopicEmbedding::new(&[
+                &["sso", "2fa", "login"],
+                &["export", "csv", "report"],
+                &["dark", "theme"],
+            ]),
+        );
+        let options = ClusterOptions::new("feature_requests.message".parse().unwrap());
+
+        let report = cluster_field(&pipeline, &options).await.unwrap();
+
+        assert_eq!(report.rows, 4);
+        assert_eq!(report.clusters.len(), 3);
+        assert_eq!(report.clusters[0].label, "Login security");
+        assert_eq!(report.clusters[0].size, 2);
+        let sso = cluster_id(&options.field, &RecordId::new("feature_requests:sso"));
+        assert_eq!(report.clusters[0].id, sso);
+        assert_eq!(
+            report.to_string().lines().nth(1).unwrap(),
+            format!("      2  Login security ({sso})")
+        );
+
+        let counts = execute_query(
+            &config,
+            "SELECT id, label, count(<-in_cluster) AS size, <-in_cluster<-feature_requests.{ id, customer_identifier } AS rows FROM cluster WHERE table = 'feature_requests' AND field = 'message' ORDER BY size DESC",
+        )
+        .await
+        .unwrap();
+        assert_eq!(counts[0]["label"], "Login security");
+        assert_eq!(counts[0]["size"], 2);
+        assert!(counts[0]["rows"].as_array().unwrap().contains(
+            &json!({ "id": "feature_requests:two_factor", "customer_identifier": "Globex" })
+        ));
+        assert_eq!(
+            clustered_fields(&config, "feature_requests").await.unwrap(),
+            ["message"]
+        );
+
+        // Clustering again replaces the clusters and their edges
+        execute_query(
+            &config,
+            "DELETE feature_requests:export, feature_requests:dark_mode",
+        )
+        .await
+        .unwrap();
+        let report = cluster_field(&pipeline, &options).await.unwrap();
+        assert_eq!(report.clusters.len(), 1);
+        assert_eq!(report.clusters[0].id, sso);
+        let edges = execute_query(&config, "SELECT VALUE in FROM in_cluster ORDER BY in")
+            .await
+            .unwrap();
+        assert_eq!(
+            edges,
+            json!(["feature_requests:sso", "feature_requests:two_factor"])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cluster_field_needs_embedder() {
+        let pipeline = Pipeline::with_llm(
+            Llm::new(ScriptedModel::new(), "mock"),
+            seeded_db("cluster-no-embedder").await,
+        );
+        let options = ClusterOptions::new("feature_requests.message".parse().unwrap());
+
+        assert!(matches!(
+            cluster_field(&pipeline, &options).await,
+            Err(ClusterError::NoEmbedder)
+        ));
+    }
+}
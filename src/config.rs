@@ -1,9 +1,40 @@
 use std::env;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use rig::providers::openai;
+
+use crate::{agents::model::MODEL, surreal::SearchField, telemetry::LogFormat};
 
 #[derive(Debug, Clone)]
 pub struct Config {
-    pub xai_api_key: String,
+    /// Provider of the completion model
+    pub provider: Provider,
+    /// Completion model; defaults to the provider's default model
+    pub model: Option<String>,
+    pub xai_api_key: Option<String>,
+    pub openai_api_key: Option<String>,
+    /// Base URL of an OpenAI compatible endpoint, e.g. a local model server
+    pub openai_base_url: Option<String>,
     pub surreal_config: SurrealConfig,
+    /// Directory with prompt template overrides
+    pub prompts_dir: Option<PathBuf>,
+    /// JSON price table used to cost the LLM calls of a run
+    pub prices_file: Option<PathBuf>,
+    /// YAML or JSON list of sub-agents registered next to the built-in ones
+    pub sub_agents_file: Option<PathBuf>,
+    /// Text fields `search-index` defines full-text search indexes for
+    pub search_fields: Vec<SearchField>,
+    /// Embedding model of `embed` and `surreal_vector_search`, served by the
+    /// OpenAI compatible endpoint of `openai_base_url`
+    pub embedding_model: Option<String>,
+    /// Where to store the transcript of every run
+    pub run_store: Option<RunStoreConfig>,
+    /// Format of the logs written to stderr
+    pub log_format: LogFormat,
+    /// OTLP/HTTP collector to export tracing spans to
+    pub otlp_endpoint: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -15,20 +46,182 @@ pub struct SurrealConfig {
     pub database: String,
 }
 
+/// Provider serving the completion model
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Provider {
+    #[default]
+    Xai,
+    /// OpenAI, or any endpoint implementing its chat completions API
+    Openai,
+}
+
+impl Provider {
+    /// Model used when none is configured
+    pub fn default_model(&self) -> &'static str {
+        match self {
+            Provider::Xai => MODEL,
+            Provider::Openai => openai::GPT_4O_MINI,
+        }
+    }
+}
+
+impl FromStr for Provider {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "xai" => Ok(Provider::Xai),
+            "openai" => Ok(Provider::Openai),
+            _ => Err(ConfigError::InvalidValue(
+                "LLM_PROVIDER must be 'xai' or 'openai'",
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Provider::Xai => f.write_str("xai"),
+            Provider::Openai => f.write_str("openai"),
+        }
+    }
+}
+
+/// Where run transcripts are stored
+#[derive(Debug, Clone, PartialEq)]
+pub enum RunStoreConfig {
+    /// The `agent_runs` table of the configured SurrealDB database
+    Surreal,
+    /// One JSON file per run in a local directory
+    Directory(PathBuf),
+}
+
 impl Config {
     pub fn from_env() -> Result<Self, ConfigError> {
+        Self::from_env_with(SurrealConfig::from_env()?)
+    }
+
+    /// Load everything but the database connection from the environment,
+    /// e.g. to run against the embedded demo database
+    pub fn from_env_with(surreal_config: SurrealConfig) -> Result<Self, ConfigError> {
         dotenv::dotenv().ok(); // Load .env file if it exists, ignore if not found
 
-        let xai_api_key =
-            env::var("XAI_API_KEY").map_err(|_| ConfigError::MissingEnvVar("XAI_API_KEY"))?;
+        let provider = match env::var("LLM_PROVIDER") {
+            Ok(provider) if !provider.trim().is_empty() => provider.parse()?,
+            _ => Provider::default(),
+        };
+
+        let model = env::var("LLM_MODEL")
+            .ok()
+            .filter(|model| !model.trim().is_empty());
+
+        // API keys are checked once the provider to use is known
+        let xai_api_key = env::var("XAI_API_KEY").ok();
 
         // Validate API key format
-        if !xai_api_key.starts_with("xai-") {
+        if xai_api_key
+            .as_ref()
+            .is_some_and(|key| !key.starts_with("xai-"))
+        {
             return Err(ConfigError::InvalidValue(
                 "XAI_API_KEY must start with 'xai-'",
             ));
         }
 
+        let openai_api_key = env::var("OPENAI_API_KEY").ok();
+        let openai_base_url = env::var("OPENAI_BASE_URL")
+            .ok()
+            .filter(|url| !url.trim().is_empty());
+
+        let prompts_dir = env::var("PROMPTS_DIR")
+            .ok()
+            .filter(|dir| !dir.trim().is_empty())
+            .map(PathBuf::from);
+
+        let prices_file = env::var("PRICES_FILE")
+            .ok()
+            .filter(|file| !file.trim().is_empty())
+            .map(PathBuf::from);
+
+        let sub_agents_file = env::var("SUB_AGENTS")
+            .ok()
+            .filter(|file| !file.trim().is_empty())
+            .map(PathBuf::from);
+
+        let search_fields = env::var("SEARCH_FIELDS")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|field| !field.trim().is_empty())
+            .map(str::parse)
+            .collect::<Result<Vec<SearchField>, _>>()
+            .map_err(|_| {
+                ConfigError::InvalidValue(
+                    "SEARCH_FIELDS must list `table.field`s separated by commas",
+                )
+            })?;
+
+        let embedding_model = env::var("EMBEDDING_MODEL")
+            .ok()
+            .filter(|model| !model.trim().is_empty());
+
+        // `surreal` for the database, anything else is a directory
+        let run_store = env::var("RUN_STORE")
+            .ok()
+            .filter(|store| !store.trim().is_empty())
+            .map(|store| match store.trim() {
+                "surreal" => RunStoreConfig::Surreal,
+                dir => RunStoreConfig::Directory(PathBuf::from(dir)),
+            });
+
+        let log_format = match env::var("LOG_FORMAT") {
+            Ok(format) => format
+                .parse()
+                .map_err(|_| ConfigError::InvalidValue("LOG_FORMAT must be 'pretty' or 'json'"))?,
+            Err(_) => LogFormat::default(),
+        };
+
+        let otlp_endpoint = env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+            .ok()
+            .filter(|endpoint| !endpoint.trim().is_empty());
+
+        Ok(Config {
+            provider,
+            model,
+            xai_api_key,
+            openai_api_key,
+            openai_base_url,
+            surreal_config,
+            prompts_dir,
+            prices_file,
+            sub_agents_file,
+            search_fields,
+            embedding_model,
+            run_store,
+            log_format,
+            otlp_endpoint,
+        })
+    }
+}
+
+impl Config {
+    /// API key of `provider`
+    pub fn api_key(&self, provider: Provider) -> Result<&str, ConfigError> {
+        let (key, var) = match provider {
+            Provider::Xai => (&self.xai_api_key, "XAI_API_KEY"),
+            Provider::Openai => (&self.openai_api_key, "OPENAI_API_KEY"),
+        };
+
+        key.as_deref()
+            .filter(|key| !key.trim().is_empty())
+            .ok_or(ConfigError::MissingEnvVar(var))
+    }
+}
+
+impl SurrealConfig {
+    pub fn from_env() -> Result<Self, ConfigError> {
+        dotenv::dotenv().ok();
+
         let surreal_config = SurrealConfig {
             host: env::var("SURREAL_HOST")
                 .map_err(|_| ConfigError::MissingEnvVar("SURREAL_HOST"))?,
@@ -57,10 +250,7 @@ impl Config {
             ));
         }
 
-        Ok(Config {
-            xai_api_key,
-            surreal_config,
-        })
+        Ok(surreal_config)
     }
 }
 
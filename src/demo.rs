@@ -0,0 +1,95 @@
+//! Bundled demo dataset, loaded into an embedded database
+//!
+//! `fixtures/demo/demo.surql` defines the `customers` and `feature_requests`
+//! tables of the README example, with table and field comments for the
+//! schema tool and a `customer` record link from every feature request to
+//! the customer that filed it, and fills them with sample records.
+
+use serde_json::Map;
+
+use crate::{
+    config::SurrealConfig,
+    surreal::{SurrealDbConfig, SurrealError, execute_all},
+};
+
+/// SurrealQL defining and filling the demo tables
+pub const DATASET: &str = include_str!("../fixtures/demo/demo.surql");
+
+/// Configuration of the embedded database the demo runs against
+pub fn surreal_config() -> SurrealConfig {
+    SurrealConfig {
+        host: "mem://demo".to_string(),
+        username: String::new(),
+        password: String::new(),
+        namespace: "demo".to_string(),
+        database: "demo".to_string(),
+    }
+}
+
+/// Define the demo tables in the database of `config` and load their records
+///
+/// Fails if the records already exist, so load each database only once.
+pub async fn load(config: &SurrealDbConfig) -> Result<(), SurrealError> {
+    execute_all(config, DATASET, Map::new()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use rig::tool::Tool;
+
+    use super::*;
+    use crate::surreal::{SurrealSelectTool, execute_query, select::SurrealSelectArgs};
+
+    #[tokio::test]
+    async fn test_load_links_feature_requests_to_customers() {
+        let config = SurrealDbConfig::from(&SurrealConfig {
+            host: "mem://demo-test".to_string(),
+            ..surreal_config()
+        });
+        load(&config).await.unwrap();
+
+        let requester = execute_query(
+            &config,
+            "SELECT VALUE customer.name FROM feature_requests:fr1",
+        )
+        .await
+        .unwrap();
+        assert_eq!(requester, json!(["Acme Corp"]));
+
+        // The pipeline joins on the name written in the ticket, which must
+        // match the linked customer
+        let mismatched = execute_query(
+            &config,
+            "SELECT VALUE id FROM feature_requests WHERE customer.name != customer_identifier",
+        )
+        .await
+        .unwrap();
+        assert_eq!(mismatched, json!([]));
+
+        let info = execute_query(&config, "INFO FOR DB").await.unwrap();
+        assert!(info.to_string().contains("Annual Recurring Revenue"));
+    }
+
+    #[tokio::test]
+    async fn test_select_tool_orders_requests_by_date() {
+        let config = SurrealDbConfig::from(&SurrealConfig {
+            host: "mem://demo-dates".to_string(),
+            ..surreal_config()
+        });
+        load(&config).await.unwrap();
+
+        let output = SurrealSelectTool::new(config)
+            .call(SurrealSelectArgs {
+                query:
+                    "SELECT id, created_at FROM feature_requests ORDER BY created_at DESC LIMIT 1"
+                        .to_string(),
+            })
+            .await
+            .unwrap();
+
+        assert!(output.starts_with("Found 1 record(s)"), "{output}");
+        assert!(output.contains("created_at:"));
+    }
+}
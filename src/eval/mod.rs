@@ -0,0 +1,287 @@
+//! Evaluation of the pipeline against a suite of golden questions
+//!
+//! A suite is a YAML or JSON file of cases. Each case asks a question and
+//! states what a good answer looks like, in any combination of:
+//!
+//! - `expected_ids`: record IDs the answer should cite
+//! - `expected_sql`: a query whose rows (by their `id` field) are the
+//!   records the answer should cite
+//! - `expected_agents`: the sub-agents the question should be mapped to
+//! - `rubric`: criteria graded by an LLM judge
+//!
+//! Every run of a suite produces an [`EvalReport`] recording the model and
+//! prompt versions next to the scores, so reports of different runs can be
+//! compared.
+
+mod report;
+
+use std::collections::BTreeSet;
+use std::error::Error as StdError;
+use std::fmt;
+use std::path::Path;
+use std::time::Instant;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    agents::{judge::judge, model::Model},
+    pipeline::Pipeline,
+    runs::RunTranscript,
+    surreal::{SurrealDbConfig, SurrealError, execute_query},
+    usage::UsageMeter,
+};
+
+pub use report::{CaseReport, EvalReport, EvalSummary, Retrieval};
+
+/// A named set of evaluation cases
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Suite {
+    pub name: String,
+    pub cases: Vec<EvalCase>,
+}
+
+/// A golden question and what a good answer looks like
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct EvalCase {
+    pub id: String,
+    pub question: String,
+    /// Record IDs the answer should cite
+    #[serde(default)]
+    pub expected_ids: Vec<String>,
+    /// Query returning the records the answer should cite; must select `id`
+    #[serde(default)]
+    pub expected_sql: Option<String>,
+    /// Sub-agents the question should be mapped to
+    #[serde(default)]
+    pub expected_agents: Option<BTreeSet<String>>,
+    /// Criteria for the LLM judge
+    #[serde(default)]
+    pub rubric: Vec<String>,
+}
+
+/// Error loading or running an evaluation suite
+#[derive(Debug)]
+pub enum EvalError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Yaml(serde_yaml::Error),
+    /// The expected query of a case failed
+    ExpectedSql {
+        case: String,
+        source: SurrealError,
+    },
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::Io(err) => write!(f, "Failed to read evaluation suite: {err}"),
+            EvalError::Json(err) => write!(f, "Invalid evaluation suite: {err}"),
+            EvalError::Yaml(err) => write!(f, "Invalid evaluation suite: {err}"),
+            EvalError::ExpectedSql { case, source } => {
+                write!(f, "Expected query of case {case} failed: {source}")
+            }
+        }
+    }
+}
+
+impl StdError for EvalError {}
+
+impl From<std::io::Error> for EvalError {
+    fn from(err: std::io::Error) -> Self {
+        EvalError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for EvalError {
+    fn from(err: serde_json::Error) -> Self {
+        EvalError::Json(err)
+    }
+}
+
+impl From<serde_yaml::Error> for EvalError {
+    fn from(err: serde_yaml::Error) -> Self {
+        EvalError::Yaml(err)
+    }
+}
+
+impl Suite {
+    /// Load a suite from a `.json` file, or from YAML for any other extension
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, EvalError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)?;
+
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            Ok(serde_json::from_str(&source)?)
+        } else {
+            Ok(serde_yaml::from_str(&source)?)
+        }
+    }
+}
+
+/// Run every case of `suite` through `pipeline`, grading rubrics with an
+/// LLM judge when `judge_rubrics` is set
+///
+/// A case whose pipeline run fails is reported with its error and scored
+/// zero; only a failing `expected_sql` stops the evaluation, since it is a
+/// mistake in the suite rather than in the pipeline.
+#[tracing::instrument(name = "eval", skip_all, fields(suite = %suite.name))]
+pub async fn run_suite<M: Model>(
+    pipeline: &Pipeline<M>,
+    suite: &Suite,
+    judge_rubrics: bool,
+) -> Result<EvalReport, EvalError> {
+    let started_at = Utc::now();
+    let mut cases = Vec::new();
+
+    for case in &suite.cases {
+        let expected = expected_ids(pipeline, case).await?;
+        let started = Instant::now();
+
+        let mut report = match pipeline.run(&case.question).await {
+            Ok(result) => CaseReport::score(case, expected.as_ref(), &result),
+            Err(e) => CaseReport::failed(case, expected.as_ref(), e.to_string()),
+        };
+
+        if judge_rubrics
+            && !case.rubric.is_empty()
+            && let Some(answer) = &report.answer
+        {
+            let llm = pipeline.llm().clone().with_meter(UsageMeter::new());
+
+            match judge(
+                &llm,
+                pipeline.templates(),
+                &case.question,
+                answer,
+                &case.rubric,
+            )
+            .await
+            {
+                Ok(verdicts) => report.grade(verdicts),
+                Err(e) => report.error = Some(e.to_string()),
+            }
+            report.cost_usd += llm.meter().report(pipeline.price_table()).total.cost_usd;
+        }
+
+        report.duration_ms = started.elapsed().as_millis() as u64;
+        tracing::info!(case = case.id, error = report.error, "case finished");
+        cases.push(report);
+    }
+
+    Ok(EvalReport {
+        id: RunTranscript::new_id(),
+        suite: suite.name.clone(),
+        started_at,
+        model: pipeline.llm().name().to_string(),
+        prompt_versions: pipeline.templates().versions().clone(),
+        summary: EvalSummary::new(&cases),
+        cases,
+    })
+}
+
+/// Record IDs the answer to `case` should cite, or `None` if the case does
+/// not say
+async fn expected_ids<M: Model>(
+    pipeline: &Pipeline<M>,
+    case: &EvalCase,
+) -> Result<Option<BTreeSet<String>>, EvalError> {
+    let mut expected: BTreeSet<String> = case.expected_ids.iter().cloned().collect();
+
+    if let Some(sql) = &case.expected_sql {
+        let config = SurrealDbConfig::from(pipeline.surreal_config());
+        let rows = execute_query(&config, sql)
+            .await
+            .map_err(|source| EvalError::ExpectedSql {
+                case: case.id.clone(),
+                source,
+            })?;
+
+        expected.extend(sql_ids(&rows));
+    }
+
+    Ok((!expected.is_empty() || case.expected_sql.is_some()).then_some(expected))
+}
+
+/// The `id` of every row of a query result
+fn sql_ids(rows: &Value) -> BTreeSet<String> {
+    rows.as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|row| row.get("id").and_then(Value::as_str))
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        agents::model::Llm,
+        testing::{customers_sub_agent, one_query_script, seeded_db},
+    };
+
+    const SUITE: &str = r#"
+name: customers
+cases:
+  - id: top-customer
+    question: Which customer pays the most?
+    expected_sql: SELECT id, arr FROM customers ORDER BY arr DESC LIMIT 1
+    expected_agents: [customers]
+    rubric:
+      - Names Acme Corp
+      - Mentions the ARR
+"#;
+
+    #[test]
+    fn test_sql_ids() {
+        let rows = json!([{ "id": "customers:acme" }, { "name": "no id" }]);
+
+        assert_eq!(
+            sql_ids(&rows),
+            BTreeSet::from(["customers:acme".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_example_suite_parses() {
+        let suite = Suite::from_file("evals/feature_requests.yaml").unwrap();
+
+        assert_eq!(suite.cases.len(), 2);
+        assert!(suite.cases[0].expected_sql.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_run_suite() {
+        let model = one_query_script().text(
+            r#"[{"criterion": "Names Acme Corp", "pass": true, "reason": "It does."},
+                    {"criterion": "Mentions the ARR", "pass": false, "reason": "It does not."}]"#,
+        );
+
+        let pipeline = Pipeline::with_llm(
+            Llm::new(model.clone(), "mock"),
+            seeded_db("eval-suite").await,
+        )
+        .sub_agent(customers_sub_agent());
+
+        let suite: Suite = serde_yaml::from_str(SUITE).unwrap();
+        let report = run_suite(&pipeline, &suite, true).await.unwrap();
+        let case = &report.cases[0];
+
+        assert_eq!(model.remaining(), 0);
+        assert_eq!(case.error, None);
+        assert_eq!(case.exact_match, Some(true));
+        assert_eq!(case.plan_match, Some(true));
+        assert_eq!(case.rubric_score, Some(0.5));
+        assert_eq!(report.summary.precision, Some(1.0));
+        assert_eq!(report.summary.recall, Some(1.0));
+        assert_eq!(report.prompt_versions.len(), 6);
+    }
+}
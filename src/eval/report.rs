@@ -0,0 +1,310 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::EvalCase;
+use crate::{agents::judge::Verdict, pipeline::PipelineResult};
+
+/// Precision and recall of the record IDs cited in an answer
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Retrieval {
+    pub expected: BTreeSet<String>,
+    pub cited: BTreeSet<String>,
+    /// Share of the cited IDs that were expected
+    pub precision: f64,
+    /// Share of the expected IDs that were cited
+    pub recall: f64,
+}
+
+impl Retrieval {
+    /// An answer citing nothing when nothing was expected scores 1
+    pub fn score(expected: BTreeSet<String>, cited: BTreeSet<String>) -> Self {
+        let hits = expected.intersection(&cited).count() as f64;
+        let ratio = |total: usize| if total == 0 { 1.0 } else { hits / total as f64 };
+
+        Self {
+            precision: ratio(cited.len()),
+            recall: ratio(expected.len()),
+            expected,
+            cited,
+        }
+    }
+}
+
+/// Outcome and scores of a single case
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CaseReport {
+    pub id: String,
+    pub question: String,
+    /// Final answer of the pipeline; `None` if the run failed
+    pub answer: Option<String>,
+    pub error: Option<String>,
+    /// Set when the case expects record IDs
+    pub retrieval: Option<Retrieval>,
+    /// Whether the answer cites exactly the rows of `expected_sql`
+    pub exact_match: Option<bool>,
+    /// Whether the question was mapped to exactly the expected sub-agents
+    pub plan_match: Option<bool>,
+    pub verdicts: Vec<Verdict>,
+    /// Share of the rubric criteria the judge passed
+    pub rubric_score: Option<f64>,
+    /// ID of the stored transcript of the run, when a run store is configured
+    pub run_id: Option<String>,
+    /// Cost of the run and of judging it
+    pub cost_usd: f64,
+    pub duration_ms: u64,
+}
+
+impl CaseReport {
+    pub(crate) fn score(
+        case: &EvalCase,
+        expected: Option<&BTreeSet<String>>,
+        result: &PipelineResult,
+    ) -> Self {
+        let cited: BTreeSet<String> = result.citations.cited.iter().cloned().collect();
+
+        Self {
+            answer: Some(result.answer.clone()),
+            retrieval: expected.map(|expected| Retrieval::score(expected.clone(), cited.clone())),
+            exact_match: case
+                .expected_sql
+                .as_ref()
+                .and(expected)
+                .map(|expected| *expected == cited),
+            plan_match: case
+                .expected_agents
+                .as_ref()
+                .map(|agents| agents.iter().eq(result.sub_questions.keys())),
+            run_id: result.run_id.clone(),
+            cost_usd: result.usage.total.cost_usd,
+            ..Self::new(case)
+        }
+    }
+
+    pub(crate) fn failed(
+        case: &EvalCase,
+        expected: Option<&BTreeSet<String>>,
+        error: String,
+    ) -> Self {
+        Self {
+            error: Some(error),
+            retrieval: expected.map(|expected| Retrieval {
+                expected: expected.clone(),
+                cited: BTreeSet::new(),
+                precision: 0.0,
+                recall: 0.0,
+            }),
+            exact_match: case.expected_sql.as_ref().map(|_| false),
+            plan_match: case.expected_agents.as_ref().map(|_| false),
+            rubric_score: (!case.rubric.is_empty()).then_some(0.0),
+            ..Self::new(case)
+        }
+    }
+
+    pub(crate) fn grade(&mut self, verdicts: Vec<Verdict>) {
+        let passed = verdicts.iter().filter(|verdict| verdict.pass).count();
+        self.rubric_score = (!verdicts.is_empty()).then(|| passed as f64 / verdicts.len() as f64);
+        self.verdicts = verdicts;
+    }
+
+    fn new(case: &EvalCase) -> Self {
+        Self {
+            id: case.id.clone(),
+            question: case.question.clone(),
+            answer: None,
+            error: None,
+            retrieval: None,
+            exact_match: None,
+            plan_match: None,
+            verdicts: Vec::new(),
+            rubric_score: None,
+            run_id: None,
+            cost_usd: 0.0,
+            duration_ms: 0,
+        }
+    }
+}
+
+/// Scores averaged over the cases that define them
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct EvalSummary {
+    pub cases: usize,
+    /// Cases whose pipeline run or judging failed
+    pub failed: usize,
+    pub precision: Option<f64>,
+    pub recall: Option<f64>,
+    pub exact_match_rate: Option<f64>,
+    pub plan_match_rate: Option<f64>,
+    pub rubric_score: Option<f64>,
+    pub cost_usd: f64,
+}
+
+impl EvalSummary {
+    pub fn new(cases: &[CaseReport]) -> Self {
+        let retrievals = cases.iter().filter_map(|case| case.retrieval.as_ref());
+        let rate = |matches: Vec<bool>| {
+            mean(
+                matches
+                    .into_iter()
+                    .map(|matched| if matched { 1.0 } else { 0.0 }),
+            )
+        };
+
+        Self {
+            cases: cases.len(),
+            failed: cases.iter().filter(|case| case.error.is_some()).count(),
+            precision: mean(retrievals.clone().map(|retrieval| retrieval.precision)),
+            recall: mean(retrievals.map(|retrieval| retrieval.recall)),
+            exact_match_rate: rate(cases.iter().filter_map(|case| case.exact_match).collect()),
+            plan_match_rate: rate(cases.iter().filter_map(|case| case.plan_match).collect()),
+            rubric_score: mean(cases.iter().filter_map(|case| case.rubric_score)),
+            cost_usd: cases.iter().map(|case| case.cost_usd).sum(),
+        }
+    }
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), value| (sum + value, count + 1));
+    (count > 0).then(|| sum / count as f64)
+}
+
+/// Scores of a run of an evaluation suite
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EvalReport {
+    /// Unique, time-ordered ID of the evaluation run
+    pub id: String,
+    pub suite: String,
+    pub started_at: DateTime<Utc>,
+    pub model: String,
+    pub prompt_versions: BTreeMap<String, String>,
+    pub summary: EvalSummary,
+    pub cases: Vec<CaseReport>,
+}
+
+impl EvalReport {
+    /// Write the report to `<dir>/<suite>-<id>.json`, returning its path
+    pub fn write(&self, dir: impl AsRef<Path>) -> std::io::Result<PathBuf> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+
+        let path = dir.join(format!("{}-{}.json", self.suite, self.id));
+        std::fs::write(&path, serde_json::to_vec_pretty(self)?)?;
+
+        Ok(path)
+    }
+}
+
+impl fmt::Display for EvalReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let score =
+            |value: Option<f64>| value.map_or("-".to_string(), |value| format!("{value:.2}"));
+        let flag = |value: Option<bool>| match value {
+            Some(true) => "yes",
+            Some(false) => "no",
+            None => "-",
+        };
+
+        writeln!(
+            f,
+            "{:<24} {:>9} {:>6} {:>6} {:>6} {:>6}  error",
+            "case", "precision", "recall", "exact", "plan", "rubric"
+        )?;
+        for case in &self.cases {
+            writeln!(
+                f,
+                "{:<24} {:>9} {:>6} {:>6} {:>6} {:>6}  {}",
+                case.id,
+                score(case.retrieval.as_ref().map(|retrieval| retrieval.precision)),
+                score(case.retrieval.as_ref().map(|retrieval| retrieval.recall)),
+                flag(case.exact_match),
+                flag(case.plan_match),
+                score(case.rubric_score),
+                case.error.as_deref().unwrap_or("")
+            )?;
+        }
+
+        let summary = &self.summary;
+        writeln!(
+            f,
+            "{:<24} {:>9} {:>6} {:>6} {:>6} {:>6}  {} of {} failed, {:.4} USD",
+            "mean",
+            score(summary.precision),
+            score(summary.recall),
+            score(summary.exact_match_rate),
+            score(summary.plan_match_rate),
+            score(summary.rubric_score),
+            summary.failed,
+            summary.cases,
+            summary.cost_usd
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(ids: &[&str]) -> BTreeSet<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn test_retrieval_score() {
+        let retrieval = Retrieval::score(
+            ids(&["customers:acme", "customers:globex"]),
+            ids(&["customers:acme", "customers:initech", "customers:hooli"]),
+        );
+
+        assert_eq!(retrieval.precision, 1.0 / 3.0);
+        assert_eq!(retrieval.recall, 0.5);
+        assert_eq!(Retrieval::score(ids(&[]), ids(&[])).precision, 1.0);
+    }
+
+    #[test]
+    fn test_summary_skips_undefined_scores() {
+        let case = EvalCase {
+            id: "a".to_string(),
+            question: "?".to_string(),
+            rubric: vec!["Is polite".to_string()],
+            ..Default::default()
+        };
+        let mut graded = CaseReport::new(&case);
+        graded.grade(vec![Verdict {
+            criterion: "Is polite".to_string(),
+            pass: true,
+            reason: "It is.".to_string(),
+        }]);
+
+        let summary = EvalSummary::new(&[
+            graded,
+            CaseReport::failed(&case, None, "timeout".to_string()),
+        ]);
+
+        assert_eq!(summary.failed, 1);
+        assert_eq!(summary.rubric_score, Some(0.5));
+        assert_eq!(summary.precision, None);
+        assert_eq!(summary.exact_match_rate, None);
+    }
+
+    #[test]
+    fn test_failed_case_keeps_expected_ids() {
+        let case = EvalCase {
+            id: "a".to_string(),
+            question: "?".to_string(),
+            expected_ids: vec!["customers:acme".to_string()],
+            expected_sql: Some("SELECT id FROM customers WHERE arr > 100000".to_string()),
+            ..Default::default()
+        };
+        let expected = ids(&["customers:acme", "customers:globex"]);
+
+        let report = CaseReport::failed(&case, Some(&expected), "timeout".to_string());
+
+        let retrieval = report.retrieval.unwrap();
+        assert_eq!(retrieval.expected, expected);
+        assert_eq!(retrieval.recall, 0.0);
+        assert_eq!(report.exact_match, Some(false));
+    }
+}
@@ -0,0 +1,923 @@
+//! Loading JSONL, JSON, CSV and Parquet files into SurrealDB tables
+//!
+//! Rows are read in full, their schema is inferred from every value (or
+//! taken as given), values are converted to the types of the schema, and
+//! the rows are written in batches. With a key field, each row is upserted
+//! as the record `<table>:<key>` so an import can be repeated to update the
+//! table; without one, rows are inserted with generated IDs.
+
+mod schema;
+
+use std::collections::BTreeMap;
+use std::error::Error as StdError;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use parquet::{
+    errors::ParquetError,
+    file::reader::{FileReader, SerializedFileReader},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::surreal::{SurrealDbConfig, SurrealError, execute_all};
+
+pub use schema::{Field, FieldType, Schema};
+
+/// Rows written per query unless configured otherwise
+pub const DEFAULT_BATCH_SIZE: usize = 500;
+
+/// File format of an import
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    /// One JSON object per line
+    Jsonl,
+    /// A JSON array of objects
+    Json,
+    /// Comma separated values with a header row
+    Csv,
+    /// Apache Parquet, one row per record
+    Parquet,
+}
+
+impl FromStr for Format {
+    type Err = ImportError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "jsonl" | "ndjson" => Ok(Format::Jsonl),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            "parquet" => Ok(Format::Parquet),
+            other => Err(ImportError::UnknownFormat(other.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Format::Jsonl => "jsonl",
+            Format::Json => "json",
+            Format::Csv => "csv",
+            Format::Parquet => "parquet",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Format {
+    /// Format of a file, from its extension
+    pub fn from_path(path: &Path) -> Result<Self, ImportError> {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .parse()
+    }
+}
+
+/// Error reading or importing rows
+#[derive(Debug)]
+pub enum ImportError {
+    Io(std::io::Error),
+    Csv(csv::Error),
+    Parquet(ParquetError),
+    UnknownFormat(String),
+    /// The source cannot be read in the requested format
+    InvalidInput(String),
+    InvalidSchema(String),
+    /// A row could not be read or converted; rows are numbered from 1
+    InvalidRow {
+        row: usize,
+        message: String,
+    },
+    Surreal(SurrealError),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Io(err) => write!(f, "Failed to read import file: {err}"),
+            ImportError::Csv(err) => write!(f, "Invalid CSV: {err}"),
+            ImportError::Parquet(err) => write!(f, "Invalid Parquet: {err}"),
+            ImportError::UnknownFormat(format) => {
+                write!(
+                    f,
+                    "Unknown import format `{format}`; use jsonl, json, csv or parquet"
+                )
+            }
+            ImportError::InvalidInput(msg) => write!(f, "Invalid input: {msg}"),
+            ImportError::InvalidSchema(msg) => write!(f, "Invalid schema: {msg}"),
+            ImportError::InvalidRow { row, message } => write!(f, "Invalid row {row}: {message}"),
+            ImportError::Surreal(err) => write!(f, "Failed to import rows: {err}"),
+        }
+    }
+}
+
+impl StdError for ImportError {}
+
+impl From<std::io::Error> for ImportError {
+    fn from(err: std::io::Error) -> Self {
+        ImportError::Io(err)
+    }
+}
+
+impl From<csv::Error> for ImportError {
+    fn from(err: csv::Error) -> Self {
+        ImportError::Csv(err)
+    }
+}
+
+impl From<ParquetError> for ImportError {
+    fn from(err: ParquetError) -> Self {
+        ImportError::Parquet(err)
+    }
+}
+
+impl From<SurrealError> for ImportError {
+    fn from(err: SurrealError) -> Self {
+        ImportError::Surreal(err)
+    }
+}
+
+/// How rows are imported into a table
+#[derive(Clone, Debug)]
+pub struct ImportOptions {
+    table: String,
+    format: Option<Format>,
+    schema: Option<Schema>,
+    define: bool,
+    key: Option<String>,
+    replace: bool,
+    batch_size: usize,
+    dry_run: bool,
+}
+
+impl ImportOptions {
+    /// Import into `table`, inferring the schema and inserting new records
+    pub fn new(table: impl Into<String>) -> Self {
+        Self {
+            table: table.into(),
+            format: None,
+            schema: None,
+            define: false,
+            key: None,
+            replace: false,
+            batch_size: DEFAULT_BATCH_SIZE,
+            dry_run: false,
+        }
+    }
+
+    /// Read files in this format instead of guessing it from their extension
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// Convert values to the types of `schema` instead of inferred ones
+    pub fn schema(mut self, schema: Schema) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// Define the table and its fields before writing rows
+    pub fn define(mut self, define: bool) -> Self {
+        self.define = define;
+        self
+    }
+
+    /// Upsert each row as the record keyed by the value of its `key` field
+    pub fn upsert_by(mut self, key: impl Into<String>) -> Self {
+        self.key = Some(key.into());
+        self
+    }
+
+    /// Delete the records of the table before writing rows, so importing the
+    /// same rows again does not duplicate them
+    pub fn replace(mut self, replace: bool) -> Self {
+        self.replace = replace;
+        self
+    }
+
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Read, check and report the rows without writing anything
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+}
+
+/// What an import did, or would do in a dry run
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub table: String,
+    pub rows: usize,
+    pub batches: usize,
+    pub schema: Schema,
+    /// `DEFINE` statements run before the rows were written
+    pub statements: Vec<String>,
+    /// Field the records were keyed by, when upserting
+    pub key: Option<String>,
+    pub dry_run: bool,
+}
+
+impl fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verb = if self.dry_run {
+            "Would import"
+        } else {
+            "Imported"
+        };
+        write!(
+            f,
+            "{verb} {} row(s) into {} in {} batch(es)",
+            self.rows, self.table, self.batches
+        )?;
+        match &self.key {
+            Some(key) => writeln!(f, ", upserting by {key}")?,
+            None => writeln!(f)?,
+        }
+
+        for field in &self.schema.fields {
+            writeln!(f, "  {:<24} {}", field.name, field.surreal_type())?;
+        }
+        for statement in &self.statements {
+            writeln!(f, "{statement}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Read the rows of a file, in the format given by `format` or its extension
+pub fn read_file(
+    path: impl AsRef<Path>,
+    format: Option<Format>,
+) -> Result<Vec<Map<String, Value>>, ImportError> {
+    let path = path.as_ref();
+    let format = match format {
+        Some(format) => format,
+        None => Format::from_path(path)?,
+    };
+
+    read_path(path, format, None)
+}
+
+/// Read the rows of a file in `format`, keeping the text of the CSV cells of
+/// the fields of `schema`
+fn read_path(
+    path: &Path,
+    format: Format,
+    schema: Option<&Schema>,
+) -> Result<Vec<Map<String, Value>>, ImportError> {
+    match format {
+        Format::Parquet => read_parquet(std::fs::read(path)?.into()),
+        Format::Csv => read_csv(&std::fs::read_to_string(path)?, schema),
+        format => read_rows(&std::fs::read_to_string(path)?, format),
+    }
+}
+
+/// Read rows from the contents of a file
+///
+/// CSV cells are typed as by [`read_csv`] without a schema. Parquet is
+/// binary, so Parquet files are read with [`read_file`] or [`read_parquet`].
+pub fn read_rows(source: &str, format: Format) -> Result<Vec<Map<String, Value>>, ImportError> {
+    match format {
+        Format::Jsonl => source
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| object(serde_json::from_str(line), index + 1))
+            .collect(),
+        Format::Json => {
+            let rows: Vec<Value> =
+                serde_json::from_str(source).map_err(|e| ImportError::InvalidRow {
+                    row: 0,
+                    message: format!("expected a JSON array of objects: {e}"),
+                })?;
+
+            rows.into_iter()
+                .enumerate()
+                .map(|(index, row)| object(Ok(row), index + 1))
+                .collect()
+        }
+        Format::Parquet => Err(ImportError::InvalidInput(
+            "Parquet is binary; read it with read_file or read_parquet".to_string(),
+        )),
+        Format::Csv => read_csv(source, None),
+    }
+}
+
+/// Read CSV rows with a header row
+///
+/// Empty cells are null. Cells of the fields of `schema` keep their text,
+/// to be converted to the type of their field on import; other cells are
+/// typed by their text, as booleans or numbers when they read as such.
+/// Numbers written with leading zeros, such as ZIP codes, stay text.
+pub fn read_csv(
+    source: &str,
+    schema: Option<&Schema>,
+) -> Result<Vec<Map<String, Value>>, ImportError> {
+    let mut reader = csv::Reader::from_reader(source.as_bytes());
+    let headers = reader.headers()?.clone();
+
+    reader
+        .records()
+        .enumerate()
+        .map(|(index, record)| {
+            let record = record.map_err(|e| ImportError::InvalidRow {
+                row: index + 1,
+                message: e.to_string(),
+            })?;
+
+            Ok(headers
+                .iter()
+                .zip(record.iter())
+                .map(|(header, cell)| {
+                    let value = match schema.and_then(|schema| schema.get(header)) {
+                        Some(_) if cell.is_empty() => Value::Null,
+                        Some(_) => Value::String(cell.to_string()),
+                        None => csv_value(cell),
+                    };
+                    (header.to_string(), value)
+                })
+                .collect())
+        })
+        .collect()
+}
+
+/// Read the rows of a Parquet file
+///
+/// Dates and timestamps are read as strings.
+pub fn read_parquet(data: Bytes) -> Result<Vec<Map<String, Value>>, ImportError> {
+    let reader = SerializedFileReader::new(data)?;
+
+    reader
+        .get_row_iter(None)?
+        .enumerate()
+        .map(|(index, row)| object(Ok(row?.to_json_value()), index + 1))
+        .collect()
+}
+
+fn object(value: serde_json::Result<Value>, row: usize) -> Result<Map<String, Value>, ImportError> {
+    match value {
+        Ok(Value::Object(fields)) => Ok(fields),
+        Ok(other) => Err(ImportError::InvalidRow {
+            row,
+            message: format!("expected an object, found {other}"),
+        }),
+        Err(e) => Err(ImportError::InvalidRow {
+            row,
+            message: e.to_string(),
+        }),
+    }
+}
+
+fn csv_value(cell: &str) -> Value {
+    let zero_padded =
+        cell.len() > 1 && cell.starts_with('0') && cell.bytes().all(|byte| byte.is_ascii_digit());
+
+    if cell.is_empty() {
+        Value::Null
+    } else if zero_padded {
+        Value::String(cell.to_string())
+    } else if let Ok(value) = cell.parse::<bool>() {
+        Value::Bool(value)
+    } else if let Ok(value) = cell.parse::<i64>() {
+        value.into()
+    } else if let Some(value) = cell.parse::<f64>().ok().filter(|value| value.is_finite()) {
+        value.into()
+    } else {
+        Value::String(cell.to_string())
+    }
+}
+
+/// Import the rows of a file into a table
+pub async fn import_file(
+    config: &SurrealDbConfig,
+    path: impl AsRef<Path>,
+    options: &ImportOptions,
+) -> Result<ImportReport, ImportError> {
+    let path = path.as_ref();
+    let format = match options.format {
+        Some(format) => format,
+        None => Format::from_path(path)?,
+    };
+
+    let rows = read_path(path, format, options.schema.as_ref())?;
+    import_rows(config, rows, options).await
+}
+
+/// Import rows into a table
+#[tracing::instrument(name = "import", skip_all, fields(table = %options.table, rows = rows.len()))]
+pub async fn import_rows(
+    config: &SurrealDbConfig,
+    rows: Vec<Map<String, Value>>,
+    options: &ImportOptions,
+) -> Result<ImportReport, ImportError> {
+    let mut schema = match &options.schema {
+        Some(schema) => schema.clone(),
+        None => Schema::infer(&rows),
+    };
+    // The record ID is not a field of the table
+    schema.fields.retain(|field| field.name != "id");
+
+    // Rows with IDs keep them, as the keys of their records
+    let key = options.key.clone().or_else(|| {
+        rows.iter()
+            .any(|row| row.contains_key("id"))
+            .then(|| "id".to_string())
+    });
+
+    if let Some(key) = &key
+        && schema.get(key).is_none()
+        && !rows.iter().any(|row| row.contains_key(key))
+    {
+        return Err(ImportError::InvalidSchema(format!(
+            "key field `{key}` is not a field of the rows"
+        )));
+    }
+
+    let records = rows
+        .into_iter()
+        .enumerate()
+        .map(|(index, row)| Record::new(row, &schema, key.as_deref(), index + 1))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let statements = if options.define {
+        schema.define_statements(&options.table)
+    } else {
+        Vec::new()
+    };
+    let batches = records.chunks(options.batch_size);
+
+    let report = ImportReport {
+        table: options.table.clone(),
+        rows: records.len(),
+        batches: batches.len(),
+        schema,
+        statements,
+        key: key.clone(),
+        dry_run: options.dry_run,
+    };
+
+    if options.dry_run {
+        return Ok(report);
+    }
+
+    if !report.statements.is_empty() {
+        execute_all(config, &report.statements.join("\n"), Map::new()).await?;
+    }
+
+    if options.replace {
+        execute_all(
+            config,
+            "DELETE type::table($table);",
+            Map::from_iter([("table".to_string(), Value::from(options.table.clone()))]),
+        )
+        .await?;
+    }
+
+    let query = if key.is_some() {
+        "FOR $record IN $records { UPSERT type::thing($table, $record.key) CONTENT $record.content; }"
+    } else {
+        "FOR $record IN $records { CREATE type::table($table) CONTENT $record.content; }"
+    };
+
+    for (number, batch) in batches.enumerate() {
+        execute_all(
+            config,
+            query,
+            Bindings {
+                table: options.table.clone(),
+                records: batch.to_vec(),
+            },
+        )
+        .await?;
+        tracing::info!(batch = number + 1, rows = batch.len(), "batch imported");
+    }
+
+    Ok(report)
+}
+
+#[derive(Serialize)]
+struct Bindings {
+    table: String,
+    records: Vec<Record>,
+}
+
+/// A row converted to the types of the schema
+#[derive(Clone, Serialize)]
+struct Record {
+    /// Key of the record when upserting
+    key: Option<Value>,
+    content: BTreeMap<String, Cell>,
+}
+
+/// Value of a field; datetimes are bound as SurrealDB datetimes, since
+/// SurrealDB does not convert strings to typed datetime fields
+#[derive(Clone, Serialize)]
+#[serde(untagged)]
+enum Cell {
+    Json(Value),
+    Datetime(surrealdb::Datetime),
+}
+
+impl Record {
+    fn new(
+        row: Map<String, Value>,
+        schema: &Schema,
+        key: Option<&str>,
+        number: usize,
+    ) -> Result<Self, ImportError> {
+        let invalid = |message: String| ImportError::InvalidRow {
+            row: number,
+            message,
+        };
+
+        if row.contains_key("id") && key != Some("id") {
+            return Err(invalid(
+                "field `id` holds record IDs; import with the key `id` or rename the field"
+                    .to_string(),
+            ));
+        }
+
+        let key = match key {
+            Some(key) => match row.get(key) {
+                Some(value @ (Value::String(_) | Value::Number(_))) => Some(value.clone()),
+                _ => {
+                    return Err(invalid(format!(
+                        "key field `{key}` must be a string or number"
+                    )));
+                }
+            },
+            None => None,
+        };
+
+        let mut content = BTreeMap::new();
+        for (name, value) in row {
+            // Null fields are left out, which is how optional fields are empty in SurrealDB
+            if value.is_null() || name == "id" {
+                continue;
+            }
+
+            let cell = match schema.get(&name) {
+                Some(field) => convert(value, field.kind)
+                    .ok_or_else(|| invalid(format!("`{name}` is not a valid {}", field.kind)))?,
+                None => Cell::Json(value),
+            };
+            content.insert(name, cell);
+        }
+
+        for field in &schema.fields {
+            if !field.optional && !content.contains_key(&field.name) {
+                return Err(invalid(format!("missing required field `{}`", field.name)));
+            }
+        }
+
+        Ok(Self { key, content })
+    }
+}
+
+/// `value` as a value of type `kind`, or `None` if it cannot be converted
+fn convert(value: Value, kind: FieldType) -> Option<Cell> {
+    let value = match (kind, value) {
+        (FieldType::Datetime, Value::String(text)) => {
+            let datetime = text.parse::<DateTime<Utc>>().ok()?;
+            return Some(Cell::Datetime(datetime.into()));
+        }
+        (FieldType::Any, value)
+        | (FieldType::Bool, value @ Value::Bool(_))
+        | (FieldType::String, value @ Value::String(_))
+        | (FieldType::Object, value @ Value::Object(_))
+        | (FieldType::Array, value @ Value::Array(_)) => value,
+        (FieldType::Int, Value::Number(n)) => match n.as_i64() {
+            Some(n) => n.into(),
+            // Whole floats, e.g. from a spreadsheet export
+            None => {
+                let n = n.as_f64()?;
+                (n.fract() == 0.0).then_some((n as i64).into())?
+            }
+        },
+        (FieldType::Float, Value::Number(n)) => n.as_f64()?.into(),
+        (FieldType::Int, Value::String(text)) => text.trim().parse::<i64>().ok()?.into(),
+        (FieldType::Float, Value::String(text)) => text.trim().parse::<f64>().ok()?.into(),
+        (FieldType::Bool, Value::String(text)) => text.trim().parse::<bool>().ok()?.into(),
+        // Text fields keep the text of numbers or booleans, e.g. from JSON
+        (FieldType::String, value @ (Value::Number(_) | Value::Bool(_))) => {
+            Value::String(value.to_string())
+        }
+        _ => return None,
+    };
+
+    Some(Cell::Json(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn config(name: &str) -> SurrealDbConfig {
+        SurrealDbConfig::new(
+            format!("mem://{name}"),
+            String::new(),
+            String::new(),
+            "test".to_string(),
+            "test".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_read_formats() {
+        let jsonl = read_rows(
+            "{\"name\": \"Acme\", \"arr\": 100}\n\n{\"name\": \"Globex\"}\n",
+            Format::Jsonl,
+        )
+        .unwrap();
+        let json = read_rows(r#"[{"name": "Acme", "arr": 100}]"#, Format::Json).unwrap();
+        let csv = read_rows("name,arr,active,note\nAcme,100,true,\n", Format::Csv).unwrap();
+
+        assert_eq!(jsonl.len(), 2);
+        assert_eq!(json[0]["arr"], 100);
+        assert_eq!(
+            Value::Object(csv[0].clone()),
+            json!({ "name": "Acme", "arr": 100, "active": true, "note": null })
+        );
+    }
+
+    #[test]
+    fn test_read_parquet() {
+        use parquet::{
+            data_type::{ByteArray, ByteArrayType, Int64Type},
+            file::writer::SerializedFileWriter,
+            schema::parser::parse_message_type,
+        };
+
+        let schema = parse_message_type(
+            "message customers { REQUIRED BYTE_ARRAY name (UTF8); OPTIONAL INT64 arr; }",
+        )
+        .unwrap();
+        let mut data = Vec::new();
+        let mut writer =
+            SerializedFileWriter::new(&mut data, schema.into(), Default::default()).unwrap();
+        let mut row_group = writer.next_row_group().unwrap();
+
+        let mut column = row_group.next_column().unwrap().unwrap();
+        column
+            .typed::<ByteArrayType>()
+            .write_batch(
+                &[ByteArray::from("Acme"), ByteArray::from("Globex")],
+                None,
+                None,
+            )
+            .unwrap();
+        column.close().unwrap();
+        let mut column = row_group.next_column().unwrap().unwrap();
+        column
+            .typed::<Int64Type>()
+            .write_batch(&[100], Some(&[1, 0]), None)
+            .unwrap();
+        column.close().unwrap();
+
+        row_group.close().unwrap();
+        writer.close().unwrap();
+
+        let rows = read_parquet(data.into()).unwrap();
+
+        assert_eq!(
+            rows.into_iter().map(Value::Object).collect::<Vec<_>>(),
+            [
+                json!({ "name": "Acme", "arr": 100 }),
+                json!({ "name": "Globex", "arr": null })
+            ]
+        );
+        assert!(matches!(
+            read_parquet(Bytes::from_static(b"name,arr\n")),
+            Err(ImportError::Parquet(_))
+        ));
+    }
+
+    #[test]
+    fn test_read_rejects_non_objects() {
+        assert!(matches!(
+            read_rows("{\"name\": \"Acme\"}\n[1, 2]\n", Format::Jsonl),
+            Err(ImportError::InvalidRow { row: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_read_csv_keeps_zero_padded_text() {
+        let source = "name,zip,arr\nAcme,02134,100\n";
+        let schema = "name:string,zip:string,arr:int".parse::<Schema>().unwrap();
+
+        let inferred = read_csv(source, None).unwrap();
+        let typed = read_csv(source, Some(&schema)).unwrap();
+
+        assert_eq!(inferred[0]["zip"], "02134");
+        assert_eq!(inferred[0]["arr"], 100);
+        assert_eq!(typed[0]["zip"], "02134");
+        assert_eq!(typed[0]["arr"], "100");
+    }
+
+    #[test]
+    fn test_read_csv_reports_row_of_parse_errors() {
+        assert!(matches!(
+            read_csv("name,zip\nAcme,02134\nGlobex\n", None),
+            Err(ImportError::InvalidRow { row: 2, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_import_keeps_zero_padded_zip_codes() {
+        let config = config("import-zip");
+        let path = std::env::temp_dir().join(format!("import-zip-{}.csv", std::process::id()));
+        std::fs::write(&path, "name,zip\nAcme,02134\nGlobex,10001\n").unwrap();
+
+        import_file(
+            &config,
+            &path,
+            &ImportOptions::new("customers")
+                .schema("name:string,zip:string".parse().unwrap())
+                .define(true),
+        )
+        .await
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let zips =
+            crate::surreal::execute_query(&config, "SELECT VALUE zip FROM customers ORDER BY zip")
+                .await
+                .unwrap();
+        assert_eq!(zips, json!(["02134", "10001"]));
+    }
+
+    #[tokio::test]
+    async fn test_import_keys_records_by_id_field() {
+        let config = config("import-id");
+        let rows = read_rows("id,name\nacme,Acme\nglobex,Globex\n", Format::Csv).unwrap();
+
+        let report = import_rows(&config, rows.clone(), &ImportOptions::new("customers"))
+            .await
+            .unwrap();
+        let keyed = import_rows(
+            &config,
+            rows,
+            &ImportOptions::new("customers").upsert_by("name"),
+        )
+        .await;
+
+        assert_eq!(report.key.as_deref(), Some("id"));
+        assert_eq!(
+            crate::surreal::execute_query(&config, "SELECT VALUE id FROM customers ORDER BY id")
+                .await
+                .unwrap(),
+            json!(["customers:acme", "customers:globex"])
+        );
+        assert!(matches!(keyed, Err(ImportError::InvalidRow { row: 1, .. })));
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_reports_without_writing() {
+        let config = config("import-dry-run");
+        let rows = read_rows("name,arr\nAcme,100\nGlobex,50\n", Format::Csv).unwrap();
+
+        let report = import_rows(
+            &config,
+            rows,
+            &ImportOptions::new("customers")
+                .define(true)
+                .batch_size(1)
+                .dry_run(true),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(report.rows, 2);
+        assert_eq!(report.batches, 2);
+        assert_eq!(report.statements.len(), 3);
+        assert_eq!(
+            crate::surreal::execute_query(&config, "SELECT * FROM customers")
+                .await
+                .unwrap(),
+            json!([])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_upserts_by_key() {
+        let config = config("import-upsert");
+        let options = ImportOptions::new("customers")
+            .schema("name:string,arr:int,since:datetime".parse().unwrap())
+            .define(true)
+            .upsert_by("name")
+            .batch_size(2);
+
+        let first = read_rows(
+            "name,arr,since\nAcme,100,2024-01-01T00:00:00Z\nGlobex,50,2023-06-01T00:00:00Z\nInitech,10,2022-02-01T00:00:00Z\n",
+            Format::Csv,
+        )
+        .unwrap();
+        let report = import_rows(&config, first, &options).await.unwrap();
+        assert_eq!(report.batches, 2);
+
+        let second = read_rows(
+            r#"{"name": "Acme", "arr": "250", "since": "2024-01-01T00:00:00Z"}"#,
+            Format::Jsonl,
+        )
+        .unwrap();
+        import_rows(&config, second, &options).await.unwrap();
+
+        let rows = crate::surreal::execute_query(
+            &config,
+            "SELECT meta::id(id) AS key, arr, type::is::datetime(since) AS typed FROM customers ORDER BY key",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            rows,
+            json!([
+                { "key": "Acme", "arr": 250, "typed": true },
+                { "key": "Globex", "arr": 50, "typed": true },
+                { "key": "Initech", "arr": 10, "typed": true },
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_replaces_rows() {
+        let config = config("import-replace");
+        let options = ImportOptions::new("customers").replace(true);
+
+        for _ in 0..2 {
+            let rows = read_rows("name,arr\nAcme,100\nGlobex,50\n", Format::Csv).unwrap();
+            import_rows(&config, rows, &options).await.unwrap();
+        }
+
+        let rows = crate::surreal::execute_query(&config, "SELECT name FROM customers")
+            .await
+            .unwrap();
+        assert_eq!(rows.as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_read_rows_refuses_parquet_text() {
+        assert!(matches!(
+            read_rows("PAR1", Format::Parquet),
+            Err(ImportError::InvalidInput(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_import_inserts_rows() {
+        let config = config("import-insert");
+        let rows = read_rows(
+            r#"[{"name": "Acme", "tags": ["b2b"]}, {"name": "Globex", "note": null}]"#,
+            Format::Json,
+        )
+        .unwrap();
+
+        import_rows(&config, rows, &ImportOptions::new("customers"))
+            .await
+            .unwrap();
+
+        let rows = crate::surreal::execute_query(
+            &config,
+            "SELECT name, tags FROM customers ORDER BY name",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            rows,
+            json!([{ "name": "Acme", "tags": ["b2b"] }, { "name": "Globex", "tags": null }])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_rejects_invalid_values() {
+        let rows = read_rows("name,arr\nAcme,lots\n", Format::Csv).unwrap();
+
+        let result = import_rows(
+            &config("import-invalid"),
+            rows,
+            &ImportOptions::new("customers").schema("name:string,arr:int".parse().unwrap()),
+        )
+        .await;
+
+        assert!(matches!(
+            result,
+            Err(ImportError::InvalidRow { row: 1, .. })
+        ));
+    }
+}
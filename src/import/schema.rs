@@ -0,0 +1,300 @@
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::surreal::escape;
+
+/// Type of an imported field, named as in SurrealQL
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldType {
+    Bool,
+    Int,
+    Float,
+    String,
+    Datetime,
+    Object,
+    Array,
+    /// Values of mixed types
+    Any,
+}
+
+impl fmt::Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FieldType::Bool => "bool",
+            FieldType::Int => "int",
+            FieldType::Float => "float",
+            FieldType::String => "string",
+            FieldType::Datetime => "datetime",
+            FieldType::Object => "object",
+            FieldType::Array => "array",
+            FieldType::Any => "any",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for FieldType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "bool" => Ok(FieldType::Bool),
+            "int" => Ok(FieldType::Int),
+            "float" | "number" => Ok(FieldType::Float),
+            "string" => Ok(FieldType::String),
+            "datetime" => Ok(FieldType::Datetime),
+            "object" => Ok(FieldType::Object),
+            "array" => Ok(FieldType::Array),
+            "any" => Ok(FieldType::Any),
+            other => Err(format!("Unknown field type: {other}")),
+        }
+    }
+}
+
+impl FieldType {
+    /// Type of a single value; `None` for null
+    fn of(value: &Value) -> Option<Self> {
+        match value {
+            Value::Null => None,
+            Value::Bool(_) => Some(FieldType::Bool),
+            Value::Number(n) if n.is_f64() => Some(FieldType::Float),
+            Value::Number(_) => Some(FieldType::Int),
+            Value::String(s) if s.parse::<DateTime<Utc>>().is_ok() => Some(FieldType::Datetime),
+            Value::String(_) => Some(FieldType::String),
+            Value::Array(_) => Some(FieldType::Array),
+            Value::Object(_) => Some(FieldType::Object),
+        }
+    }
+
+    /// Narrowest type holding values of both types
+    fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (FieldType::Int, FieldType::Float) | (FieldType::Float, FieldType::Int) => {
+                FieldType::Float
+            }
+            // Text that only sometimes looks like a date is text
+            (FieldType::Datetime, FieldType::String) | (FieldType::String, FieldType::Datetime) => {
+                FieldType::String
+            }
+            _ => FieldType::Any,
+        }
+    }
+}
+
+/// A field of an imported table
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Field {
+    pub name: String,
+    pub kind: FieldType,
+    /// Whether rows may lack the field or hold null
+    pub optional: bool,
+}
+
+impl Field {
+    /// SurrealQL type of the field, e.g. `option<int>`
+    pub fn surreal_type(&self) -> String {
+        if self.optional {
+            format!("option<{}>", self.kind)
+        } else {
+            self.kind.to_string()
+        }
+    }
+}
+
+/// Fields of an imported table
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Schema {
+    pub fields: Vec<Field>,
+}
+
+impl Schema {
+    /// Infer the type of every field from the values of all rows
+    pub fn infer(rows: &[Map<String, Value>]) -> Self {
+        // Name, type of the non-null values seen so far, and optionality
+        let mut fields: Vec<(String, Option<FieldType>, bool)> = Vec::new();
+
+        for (row_number, row) in rows.iter().enumerate() {
+            for (name, value) in row {
+                let position = match fields.iter().position(|(seen, ..)| seen == name) {
+                    Some(position) => position,
+                    None => {
+                        // Rows before this one lack the field
+                        fields.push((name.clone(), None, row_number > 0));
+                        fields.len() - 1
+                    }
+                };
+
+                let (_, kind, optional) = &mut fields[position];
+                match FieldType::of(value) {
+                    Some(value_kind) => {
+                        *kind = Some(kind.map_or(value_kind, |kind| kind.merge(value_kind)))
+                    }
+                    None => *optional = true,
+                }
+            }
+
+            for (name, _, optional) in &mut fields {
+                if !row.contains_key(name.as_str()) {
+                    *optional = true;
+                }
+            }
+        }
+
+        Self {
+            fields: fields
+                .into_iter()
+                .map(|(name, kind, optional)| Field {
+                    name,
+                    kind: kind.unwrap_or(FieldType::Any),
+                    optional,
+                })
+                .collect(),
+        }
+    }
+
+    /// SurrealQL statements defining `table` and its fields
+    ///
+    /// The table is schemafull, so only the fields of the schema are stored.
+    pub fn define_statements(&self, table: &str) -> Vec<String> {
+        std::iter::once(format!(
+            "DEFINE TABLE IF NOT EXISTS {} SCHEMAFULL;",
+            escape(table)
+        ))
+        .chain(self.fields.iter().map(|field| {
+            let flexible = if field.kind == FieldType::Object {
+                "FLEXIBLE "
+            } else {
+                ""
+            };
+            format!(
+                "DEFINE FIELD OVERWRITE {} ON TABLE {} {flexible}TYPE {};",
+                escape(&field.name),
+                escape(table),
+                field.surreal_type()
+            )
+        }))
+        .collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+}
+
+/// Parses `name:type` pairs separated by commas, e.g.
+/// `name:string,arr:int,churned_at:option<datetime>`
+impl FromStr for Schema {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = s
+            .split(',')
+            .filter(|pair| !pair.trim().is_empty())
+            .map(|pair| {
+                let (name, kind) = pair
+                    .split_once(':')
+                    .ok_or_else(|| format!("Expected `name:type`, found `{pair}`"))?;
+                let kind = kind.trim();
+                let (kind, optional) = match kind
+                    .strip_prefix("option<")
+                    .and_then(|kind| kind.strip_suffix('>'))
+                {
+                    Some(kind) => (kind, true),
+                    None => (kind, false),
+                };
+
+                Ok(Field {
+                    name: name.trim().to_string(),
+                    kind: kind.parse()?,
+                    optional,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+
+        Ok(Self { fields })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn rows(rows: Value) -> Vec<Map<String, Value>> {
+        serde_json::from_value(rows).unwrap()
+    }
+
+    #[test]
+    fn test_infer() {
+        let schema = Schema::infer(&rows(json!([
+            { "name": "Acme", "arr": 100, "since": "2024-01-01T00:00:00Z", "note": null },
+            { "name": "Globex", "arr": 2.5, "since": "2023-06-01T00:00:00Z", "tags": ["b2b"] },
+        ])));
+
+        let types = schema
+            .fields
+            .iter()
+            .map(|field| (field.name.as_str(), field.surreal_type()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            types,
+            [
+                ("name", "string".to_string()),
+                ("arr", "float".to_string()),
+                ("since", "datetime".to_string()),
+                ("note", "option<any>".to_string()),
+                ("tags", "option<array>".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_infer_mixed_types() {
+        let schema = Schema::infer(&rows(json!([
+            { "id": 1, "when": "2024-01-01T00:00:00Z" },
+            { "id": "two", "when": "soon" },
+        ])));
+
+        assert_eq!(schema.get("id").unwrap().kind, FieldType::Any);
+        assert_eq!(schema.get("when").unwrap().kind, FieldType::String);
+    }
+
+    #[test]
+    fn test_parse_schema() {
+        let schema: Schema = "name:string, arr:int,churned_at:option<datetime>"
+            .parse()
+            .unwrap();
+
+        assert_eq!(schema.fields.len(), 3);
+        assert_eq!(schema.get("arr").unwrap().kind, FieldType::Int);
+        assert!(schema.get("churned_at").unwrap().optional);
+        assert!("name".parse::<Schema>().is_err());
+        assert!("name:text".parse::<Schema>().is_err());
+    }
+
+    #[test]
+    fn test_define_statements() {
+        let schema: Schema = "name:string,Customer Name:option<string>,meta:object"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            schema.define_statements("customers"),
+            [
+                "DEFINE TABLE IF NOT EXISTS customers SCHEMAFULL;",
+                "DEFINE FIELD OVERWRITE name ON TABLE customers TYPE string;",
+                "DEFINE FIELD OVERWRITE `Customer Name` ON TABLE customers TYPE option<string>;",
+                "DEFINE FIELD OVERWRITE meta ON TABLE customers FLEXIBLE TYPE object;",
+            ]
+        );
+    }
+}
@@ -0,0 +1,397 @@
+//! Deterministic join of the rows returned by two sub-agents
+//!
+//! Matching records across datasets (e.g. feature requests to the customers
+//! that filed them) is error prone when left to the reduce model. A join
+//! stage matches the rows in code and hands reduce a single merged table.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// How join keys are compared
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum KeyMatch {
+    /// Keys must be identical
+    Exact,
+    /// Keys are compared after lowercasing, stripping punctuation and
+    /// company suffixes such as "Inc" or "LLC"
+    Normalized,
+    /// Normalized keys are compared by edit-distance similarity (0.0 - 1.0)
+    /// and the most similar row at or above the threshold is matched
+    Fuzzy { threshold: f64 },
+}
+
+/// Which left rows are kept in the joined table
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JoinKind {
+    /// Keep only left rows with a matching right row
+    #[default]
+    Inner,
+    /// Keep every left row, leaving right columns empty when unmatched
+    Left,
+}
+
+/// Join of the rows returned by two sub-agents
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JoinSpec {
+    /// Name of the sub-agent providing the left rows
+    pub left: String,
+    /// Field of the left rows to join on
+    pub left_key: String,
+    /// Name of the sub-agent providing the right rows
+    pub right: String,
+    /// Field of the right rows to join on
+    pub right_key: String,
+    pub matching: KeyMatch,
+    #[serde(default)]
+    pub kind: JoinKind,
+}
+
+impl JoinSpec {
+    /// Create an inner join with exact key matching
+    pub fn new(left: &str, left_key: &str, right: &str, right_key: &str) -> Self {
+        Self {
+            left: left.to_string(),
+            left_key: left_key.to_string(),
+            right: right.to_string(),
+            right_key: right_key.to_string(),
+            matching: KeyMatch::Exact,
+            kind: JoinKind::Inner,
+        }
+    }
+
+    /// Set how join keys are compared
+    pub fn matching(mut self, matching: KeyMatch) -> Self {
+        self.matching = matching;
+        self
+    }
+
+    /// Set which left rows are kept
+    pub fn kind(mut self, kind: JoinKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Join left and right rows
+    ///
+    /// Columns of the joined table are prefixed with the name of the
+    /// sub-agent they come from, e.g. `customers.arr`.
+    pub fn join(&self, left_rows: &[Value], right_rows: &[Value]) -> JoinedTable {
+        let left_columns = columns(left_rows);
+        let right_columns = columns(right_rows);
+
+        let right_keys: Vec<Option<String>> = right_rows
+            .iter()
+            .map(|row| key_of(row, &self.right_key).map(|key| self.prepare_key(&key)))
+            .collect();
+
+        let mut rows = Vec::new();
+        let mut unmatched = 0;
+
+        for left_row in left_rows {
+            let matched = key_of(left_row, &self.left_key)
+                .map(|key| self.prepare_key(&key))
+                .and_then(|key| self.find_match(&key, &right_keys))
+                .map(|index| &right_rows[index]);
+
+            if matched.is_none() {
+                unmatched += 1;
+                if self.kind == JoinKind::Inner {
+                    continue;
+                }
+            }
+
+            let mut row = BTreeMap::new();
+            for column in &left_columns {
+                row.insert(
+                    format!("{}.{column}", self.left),
+                    left_row.get(column).cloned().unwrap_or(Value::Null),
+                );
+            }
+            for column in &right_columns {
+                row.insert(
+                    format!("{}.{column}", self.right),
+                    matched
+                        .and_then(|right_row| right_row.get(column))
+                        .cloned()
+                        .unwrap_or(Value::Null),
+                );
+            }
+            rows.push(row);
+        }
+
+        let columns = left_columns
+            .iter()
+            .map(|column| format!("{}.{column}", self.left))
+            .chain(
+                right_columns
+                    .iter()
+                    .map(|column| format!("{}.{column}", self.right)),
+            )
+            .collect();
+
+        JoinedTable {
+            columns,
+            rows,
+            unmatched,
+        }
+    }
+
+    fn prepare_key(&self, key: &str) -> String {
+        match self.matching {
+            KeyMatch::Exact => key.to_string(),
+            KeyMatch::Normalized | KeyMatch::Fuzzy { .. } => normalize_key(key),
+        }
+    }
+
+    fn find_match(&self, key: &str, right_keys: &[Option<String>]) -> Option<usize> {
+        match self.matching {
+            KeyMatch::Exact | KeyMatch::Normalized => right_keys
+                .iter()
+                .position(|right_key| right_key.as_deref() == Some(key)),
+            KeyMatch::Fuzzy { threshold } => right_keys
+                .iter()
+                .enumerate()
+                .filter_map(|(index, right_key)| {
+                    right_key
+                        .as_deref()
+                        .map(|right_key| (index, similarity(key, right_key)))
+                })
+                .filter(|(_, score)| *score >= threshold)
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(index, _)| index),
+        }
+    }
+}
+
+/// Result of a join
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct JoinedTable {
+    pub columns: Vec<String>,
+    pub rows: Vec<BTreeMap<String, Value>>,
+    /// Number of left rows without a matching right row
+    pub unmatched: usize,
+}
+
+impl JoinedTable {
+    /// Render the table as Markdown
+    pub fn to_markdown(&self) -> String {
+        let mut output = format!("| {} |\n", self.columns.join(" | "));
+        output.push_str(&format!("|{}\n", " --- |".repeat(self.columns.len())));
+
+        for row in &self.rows {
+            let cells: Vec<String> = self
+                .columns
+                .iter()
+                .map(|column| format_cell(row.get(column).unwrap_or(&Value::Null)))
+                .collect();
+            output.push_str(&format!("| {} |\n", cells.join(" | ")));
+        }
+
+        output
+    }
+}
+
+/// Union of the fields of all rows, keeping the order of first appearance
+pub(crate) fn columns(rows: &[Value]) -> Vec<String> {
+    let mut columns: Vec<String> = Vec::new();
+
+    for row in rows {
+        if let Value::Object(obj) = row {
+            for key in obj.keys() {
+                if !columns.contains(key) {
+                    columns.push(key.clone());
+                }
+            }
+        }
+    }
+
+    columns
+}
+
+fn key_of(row: &Value, field: &str) -> Option<String> {
+    match row.get(field)? {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn format_cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.replace('|', "\\|").replace('\n', " "),
+        other => other.to_string().replace('|', "\\|"),
+    }
+}
+
+/// Company suffixes ignored when comparing normalized keys
+const COMPANY_SUFFIXES: [&str; 9] = [
+    "inc",
+    "llc",
+    "ltd",
+    "corp",
+    "corporation",
+    "co",
+    "company",
+    "gmbh",
+    "plc",
+];
+
+/// Lowercase, strip punctuation and trailing company suffixes
+pub fn normalize_key(key: &str) -> String {
+    let cleaned: String = key
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect();
+
+    let mut words: Vec<&str> = cleaned.split_whitespace().collect();
+    while words.len() > 1 && COMPANY_SUFFIXES.contains(words.last().unwrap()) {
+        words.pop();
+    }
+
+    words.join(" ")
+}
+
+/// Similarity of two strings based on Levenshtein distance, from 0.0 to 1.0
+fn similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let max_len = a.len().max(b.len());
+
+    if max_len == 0 {
+        return 1.0;
+    }
+
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    1.0 - previous[b.len()] as f64 / max_len as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn feature_requests() -> Vec<Value> {
+        vec![
+            json!({ "id": "feature_requests:1", "customer_identifier": "Acme Corp", "text": "Slack integration" }),
+            json!({ "id": "feature_requests:2", "customer_identifier": "beta, LLC", "text": "Mobile app" }),
+            json!({ "id": "feature_requests:3", "customer_identifier": "Zeta Grup", "text": "Roles" }),
+        ]
+    }
+
+    fn customers() -> Vec<Value> {
+        vec![
+            json!({ "id": "customers:a", "name": "Acme Corp", "arr": 150000 }),
+            json!({ "id": "customers:b", "name": "Beta LLC", "arr": 120000 }),
+            json!({ "id": "customers:z", "name": "Zeta Group", "arr": 30000 }),
+        ]
+    }
+
+    #[test]
+    fn test_exact_join() {
+        let spec = JoinSpec::new(
+            "feature_requests",
+            "customer_identifier",
+            "customers",
+            "name",
+        );
+        let table = spec.join(&feature_requests(), &customers());
+
+        assert_eq!(table.rows.len(), 1);
+        assert_eq!(table.unmatched, 2);
+        assert_eq!(table.rows[0]["customers.arr"], json!(150000));
+        assert_eq!(table.columns.len(), 6);
+        assert!(table.columns.contains(&"customers.name".to_string()));
+    }
+
+    #[test]
+    fn test_normalized_join() {
+        let spec = JoinSpec::new(
+            "feature_requests",
+            "customer_identifier",
+            "customers",
+            "name",
+        )
+        .matching(KeyMatch::Normalized);
+        let table = spec.join(&feature_requests(), &customers());
+
+        assert_eq!(table.rows.len(), 2);
+        assert_eq!(table.rows[1]["customers.id"], json!("customers:b"));
+    }
+
+    #[test]
+    fn test_fuzzy_left_join() {
+        let spec = JoinSpec::new(
+            "feature_requests",
+            "customer_identifier",
+            "customers",
+            "name",
+        )
+        .matching(KeyMatch::Fuzzy { threshold: 0.8 })
+        .kind(JoinKind::Left);
+        let table = spec.join(&feature_requests(), &customers());
+
+        assert_eq!(table.rows.len(), 3);
+        assert_eq!(table.unmatched, 0);
+        assert_eq!(table.rows[2]["customers.id"], json!("customers:z"));
+    }
+
+    #[test]
+    fn test_left_join_keeps_unmatched_rows() {
+        let spec = JoinSpec::new(
+            "feature_requests",
+            "customer_identifier",
+            "customers",
+            "name",
+        )
+        .kind(JoinKind::Left);
+        let table = spec.join(&feature_requests(), &customers());
+
+        assert_eq!(table.rows.len(), 3);
+        assert_eq!(table.unmatched, 2);
+        assert_eq!(table.rows[1]["customers.arr"], Value::Null);
+    }
+
+    #[test]
+    fn test_normalize_key() {
+        assert_eq!(normalize_key("  Acme  Corp."), "acme");
+        assert_eq!(normalize_key("Beta, LLC"), "beta");
+        assert_eq!(normalize_key("Co"), "co");
+    }
+
+    #[test]
+    fn test_to_markdown() {
+        let spec = JoinSpec::new(
+            "feature_requests",
+            "customer_identifier",
+            "customers",
+            "name",
+        );
+        let markdown = spec
+            .join(&feature_requests()[..1], &customers()[..1])
+            .to_markdown();
+
+        let lines: Vec<&str> = markdown.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1], "| --- | --- | --- | --- | --- | --- |");
+        assert!(lines[2].contains("| Acme Corp |"));
+        assert!(lines[2].contains("| 150000 |"));
+    }
+}
@@ -1,10 +1,48 @@
 pub mod agents;
+pub mod citations;
+pub mod cluster;
 pub mod config;
+pub mod demo;
+pub mod eval;
+pub mod import;
+pub mod join;
+pub mod mcp;
+pub mod pipeline;
+pub mod prompts;
+pub mod repl;
+pub mod runs;
+pub mod server;
 pub mod surreal;
+pub mod telemetry;
+#[cfg(test)]
+mod testing;
+pub mod tokens;
+pub mod usage;
 
-pub use config::{Config, SurrealConfig};
+pub use agents::{
+    AgentError, RecordId, SubAgentResult,
+    model::{Llm, Model},
+};
+pub use citations::{CitationReport, Provenance};
+pub use cluster::{ClusterError, ClusterOptions, ClusterReport};
+pub use config::{Config, Provider, RunStoreConfig, SurrealConfig};
+pub use eval::{EvalError, EvalReport, Suite};
+pub use import::{ImportError, ImportOptions, ImportReport};
+pub use join::{JoinKind, JoinSpec, JoinedTable, KeyMatch};
+pub use pipeline::{
+    Conversation, ExportError, ExportFormat, Pipeline, PipelineEvent, PipelineResult,
+    SubAgentConfig, Turn,
+};
+pub use prompts::{Prompts, TemplateError};
+pub use runs::{RunStore, RunStoreError, RunSummary, RunTranscript};
 pub use surreal::{
-    SurrealDbConfig, SurrealError, SurrealSchemaTool, SurrealSelectTool,
+    Embedder, QueryLedger, SearchField, SurrealDbConfig, SurrealError, SurrealSchemaTool,
+    SurrealSearchTool, SurrealSelectTool, SurrealVectorSearchTool,
     schema::{SurrealSchemaArgs, TableColumn, TableSchema},
+    search::SurrealSearchArgs,
     select::SurrealSelectArgs,
+    vector::{EmbedReport, SurrealVectorSearchArgs},
 };
+pub use telemetry::{LogFormat, TelemetryError, TelemetryGuard};
+pub use tokens::Tokenizer;
+pub use usage::{PriceTable, UsageReport};
@@ -1,16 +1,246 @@
-pub mod agents;
-pub mod config;
-pub mod surreal;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::pin::pin;
 
-use std::collections::BTreeMap;
+use clap::{Parser, Subcommand};
+use futures::StreamExt;
+use rig::{
+    client::{CompletionClient, EmbeddingsClient},
+    providers::{openai, xai},
+};
+use rig_tutorial::{
+    ClusterError, ClusterOptions, Config, ExportFormat, ImportOptions, JoinSpec, KeyMatch, Llm,
+    Model, Pipeline, PipelineEvent, PipelineResult, PriceTable, Prompts, Provider, RunStore,
+    RunStoreConfig, SubAgentConfig, Suite, SurrealDbConfig, SurrealSchemaTool, SurrealSelectTool,
+    Tokenizer,
+    agents::query::MAX_TURNS,
+    cluster, demo, eval,
+    import::{self, DEFAULT_BATCH_SIZE, Format, Schema},
+    mcp::{self, McpTransport},
+    repl::{self, ReplCommand, Session},
+    server,
+    surreal::{self, Embedder, SearchField},
+    telemetry,
+};
+use tokio::io::{AsyncBufReadExt, BufReader};
 
-use config::Config;
-use rig::providers::xai;
-use surreal::SurrealSelectTool;
+/// Question used when `ask` is given none
+const DEFAULT_QUESTION: &str = r#"
+        Which feature requests should I prioritize to satisfy my highest paying customers?
+        Analyze the tone of customer's message feature requests to determine their urgency.
+        Combine the urgency of the FR with the value of each customer to prioritize feature requests.
+        "#;
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Answer questions about SurrealDB tables with a team of agents"
+)]
+struct Cli {
+    /// Env file to load settings from; `.env` still fills in anything it leaves unset
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// xai or openai; overrides LLM_PROVIDER
+    #[arg(long, global = true)]
+    provider: Option<Provider>,
+    /// Completion model; overrides LLM_MODEL
+    #[arg(long, global = true)]
+    model: Option<String>,
+    /// Tool-calling turns each sub-agent may take before giving up
+    #[arg(long, global = true, default_value_t = MAX_TURNS)]
+    max_turns: usize,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(flatten)]
+    Pipeline(PipelineCommand),
+    /// List the tables of the database
+    Tables,
+    /// Print the statements defining a table, its fields and indexes
+    Schema { table: String },
+    /// Run a SurrealQL query and print the result of its first statement as JSON
+    Query {
+        query: String,
+        /// Allow statements other than SELECT
+        #[arg(long)]
+        write: bool,
+    },
+    /// Load a JSONL, JSON, CSV or Parquet file into a table
+    Import(ImportArgs),
+    /// Define full-text search indexes for the `surreal_search` tool
+    SearchIndex {
+        /// Text fields to index, as `table.field`; defaults to SEARCH_FIELDS
+        fields: Vec<SearchField>,
+    },
+    /// Compute and store embeddings of text fields for `surreal_vector_search`,
+    /// for the rows whose text changed since the last run (see EMBEDDING_MODEL)
+    Embed {
+        /// Text fields to embed, as `table.field`
+        #[arg(required = true)]
+        fields: Vec<SearchField>,
+    },
+    /// Inspect stored run transcripts (see RUN_STORE)
+    #[command(subcommand)]
+    Runs(RunsCommand),
+}
+
+/// Commands running the pipeline, and so needing a model
+#[derive(Subcommand)]
+enum PipelineCommand {
+    /// Answer a question, streaming the answer to stdout (the default)
+    Ask {
+        /// Defaults to the feature request prioritization example
+        question: Option<String>,
+        #[command(flatten)]
+        report: ReportArgs,
+    },
+    /// Answer a question about the bundled demo dataset, loaded into an
+    /// embedded database; needs no SurrealDB settings
+    Demo {
+        /// Defaults to the feature request prioritization example
+        question: Option<String>,
+        #[command(flatten)]
+        report: ReportArgs,
+    },
+    /// Answer questions interactively; follow-up questions see the earlier
+    /// questions and answers
+    Repl {
+        /// Load the bundled demo dataset into an embedded database first
+        #[arg(long)]
+        demo: bool,
+    },
+    /// Serve the pipeline over HTTP
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:3000")]
+        addr: SocketAddr,
+        /// Listen on a non-loopback address; the API has no authentication
+        #[arg(long)]
+        allow_remote: bool,
+        /// Load the bundled demo dataset into an embedded database first
+        #[arg(long)]
+        demo: bool,
+    },
+    /// Serve the SurrealDB tools and the pipeline to MCP clients, over stdio
+    /// unless `--sse` is given
+    Mcp {
+        /// Serve over SSE on this address instead of stdio
+        #[arg(long)]
+        sse: Option<SocketAddr>,
+        /// Serve SSE on a non-loopback address; the server has no authentication
+        #[arg(long)]
+        allow_remote: bool,
+        /// Load the bundled demo dataset into an embedded database first
+        #[arg(long)]
+        demo: bool,
+    },
+    /// Run an evaluation suite through the pipeline and write its report
+    Eval {
+        /// YAML or JSON file of evaluation cases
+        suite: PathBuf,
+        /// Directory the JSON report is written to
+        #[arg(long, default_value = "eval-reports")]
+        output: PathBuf,
+        /// Grade rubric criteria with an LLM judge
+        #[arg(long)]
+        judge: bool,
+    },
+    /// Group the rows of a text field with the same meaning into clusters
+    /// labelled by the model, stored in the `cluster` table (see EMBEDDING_MODEL)
+    Cluster {
+        /// Text field to cluster, as `table.field`
+        field: SearchField,
+        /// Cosine similarity a row needs to join a cluster, between 0 and 1
+        #[arg(long, default_value_t = cluster::DEFAULT_THRESHOLD)]
+        threshold: f64,
+    },
+}
+
+#[derive(Subcommand)]
+enum RunsCommand {
+    /// List the most recent runs, newest first
+    List {
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Print a report of a stored run, as Markdown unless another format is given
+    Show {
+        id: String,
+        #[command(flatten)]
+        report: ReportArgs,
+    },
+}
+
+#[derive(clap::Args, Default)]
+struct ReportArgs {
+    /// Write a report of the run to this file instead of streaming the answer
+    #[arg(long)]
+    output: Option<PathBuf>,
+    /// json, markdown or csv; defaults to the extension of `--output`. Without
+    /// `--output` the report is printed to stdout
+    #[arg(long)]
+    format: Option<ExportFormat>,
+}
+
+#[derive(clap::Args)]
+struct ImportArgs {
+    /// File to load
+    file: PathBuf,
+    /// Table to load the rows into
+    #[arg(long)]
+    table: String,
+    /// jsonl, json, csv or parquet; defaults to the extension of the file
+    #[arg(long)]
+    format: Option<Format>,
+    /// Field types, e.g. `name:string,arr:int`; inferred from the rows by default
+    #[arg(long)]
+    schema: Option<Schema>,
+    /// Define the table and its fields before loading the rows
+    #[arg(long)]
+    define: bool,
+    /// Upsert each row as the record keyed by this field, instead of inserting it
+    #[arg(long)]
+    key: Option<String>,
+    /// Rows written per query
+    #[arg(long, default_value_t = DEFAULT_BATCH_SIZE)]
+    batch_size: usize,
+    /// Report what would be loaded without writing anything
+    #[arg(long)]
+    dry_run: bool,
+}
 
 #[tokio::main]
 async fn main() {
-    let config = match Config::from_env() {
+    let cli = Cli::parse();
+
+    if let Some(path) = &cli.config
+        && let Err(e) = dotenv::from_path(path)
+    {
+        eprintln!("Error loading configuration file {}: {}", path.display(), e);
+        std::process::exit(1);
+    }
+
+    let command = cli
+        .command
+        .unwrap_or(Command::Pipeline(PipelineCommand::Ask {
+            question: None,
+            report: ReportArgs::default(),
+        }));
+
+    let config = match &command {
+        Command::Pipeline(
+            PipelineCommand::Demo { .. }
+            | PipelineCommand::Repl { demo: true }
+            | PipelineCommand::Serve { demo: true, .. }
+            | PipelineCommand::Mcp { demo: true, .. },
+        ) => Config::from_env_with(demo::surreal_config()),
+        _ => Config::from_env(),
+    };
+    let config = match config {
         Ok(config) => config,
         Err(e) => {
             eprintln!("Error loading configuration: {}", e);
@@ -20,48 +250,660 @@ async fn main() {
         }
     };
 
-    let xai_client = xai::Client::new(&config.xai_api_key);
+    let _telemetry = match telemetry::init(config.log_format, config.otlp_endpoint.as_deref()) {
+        Ok(guard) => guard,
+        Err(e) => {
+            eprintln!("Error setting up logging: {}", e);
+            std::process::exit(1);
+        }
+    };
 
-    let question = r#"
-        Which feature requests should I prioritize to satisfy my highest paying customers?
-        Analyze the tone of customer's message feature requests to determine their urgency.
-        Combine the urgency of the FR with the value of each customer to prioritize feature requests.
-        "#;
+    let surreal_db_config = SurrealDbConfig::from(&config.surreal_config);
 
-    let mut agents = BTreeMap::new();
+    let run_store = config.run_store.clone().map(|store| match store {
+        RunStoreConfig::Surreal => RunStore::Surreal(surreal_db_config.clone()),
+        RunStoreConfig::Directory(dir) => RunStore::Files(dir),
+    });
 
-    agents.insert("feature_requests", "This agent specializes in finding incoming support tickets or feedback logs with feature requests. Do not ask it about customer data other than identifiers.");
-    agents.insert("customers", "This agent specializes in finding customers and their Annual Recurring Revenue (ARR) in USD. Do not ask it about feature requests.");
+    // Only the pipeline commands need a model
+    let command = match command {
+        Command::Pipeline(command) => command,
+        Command::Tables => return list_tables(&surreal_db_config).await,
+        Command::Schema { table } => return show_schema(&surreal_db_config, &table).await,
+        Command::Query { query, write } => {
+            return run_query(&surreal_db_config, &query, write).await;
+        }
+        Command::Import(args) => return run_import(&surreal_db_config, args).await,
+        Command::SearchIndex { fields } => {
+            let fields = if fields.is_empty() {
+                config.search_fields.clone()
+            } else {
+                fields
+            };
+            return define_search_indexes(&surreal_db_config, &fields).await;
+        }
+        Command::Embed { fields } => {
+            let Some(model) = embedding_model(&config) else {
+                tracing::error!("no embedding model configured; set EMBEDDING_MODEL");
+                std::process::exit(1);
+            };
+            return embed_fields(
+                &surreal_db_config,
+                &Embedder::new(model.model.clone(), model),
+                &fields,
+            )
+            .await;
+        }
+        Command::Runs(command) => return runs(run_store, command).await,
+    };
 
-    let sq = crate::agents::map::map(&xai_client, question, &agents).await;
+    let provider = cli.provider.unwrap_or(config.provider);
+    let model = cli
+        .model
+        .or_else(|| config.model.clone())
+        .unwrap_or_else(|| provider.default_model().to_string());
 
-    println!("sub questions: {sq:?}");
+    let api_key = match config.api_key(provider) {
+        Ok(api_key) => api_key.to_string(),
+        Err(e) => {
+            tracing::error!(%provider, error = %e, "no API key for provider");
+            std::process::exit(1);
+        }
+    };
 
-    let fr_resp = crate::agents::query::question(
-        &xai_client,
-        &sq.feature_requests,
-        "feature_requests",
-        "This table captures incoming support tickets or feedback logs with feature requests.",
-        &config.surreal_config,
-    )
-    .await;
-    let cust_resp = crate::agents::query::question(
-        &xai_client,
-        &sq.customers,
-        "customers",
-        "This table lists customers and their Annual Recurring Revenue (ARR) in USD.",
-        &config.surreal_config,
-    )
-    .await;
+    let llm_config = LlmConfig {
+        provider,
+        max_turns: cli.max_turns,
+        run_store,
+    };
 
-    println!("fr_resp: {fr_resp}");
-    println!("cust_resp: {cust_resp}");
+    match provider {
+        Provider::Xai => {
+            let client = xai::Client::new(&api_key);
+            let llm = Llm::new(client.completion_model(&model), &model);
+            run_pipeline(llm, config, llm_config, command).await
+        }
+        Provider::Openai => {
+            let client = match &config.openai_base_url {
+                Some(url) => openai::Client::from_url(&api_key, url),
+                None => openai::Client::new(&api_key),
+            };
+            let llm = Llm::new(client.completion_model(&model).completions_api(), &model);
+            run_pipeline(llm, config, llm_config, command).await
+        }
+    }
+}
 
-    let mut data: Vec<String> = Vec::new();
-    data.push(fr_resp);
-    data.push(cust_resp);
+/// Settings of a pipeline run taken from the command line
+struct LlmConfig {
+    provider: Provider,
+    max_turns: usize,
+    run_store: Option<RunStore>,
+}
+
+/// Build the pipeline around `llm` and run `command` with it
+async fn run_pipeline<M: Model>(
+    llm: Llm<M>,
+    config: Config,
+    llm_config: LlmConfig,
+    command: PipelineCommand,
+) {
+    let prompts = match &config.prompts_dir {
+        Some(dir) => match Prompts::from_dir(dir) {
+            Ok(prompts) => prompts,
+            Err(e) => {
+                tracing::error!(dir = %dir.display(), error = %e, "failed to load prompts");
+                std::process::exit(1);
+            }
+        },
+        None => Prompts::embedded(),
+    };
+
+    let prices = match &config.prices_file {
+        Some(file) => match std::fs::read_to_string(file)
+            .map_err(|e| e.to_string())
+            .and_then(|json| PriceTable::from_json(&json).map_err(|e| e.to_string()))
+        {
+            Ok(prices) => prices,
+            Err(e) => {
+                tracing::error!(file = %file.display(), error = %e, "failed to load price table");
+                std::process::exit(1);
+            }
+        },
+        None => PriceTable::default(),
+    };
+
+    let sub_agents: Vec<SubAgentConfig> = match &config.sub_agents_file {
+        Some(file) => match std::fs::read_to_string(file)
+            .map_err(|e| e.to_string())
+            .and_then(|yaml| serde_yaml::from_str(&yaml).map_err(|e| e.to_string()))
+        {
+            Ok(sub_agents) => sub_agents,
+            Err(e) => {
+                tracing::error!(file = %file.display(), error = %e, "failed to load sub-agents");
+                std::process::exit(1);
+            }
+        },
+        None => Vec::new(),
+    };
+    for sub_agent in &sub_agents {
+        if let Err(e) = sub_agent.validate() {
+            tracing::error!(sub_agent = %sub_agent.name, error = %e, "invalid sub-agent");
+            std::process::exit(1);
+        }
+    }
+
+    let embeddings = embedding_model(&config);
+    let surreal_db_config = SurrealDbConfig::from(&config.surreal_config);
+
+    let mut pipeline = Pipeline::with_llm(llm, config.surreal_config)
+        .prompts(prompts)
+        .prices(prices)
+        .tokenizer(Tokenizer::for_provider(&llm_config.provider.to_string()))
+        .max_turns(llm_config.max_turns)
+        .sub_agent(SubAgentConfig {
+            name: "feature_requests".to_string(),
+            description: "This agent specializes in finding incoming support tickets or feedback logs with feature requests. Do not ask it about customer data other than identifiers.".to_string(),
+            table: "feature_requests".to_string(),
+            table_context: "This table captures incoming support tickets or feedback logs with feature requests.".to_string(),
+            mcp: None,
+            file: None,
+        })
+        .sub_agent(SubAgentConfig {
+            name: "customers".to_string(),
+            description: "This agent specializes in finding customers and their Annual Recurring Revenue (ARR) in USD. Do not ask it about feature requests.".to_string(),
+            table: "customers".to_string(),
+            table_context: "This table lists customers and their Annual Recurring Revenue (ARR) in USD.".to_string(),
+            mcp: None,
+            file: None,
+        })
+        .join(
+            JoinSpec::new("feature_requests", "customer_identifier", "customers", "name")
+                .matching(KeyMatch::Fuzzy { threshold: 0.85 }),
+        )
+        .token_budget(100_000);
+
+    for sub_agent in sub_agents {
+        pipeline = pipeline.sub_agent(sub_agent);
+    }
+    if let Some(store) = llm_config.run_store {
+        pipeline = pipeline.store(store);
+    }
+    if let Some(model) = embeddings {
+        let name = model.model.clone();
+        pipeline = pipeline.embeddings(&name, model);
+    }
+
+    match command {
+        PipelineCommand::Ask { question, report } => {
+            ask(
+                &pipeline,
+                question.as_deref().unwrap_or(DEFAULT_QUESTION),
+                &report,
+            )
+            .await
+        }
+        PipelineCommand::Demo { question, report } => {
+            if let Err(e) = demo::load(&surreal_db_config).await {
+                tracing::error!(error = %e, "failed to load demo dataset");
+                std::process::exit(1);
+            }
+            ask(
+                &pipeline,
+                question.as_deref().unwrap_or(DEFAULT_QUESTION),
+                &report,
+            )
+            .await
+        }
+        PipelineCommand::Repl { demo } => {
+            if demo && let Err(e) = demo::load(&surreal_db_config).await {
+                tracing::error!(error = %e, "failed to load demo dataset");
+                std::process::exit(1);
+            }
+            run_repl(&pipeline).await
+        }
+        PipelineCommand::Serve {
+            addr,
+            allow_remote,
+            demo,
+        } => {
+            refuse_remote(addr, allow_remote);
+            if demo && let Err(e) = demo::load(&surreal_db_config).await {
+                tracing::error!(error = %e, "failed to load demo dataset");
+                std::process::exit(1);
+            }
+            if let Err(e) = server::serve(pipeline, addr).await {
+                tracing::error!(error = %e, %addr, "server failed");
+                std::process::exit(1);
+            }
+        }
+        PipelineCommand::Mcp {
+            sse,
+            allow_remote,
+            demo,
+        } => {
+            if let Some(addr) = sse {
+                refuse_remote(addr, allow_remote);
+            }
+            if demo && let Err(e) = demo::load(&surreal_db_config).await {
+                tracing::error!(error = %e, "failed to load demo dataset");
+                std::process::exit(1);
+            }
+            let transport = sse.map_or(McpTransport::Stdio, McpTransport::Sse);
+            if let Err(e) = mcp::serve(pipeline, transport).await {
+                tracing::error!(error = %e, "MCP server failed");
+                std::process::exit(1);
+            }
+        }
+        PipelineCommand::Eval {
+            suite,
+            output,
+            judge,
+        } => run_eval(&pipeline, &suite, &output, judge).await,
+        PipelineCommand::Cluster { field, threshold } => {
+            run_cluster(&pipeline, ClusterOptions::new(field).threshold(threshold)).await
+        }
+    }
+}
 
-    let answer = crate::agents::reduce::reduce(&xai_client, question, data).await;
+/// Answer `question`, streaming the answer to stdout and progress to the log
+///
+/// When a report is requested, the answer is not streamed; the report of
+/// the finished run is written instead.
+async fn ask<M: Model>(pipeline: &Pipeline<M>, question: &str, report: &ReportArgs) {
+    let format = report_format(report);
+
+    let Some(result) = follow(pipeline.stream(question), format.is_none()).await else {
+        std::process::exit(1);
+    };
+
+    if let Some(format) = format {
+        write_report(&result, format, report.output.as_deref());
+    }
+}
+
+/// Answer questions read from stdin, each following up on the earlier ones
+async fn run_repl<M: Model>(pipeline: &Pipeline<M>) {
+    let mut session = Session::new();
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+
+    eprintln!("Ask a question, or type :help for commands.");
+
+    loop {
+        eprint!("> ");
+        std::io::stderr().flush().ok();
+
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(e) => {
+                tracing::error!(error = %e, "failed to read input");
+                std::process::exit(1);
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let output = match line.parse::<ReplCommand>() {
+            Ok(ReplCommand::Ask(question)) => {
+                let events = pipeline.stream_with_history(&question, session.conversation());
+                if let Some(result) = follow(events, true).await {
+                    session.record(result);
+                }
+                continue;
+            }
+            Ok(ReplCommand::Plan) => session.plan(),
+            Ok(ReplCommand::Sql(agent)) => session.sql(agent.as_deref()),
+            Ok(ReplCommand::Rows(agent)) => session.rows(agent.as_deref()),
+            Ok(ReplCommand::Agent(name)) => session.agent(&name),
+            Ok(ReplCommand::Save(path)) => session
+                .save(&path)
+                .map(|()| format!("Saved {}", path.display())),
+            Ok(ReplCommand::History) => Ok(session.history()),
+            Ok(ReplCommand::Clear) => {
+                session.clear();
+                Ok("Forgot the conversation.".to_string())
+            }
+            Ok(ReplCommand::Help) => Ok(repl::HELP.to_string()),
+            Ok(ReplCommand::Quit) => break,
+            Err(e) => Err(e),
+        };
+
+        match output {
+            Ok(output) => println!("{output}"),
+            Err(e) => eprintln!("{e}"),
+        }
+    }
+}
+
+/// Log the progress of a run, printing its answer as it is streamed when
+/// `print_answer` is set, and return its result unless it failed
+async fn follow(
+    events: impl futures::Stream<Item = PipelineEvent>,
+    print_answer: bool,
+) -> Option<PipelineResult> {
+    let mut events = pin!(events);
+
+    while let Some(event) = events.next().await {
+        match event {
+            PipelineEvent::PlanProduced { sub_questions } => {
+                for (agent, sub_question) in &sub_questions {
+                    tracing::info!(agent, sub_question, "planned sub-question");
+                }
+            }
+            PipelineEvent::SubAgentDone { result } => tracing::info!(
+                agent = result.agent,
+                records = result.rows_used.len(),
+                "sub-agent done"
+            ),
+            PipelineEvent::AnswerDelta { text } if print_answer => {
+                print!("{text}");
+                std::io::stdout().flush().ok();
+            }
+            PipelineEvent::Done { result } => {
+                if print_answer {
+                    println!();
+                }
+                tracing::info!(prompt_versions = ?result.prompt_versions, "run finished");
+                eprint!("{}", result.usage);
+
+                if let Some(run_id) = &result.run_id {
+                    tracing::info!(run_id, "stored run transcript");
+                }
+
+                let unverified = result.unverified_citations();
+                if !unverified.is_empty() {
+                    tracing::warn!(
+                        record_ids = unverified.into_iter().collect::<Vec<_>>().join(", "),
+                        "cited record IDs never returned by a query"
+                    );
+                }
+
+                return Some(*result);
+            }
+            PipelineEvent::Failed { error } => {
+                if print_answer {
+                    println!();
+                }
+                tracing::error!(error, "run failed");
+                return None;
+            }
+            // Queries and stage boundaries are logged by their tracing spans
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Format of the requested report, or `None` if none was requested
+fn report_format(report: &ReportArgs) -> Option<ExportFormat> {
+    match (report.format, &report.output) {
+        (Some(format), _) => Some(format),
+        (None, Some(output)) => match ExportFormat::from_path(output) {
+            Ok(format) => Some(format),
+            Err(e) => {
+                tracing::error!(output = %output.display(), error = %e, "unknown report format");
+                std::process::exit(1);
+            }
+        },
+        (None, None) => None,
+    }
+}
+
+/// Write the report of a run to `output`, or to stdout
+fn write_report(result: &PipelineResult, format: ExportFormat, output: Option<&Path>) {
+    let report = match result.export(format) {
+        Ok(report) => report,
+        Err(e) => {
+            tracing::error!(%format, error = %e, "failed to export result");
+            std::process::exit(1);
+        }
+    };
+
+    match output {
+        Some(path) => match std::fs::write(path, report) {
+            Ok(()) => tracing::info!(report = %path.display(), "wrote report"),
+            Err(e) => {
+                tracing::error!(report = %path.display(), error = %e, "failed to write report");
+                std::process::exit(1);
+            }
+        },
+        None => print!("{report}"),
+    }
+}
+
+/// Run the suite at `path`, print its scores and write its report to `output`
+async fn run_eval<M: Model>(pipeline: &Pipeline<M>, path: &Path, output: &Path, judge: bool) {
+    let suite = match Suite::from_file(path) {
+        Ok(suite) => suite,
+        Err(e) => {
+            tracing::error!(suite = %path.display(), error = %e, "failed to load evaluation suite");
+            std::process::exit(1);
+        }
+    };
+
+    let report = match eval::run_suite(pipeline, &suite, judge).await {
+        Ok(report) => report,
+        Err(e) => {
+            tracing::error!(error = %e, "evaluation failed");
+            std::process::exit(1);
+        }
+    };
+
+    print!("{report}");
+
+    match report.write(output) {
+        Ok(path) => tracing::info!(report = %path.display(), "wrote evaluation report"),
+        Err(e) => {
+            tracing::error!(dir = %output.display(), error = %e, "failed to write evaluation report");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Cluster the rows of a field and print the clusters
+async fn run_cluster<M: Model>(pipeline: &Pipeline<M>, options: ClusterOptions) {
+    match cluster::cluster_field(pipeline, &options).await {
+        Ok(report) => print!("{report}"),
+        Err(ClusterError::NoEmbedder) => {
+            tracing::error!("no embedding model configured; set EMBEDDING_MODEL");
+            std::process::exit(1);
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "clustering failed");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Load a file into a table and print what was loaded
+async fn run_import(config: &SurrealDbConfig, args: ImportArgs) {
+    let mut options = ImportOptions::new(args.table)
+        .define(args.define)
+        .batch_size(args.batch_size)
+        .dry_run(args.dry_run);
+    if let Some(format) = args.format {
+        options = options.format(format);
+    }
+    if let Some(schema) = args.schema {
+        options = options.schema(schema);
+    }
+    if let Some(key) = args.key {
+        options = options.upsert_by(key);
+    }
+
+    match import::import_file(config, &args.file, &options).await {
+        Ok(report) => print!("{report}"),
+        Err(e) => {
+            tracing::error!(file = %args.file.display(), error = %e, "import failed");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Embedding model configured with EMBEDDING_MODEL, if any
+fn embedding_model(config: &Config) -> Option<openai::EmbeddingModel> {
+    let model = config.embedding_model.as_ref()?;
+
+    let api_key = match config.api_key(Provider::Openai) {
+        Ok(api_key) => api_key,
+        Err(e) => {
+            tracing::error!(error = %e, "no API key for the embedding model");
+            std::process::exit(1);
+        }
+    };
+    let client = match &config.openai_base_url {
+        Some(url) => openai::Client::from_url(api_key, url),
+        None => openai::Client::new(api_key),
+    };
+
+    Some(client.embedding_model(model))
+}
+
+/// Embed the changed rows of every field
+async fn embed_fields(config: &SurrealDbConfig, embedder: &Embedder, fields: &[SearchField]) {
+    for field in fields {
+        match surreal::embed_field(config, embedder, field).await {
+            Ok(report) => println!(
+                "Embedded {} of {} row(s) of {field}",
+                report.embedded, report.rows
+            ),
+            Err(e) => {
+                tracing::error!(%field, error = %e, "failed to embed field");
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Define the search index of every field
+async fn define_search_indexes(config: &SurrealDbConfig, fields: &[SearchField]) {
+    if fields.is_empty() {
+        tracing::error!("no fields to index; pass `table.field`s or set SEARCH_FIELDS");
+        std::process::exit(1);
+    }
+
+    for field in fields {
+        match surreal::define_search_index(config, field).await {
+            Ok(()) => println!("Indexed {field}"),
+            Err(e) => {
+                tracing::error!(%field, error = %e, "failed to define search index");
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Print the name of every table
+async fn list_tables(config: &SurrealDbConfig) {
+    match SurrealSchemaTool::new(config.clone()).list_tables().await {
+        Ok(tables) => {
+            for table in tables {
+                println!("{table}");
+            }
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "failed to list tables");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Print the statements defining `table`
+async fn show_schema(config: &SurrealDbConfig, table: &str) {
+    match SurrealSchemaTool::new(config.clone())
+        .definitions(table)
+        .await
+    {
+        Ok(definitions) => {
+            for definition in definitions {
+                println!("{definition};");
+            }
+        }
+        Err(e) => {
+            tracing::error!(table, error = %e, "failed to read schema");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Exit unless `addr` is a loopback address or remote clients are allowed,
+/// since the servers have no authentication
+fn refuse_remote(addr: SocketAddr, allow_remote: bool) {
+    if !addr.ip().is_loopback() && !allow_remote {
+        tracing::error!(
+            %addr,
+            "the server has no authentication; pass --allow-remote to listen on a non-loopback address"
+        );
+        std::process::exit(1);
+    }
+}
+
+/// Run `query` and print its result as JSON
+///
+/// Without `write`, the query must be a single read-only `SELECT`
+/// statement, validated like those of the sub-agents.
+async fn run_query(config: &SurrealDbConfig, query: &str, write: bool) {
+    if !write && let Err(e) = SurrealSelectTool::new(config.clone()).validate_query(query) {
+        tracing::error!(query, error = %e, "refusing query; pass --write to run it anyway");
+        std::process::exit(1);
+    }
+
+    match rig_tutorial::surreal::execute_query(config, query).await {
+        Ok(result) => println!(
+            "{}",
+            serde_json::to_string_pretty(&result).unwrap_or_else(|_| result.to_string())
+        ),
+        Err(e) => {
+            tracing::error!(query, error = %e, "query failed");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// List or show stored runs
+async fn runs(store: Option<RunStore>, command: RunsCommand) {
+    let Some(store) = store else {
+        tracing::error!("no run store configured; set RUN_STORE");
+        std::process::exit(1);
+    };
 
-    println!("answer: {answer}");
+    match command {
+        RunsCommand::List { limit } => match store.list(limit).await {
+            Ok(runs) => {
+                for run in runs {
+                    let question = run.question.split_whitespace().collect::<Vec<_>>();
+                    println!(
+                        "{}  {}  {:>7}ms  {:>8.4} USD  {}",
+                        run.id,
+                        run.started_at.format("%Y-%m-%d %H:%M:%S"),
+                        run.duration_ms,
+                        run.cost_usd,
+                        question.join(" ")
+                    );
+                }
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "failed to list runs");
+                std::process::exit(1);
+            }
+        },
+        RunsCommand::Show { id, report } => match store.load(&id).await {
+            Ok(Some(run)) => write_report(
+                &run.result,
+                report_format(&report).unwrap_or(ExportFormat::Markdown),
+                report.output.as_deref(),
+            ),
+            Ok(None) => {
+                tracing::error!(run_id = id, "no such run");
+                std::process::exit(1);
+            }
+            Err(e) => {
+                tracing::error!(run_id = id, error = %e, "failed to load run");
+                std::process::exit(1);
+            }
+        },
+    }
 }
@@ -0,0 +1,296 @@
+//! Connections to external MCP servers, whose tools sub-agents call
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use mcp_core::{
+    client::{Client, ClientBuilder},
+    transport::{ClientSseTransport, ClientSseTransportBuilder, ClientStdioTransport, Transport},
+    types::{ProtocolVersion, Tool},
+};
+use rig::{agent::AgentBuilder, completion::CompletionModel};
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+
+use super::McpError;
+
+/// How long starting or connecting to a server, initializing the session and
+/// listing its tools may take
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// MCP server a sub-agent gets tools from
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "transport", rename_all = "snake_case")]
+pub enum McpServerConfig {
+    /// Server started as a child process, talking over its stdin and stdout
+    Stdio {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+    /// Server accepting SSE connections at `url`, e.g. `http://localhost:3001/sse`
+    Sse { url: String },
+}
+
+impl fmt::Display for McpServerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            McpServerConfig::Stdio { command, args } if args.is_empty() => write!(f, "{command}"),
+            McpServerConfig::Stdio { command, args } => write!(f, "{command} {}", args.join(" ")),
+            McpServerConfig::Sse { url } => write!(f, "{url}"),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum McpClient {
+    Stdio(Client<ClientStdioTransport>),
+    Sse(Client<ClientSseTransport>),
+}
+
+/// Initialized connection to an MCP server, and the tools it offers
+pub struct McpConnection {
+    client: McpClient,
+    tools: Vec<Tool>,
+}
+
+impl McpConnection {
+    /// Start or connect to the server, initialize the session and list its tools
+    pub async fn connect(config: &McpServerConfig) -> Result<Self, McpError> {
+        let server = config.to_string();
+
+        let client = match config {
+            McpServerConfig::Stdio { command, args } => {
+                let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+                let transport = ClientStdioTransport::new(command, &args)
+                    .map_err(|e| connect_error(&server, e))?;
+                McpClient::Stdio(client(transport))
+            }
+            McpServerConfig::Sse { url } => {
+                McpClient::Sse(client(ClientSseTransportBuilder::new(url.clone()).build()))
+            }
+        };
+
+        let tools = match &client {
+            McpClient::Stdio(client) => list_tools(client, &server).await?,
+            McpClient::Sse(client) => list_tools(client, &server).await?,
+        };
+        tracing::info!(%server, tools = tools.len(), "connected to MCP server");
+
+        Ok(Self { client, tools })
+    }
+
+    /// Tools the server offers
+    pub fn tools(&self) -> &[Tool] {
+        &self.tools
+    }
+
+    /// Give the agent built by `builder` every tool of the server
+    pub(crate) fn register<M: CompletionModel>(&self, builder: AgentBuilder<M>) -> AgentBuilder<M> {
+        self.tools
+            .iter()
+            .fold(builder, |builder, tool| match &self.client {
+                McpClient::Stdio(client) => builder.mcp_tool(tool.clone(), client.clone()),
+                McpClient::Sse(client) => builder.mcp_tool(tool.clone(), client.clone()),
+            })
+    }
+}
+
+fn client<T: Transport>(transport: T) -> Client<T> {
+    ClientBuilder::new(transport)
+        .set_protocol_version(ProtocolVersion::V2024_11_05)
+        .set_client_info(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+        .build()
+}
+
+async fn list_tools<T: Transport>(client: &Client<T>, server: &str) -> Result<Vec<Tool>, McpError> {
+    client.open().await.map_err(|e| connect_error(server, e))?;
+    client
+        .initialize()
+        .await
+        .map_err(|e| connect_error(server, e))?;
+
+    let response = client
+        .list_tools(None, None)
+        .await
+        .map_err(|e| connect_error(server, e))?;
+
+    Ok(response.tools)
+}
+
+fn connect_error(server: &str, err: impl fmt::Display) -> McpError {
+    McpError::Connect {
+        server: server.to_string(),
+        message: err.to_string(),
+    }
+}
+
+/// Connections of a pipeline to the MCP servers of its sub-agents, opened on
+/// first use and shared by sub-agents of the same server
+///
+/// Each server connects at most once at a time, without holding up the
+/// sub-agents of other servers; a failed connection is retried on next use.
+#[derive(Default)]
+pub(crate) struct McpConnections(Mutex<HashMap<String, Arc<OnceCell<Arc<McpConnection>>>>>);
+
+impl McpConnections {
+    pub(crate) async fn get(
+        &self,
+        config: &McpServerConfig,
+    ) -> Result<Arc<McpConnection>, McpError> {
+        let server = config.to_string();
+        let cell = self
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .entry(server.clone())
+            .or_default()
+            .clone();
+
+        let connection = cell
+            .get_or_try_init(|| async {
+                match tokio::time::timeout(CONNECT_TIMEOUT, McpConnection::connect(config)).await {
+                    Ok(connection) => connection.map(Arc::new),
+                    Err(_) => Err(connect_error(
+                        &server,
+                        format!("timed out after {}s", CONNECT_TIMEOUT.as_secs()),
+                    )),
+                }
+            })
+            .await?;
+
+        Ok(connection.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        agents::{
+            mock::ScriptedModel,
+            model::Llm,
+            query::{self, MAX_TURNS, QueryInput},
+        },
+        config::SurrealConfig,
+        mcp::{McpTransport, serve},
+        pipeline::{Pipeline, SubAgentConfig},
+        prompts::Prompts,
+        surreal::QueryLedger,
+        testing::seeded_db,
+    };
+
+    /// Serve our own MCP server over SSE on a free port, standing in for an
+    /// external one, and wait until it accepts connections
+    async fn serve_sse(surreal_config: SurrealConfig) -> u16 {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let served = Pipeline::with_llm(Llm::new(ScriptedModel::new(), "mock"), surreal_config);
+        let server = tokio::spawn(serve(
+            served,
+            McpTransport::Sse(([127, 0, 0, 1], port).into()),
+        ));
+
+        for _ in 0..100 {
+            assert!(!server.is_finished(), "MCP server failed to start");
+            if tokio::net::TcpStream::connect(("127.0.0.1", port))
+                .await
+                .is_ok()
+            {
+                return port;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        panic!("MCP server did not start listening on port {port}");
+    }
+
+    #[tokio::test]
+    async fn test_sub_agent_calls_mcp_tools() {
+        let surreal_config = seeded_db("mcp-client").await;
+        let port = serve_sse(surreal_config.clone()).await;
+
+        let server = McpServerConfig::Sse {
+            url: format!("http://127.0.0.1:{port}/sse"),
+        };
+        let connections = McpConnections::default();
+        let connection = connections.get(&server).await.unwrap();
+        assert!(Arc::ptr_eq(
+            &connection,
+            &connections.get(&server).await.unwrap()
+        ));
+
+        let names = connection
+            .tools()
+            .iter()
+            .map(|tool| tool.name.as_str())
+            .collect::<Vec<_>>();
+        assert!(names.contains(&"surreal_select"));
+
+        let model = ScriptedModel::new()
+            .tool_call(
+                "surreal_select",
+                json!({ "query": "SELECT id, name FROM customers:acme" }),
+            )
+            .text("The CRM lists Acme Corp.");
+        let sub_agent = SubAgentConfig {
+            name: "crm".to_string(),
+            description: "Finds accounts in the CRM.".to_string(),
+            table: String::new(),
+            table_context: "The CRM holds every account.".to_string(),
+            mcp: Some(server),
+            file: None,
+        };
+
+        let result = query::question(
+            &Llm::new(model.clone(), "mock"),
+            &Prompts::embedded(),
+            QueryInput {
+                sub_agent: &sub_agent,
+                question: "Which accounts are in the CRM?",
+                ledger: QueryLedger::new(),
+                mcp: Some(&connection),
+                embedder: None,
+            },
+            &surreal_config,
+            MAX_TURNS,
+        )
+        .await
+        .unwrap();
+
+        let sent = serde_json::to_string(&model.requests()[1].chat_history).unwrap();
+        assert!(sent.contains("Acme Corp"));
+        assert_eq!(result.answer, "The CRM lists Acme Corp.");
+        // Only the SurrealDB tools of the sub-agent itself are recorded
+        assert!(result.queries_run.is_empty());
+
+        let preamble = model.requests()[0].preamble.clone().unwrap();
+        assert!(preamble.contains("- surreal_select: Execute a SQL SELECT"));
+        assert!(!preamble.contains("from the  table"));
+    }
+
+    #[tokio::test]
+    async fn test_connect_reports_unreachable_server() {
+        let server = McpServerConfig::Stdio {
+            command: "/nonexistent/mcp-server".to_string(),
+            args: vec!["--stdio".to_string()],
+        };
+
+        let err = McpConnection::connect(&server).await.err().unwrap();
+
+        assert!(
+            err.to_string()
+                .starts_with("Failed to connect to MCP server /nonexistent/mcp-server --stdio")
+        );
+    }
+}
@@ -0,0 +1,38 @@
+//! Model Context Protocol support
+//!
+//! [`server`] exposes the SurrealDB tools and the pipeline to MCP clients;
+//! [`client`] connects sub-agents to external MCP servers whose tools they
+//! call instead of, or next to, the SurrealDB tools.
+
+pub mod client;
+pub mod server;
+
+use std::error::Error as StdError;
+use std::fmt;
+
+pub use client::{McpConnection, McpServerConfig};
+pub use server::{ASK_TOOL, McpTransport, protocol, serve};
+
+/// Error of the MCP server or of a connection to one
+#[derive(Debug)]
+pub enum McpError {
+    Transport(String),
+    /// An external MCP server could not be started, initialized or asked for its tools
+    Connect {
+        server: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for McpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            McpError::Transport(msg) => write!(f, "MCP transport failed: {msg}"),
+            McpError::Connect { server, message } => {
+                write!(f, "Failed to connect to MCP server {server}: {message}")
+            }
+        }
+    }
+}
+
+impl StdError for McpError {}
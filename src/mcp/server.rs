@@ -0,0 +1,359 @@
+//! MCP server exposing the SurrealDB tools and the pipeline
+//!
+//! Other MCP clients get the same `surreal_schema`, `surreal_select`,
+//! `surreal_search` and, when the pipeline has an embedding model,
+//! `surreal_vector_search` tools the sub-agents use, with the same validation, and an `ask` tool
+//! answering a question with the whole pipeline. The server speaks
+//! JSON-RPC over stdio or over SSE.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use mcp_core::{
+    protocol::{Protocol, ProtocolBuilder},
+    server::Server,
+    tool_error_response, tool_text_response,
+    transport::{ServerSseTransport, ServerStdioTransport},
+    types::{
+        CallToolRequest, CallToolResponse, Implementation, InitializeRequest, InitializeResponse,
+        ListRequest, ServerCapabilities, Tool as McpTool, ToolCapabilities, ToolsListResponse,
+    },
+};
+use rig::tool::Tool;
+use serde_json::{Value, json};
+
+use super::McpError;
+use crate::{
+    agents::model::Model,
+    pipeline::Pipeline,
+    server::MAX_TEXT_CHARS,
+    surreal::{
+        SurrealDbConfig, schema::SurrealSchemaTool, search::SurrealSearchTool,
+        select::SurrealSelectTool, vector::SurrealVectorSearchTool,
+    },
+};
+
+/// Name of the tool answering a question with the pipeline
+pub const ASK_TOOL: &str = "ask";
+
+/// How the MCP server talks to its clients
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum McpTransport {
+    /// JSON-RPC messages, one per line, on stdin and stdout
+    Stdio,
+    /// Server-sent events on `GET /sse`, requests on `POST /message`
+    Sse(SocketAddr),
+}
+
+/// Tools served to MCP clients
+struct Tools<M: Model> {
+    schema: SurrealSchemaTool,
+    select: SurrealSelectTool,
+    search: SurrealSearchTool,
+    vector: Option<SurrealVectorSearchTool>,
+    pipeline: Pipeline<M>,
+    definitions: Vec<McpTool>,
+}
+
+impl<M: Model> Tools<M> {
+    async fn new(pipeline: Pipeline<M>) -> Self {
+        let config = SurrealDbConfig::from(pipeline.surreal_config());
+        let schema = SurrealSchemaTool::new(config.clone());
+        let select = SurrealSelectTool::new(config.clone());
+        let search = SurrealSearchTool::new(config.clone());
+        let vector = pipeline
+            .embedder()
+            .map(|embedder| SurrealVectorSearchTool::new(config, embedder.clone()));
+
+        let mut definitions = vec![
+            definition(&schema).await,
+            definition(&select).await,
+            definition(&search).await,
+            McpTool {
+                name: ASK_TOOL.to_string(),
+                description: Some(
+                    "Answer a question about the database with a team of agents: the question is split into sub-questions, each answered by a sub-agent querying its table, and the findings are combined into one answer citing the record IDs it relies on.".to_string(),
+                ),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "question": {
+                            "type": "string",
+                            "description": "The question to answer"
+                        }
+                    },
+                    "required": ["question"]
+                }),
+                annotations: None,
+            },
+        ];
+        if let Some(vector) = &vector {
+            definitions.insert(3, definition(vector).await);
+        }
+
+        Self {
+            schema,
+            select,
+            search,
+            vector,
+            pipeline,
+            definitions,
+        }
+    }
+
+    async fn call(&self, request: CallToolRequest) -> CallToolResponse {
+        let arguments = Value::Object(request.arguments.unwrap_or_default().into_iter().collect());
+        tracing::info!(tool = %request.name, "MCP tool called");
+
+        match request.name.as_str() {
+            SurrealSchemaTool::NAME => call(&self.schema, arguments).await,
+            SurrealSelectTool::NAME => call(&self.select, arguments).await,
+            SurrealSearchTool::NAME => call(&self.search, arguments).await,
+            SurrealVectorSearchTool::NAME => match &self.vector {
+                Some(vector) => call(vector, arguments).await,
+                None => tool_error_response!(
+                    "surreal_vector_search needs an embedding model; set EMBEDDING_MODEL"
+                ),
+            },
+            ASK_TOOL => self.ask(arguments).await,
+            other => tool_error_response!(format!("Unknown tool: {other}")),
+        }
+    }
+
+    async fn ask(&self, arguments: Value) -> CallToolResponse {
+        let Some(question) = arguments["question"].as_str() else {
+            return tool_error_response!("Invalid arguments: missing string field `question`");
+        };
+        let chars = question.chars().count();
+        if chars > MAX_TEXT_CHARS {
+            return tool_error_response!(format!(
+                "Question of {chars} characters exceeds the limit of {MAX_TEXT_CHARS}"
+            ));
+        }
+
+        match self.pipeline.run(question).await {
+            Ok(result) => tool_text_response!(result.answer),
+            Err(e) => tool_error_response!(e),
+        }
+    }
+}
+
+/// MCP description of a rig tool
+async fn definition<T: Tool>(tool: &T) -> McpTool {
+    let definition = tool.definition(String::new()).await;
+
+    McpTool {
+        name: definition.name,
+        description: Some(definition.description),
+        input_schema: definition.parameters,
+        annotations: None,
+    }
+}
+
+/// Call a rig tool with the arguments of an MCP request
+async fn call<T: Tool<Output = String>>(tool: &T, arguments: Value) -> CallToolResponse {
+    let args = match serde_json::from_value(arguments) {
+        Ok(args) => args,
+        Err(e) => return tool_error_response!(format!("Invalid arguments: {e}")),
+    };
+
+    match tool.call(args).await {
+        Ok(output) => tool_text_response!(output),
+        Err(e) => tool_error_response!(e),
+    }
+}
+
+/// JSON-RPC handlers of the server, answering `ask` with `pipeline`
+pub async fn protocol<M: Model>(pipeline: Pipeline<M>) -> Protocol {
+    let tools = Arc::new(Tools::new(pipeline).await);
+    let list = tools.clone();
+
+    ProtocolBuilder::new()
+        .request_handler("initialize", |request: InitializeRequest| {
+            Box::pin(async move {
+                tracing::info!(client = %request.client_info.name, "MCP client connected");
+
+                Ok(InitializeResponse {
+                    protocol_version: request.protocol_version,
+                    capabilities: ServerCapabilities {
+                        tools: Some(ToolCapabilities::default()),
+                        ..Default::default()
+                    },
+                    server_info: Implementation {
+                        name: env!("CARGO_PKG_NAME").to_string(),
+                        version: env!("CARGO_PKG_VERSION").to_string(),
+                    },
+                    instructions: None,
+                })
+            })
+        })
+        .notification_handler("notifications/initialized", |_: Value| {
+            Box::pin(async { Ok(()) })
+        })
+        .request_handler("ping", |_: Value| Box::pin(async { Ok(json!({})) }))
+        .request_handler("tools/list", move |_: ListRequest| {
+            let tools = list.definitions.clone();
+            Box::pin(async move {
+                Ok(ToolsListResponse {
+                    tools,
+                    next_cursor: None,
+                    meta: None,
+                })
+            })
+        })
+        .request_handler("tools/call", move |request: CallToolRequest| {
+            let tools = tools.clone();
+            Box::pin(async move { Ok(tools.call(request).await) })
+        })
+        .build()
+}
+
+/// Serve the tools over `transport` until the client disconnects, or
+/// until the process stops for SSE
+pub async fn serve<M: Model>(
+    pipeline: Pipeline<M>,
+    transport: McpTransport,
+) -> Result<(), McpError> {
+    let protocol = protocol(pipeline).await;
+
+    let result = match transport {
+        McpTransport::Stdio => Server::start(ServerStdioTransport::new(protocol)).await,
+        McpTransport::Sse(addr) => {
+            tracing::info!(address = %addr, "MCP server listening");
+            Server::start(ServerSseTransport::new(
+                addr.ip().to_string(),
+                addr.port(),
+                protocol,
+            ))
+            .await
+        }
+    };
+
+    result.map_err(|e| McpError::Transport(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use mcp_core::transport::{JsonRpcRequest, JsonRpcVersion};
+
+    use super::*;
+    use crate::{
+        agents::model::Llm,
+        testing::{customers_sub_agent, one_query_script, seeded_db},
+    };
+
+    async fn request(protocol: &Protocol, method: &str, params: Value) -> Value {
+        let response = protocol
+            .handle_request(JsonRpcRequest {
+                id: 1,
+                method: method.to_string(),
+                params: Some(params),
+                jsonrpc: JsonRpcVersion::default(),
+            })
+            .await;
+
+        assert!(response.error.is_none(), "{:?}", response.error);
+        response.result.unwrap()
+    }
+
+    async fn call_tool(protocol: &Protocol, name: &str, arguments: Value) -> Value {
+        request(
+            protocol,
+            "tools/call",
+            json!({ "name": name, "arguments": arguments }),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_lists_and_calls_tools() {
+        let pipeline = Pipeline::with_llm(
+            Llm::new(one_query_script(), "mock"),
+            seeded_db("mcp-tools").await,
+        )
+        .sub_agent(customers_sub_agent());
+        let protocol = protocol(pipeline).await;
+
+        let init = request(
+            &protocol,
+            "initialize",
+            json!({ "protocolVersion": "2024-11-05", "capabilities": {}, "clientInfo": { "name": "test", "version": "1" } }),
+        )
+        .await;
+        assert_eq!(init["protocolVersion"], "2024-11-05");
+        assert!(init["capabilities"]["tools"].is_object());
+
+        let list = request(&protocol, "tools/list", json!({})).await;
+        let names = list["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tool| tool["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            ["surreal_schema", "surreal_select", "surreal_search", "ask"]
+        );
+
+        let selected = call_tool(
+            &protocol,
+            "surreal_select",
+            json!({ "query": "SELECT name FROM customers:acme" }),
+        )
+        .await;
+        assert!(
+            selected["content"][0]["text"]
+                .as_str()
+                .unwrap()
+                .contains("Acme")
+        );
+
+        let rejected = call_tool(
+            &protocol,
+            "surreal_select",
+            json!({ "query": "DELETE customers" }),
+        )
+        .await;
+        assert!(
+            rejected["content"][0]["text"]
+                .as_str()
+                .unwrap()
+                .starts_with("Query validation error")
+        );
+
+        let chained = call_tool(
+            &protocol,
+            "surreal_select",
+            json!({ "query": "SELECT name FROM customers; SELECT name FROM feature_requests" }),
+        )
+        .await;
+        assert!(
+            chained["content"][0]["text"]
+                .as_str()
+                .unwrap()
+                .contains("Only a single statement is allowed")
+        );
+
+        let invalid = call_tool(&protocol, "surreal_select", json!({})).await;
+        assert_eq!(invalid["isError"], true);
+
+        let long = call_tool(
+            &protocol,
+            "ask",
+            json!({ "question": "x".repeat(MAX_TEXT_CHARS + 1) }),
+        )
+        .await;
+        assert_eq!(long["isError"], true);
+
+        let answer = call_tool(
+            &protocol,
+            "ask",
+            json!({ "question": "Who pays the most?" }),
+        )
+        .await;
+        assert_eq!(
+            answer["content"][0]["text"],
+            "Acme Corp (customers:acme) pays the most."
+        );
+    }
+}
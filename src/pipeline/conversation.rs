@@ -0,0 +1,84 @@
+//! Memory of the questions and answers of an interactive session
+
+use serde::{Deserialize, Serialize};
+
+use super::PipelineResult;
+pub use crate::agents::map::Turn;
+
+/// Earlier turns shown to the map and reduce agents; older turns are dropped
+/// to keep the prompts small
+pub const CONTEXT_TURNS: usize = 5;
+
+impl From<&PipelineResult> for Turn {
+    fn from(result: &PipelineResult) -> Self {
+        Self {
+            question: result.question.trim().to_string(),
+            sub_questions: result.sub_questions.clone(),
+            answer: result.answer.clone(),
+        }
+    }
+}
+
+/// Questions and answers of a session, so follow-up questions can refer to
+/// earlier ones
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Conversation {
+    turns: Vec<Turn>,
+}
+
+impl From<Vec<Turn>> for Conversation {
+    fn from(turns: Vec<Turn>) -> Self {
+        Self { turns }
+    }
+}
+
+impl Conversation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remember the question and answer of a run
+    pub fn push(&mut self, result: &PipelineResult) {
+        self.turns.push(Turn::from(result));
+    }
+
+    /// Forget every turn
+    pub fn clear(&mut self) {
+        self.turns.clear();
+    }
+
+    pub fn turns(&self) -> &[Turn] {
+        &self.turns
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.turns.is_empty()
+    }
+
+    /// Turns passed as context to the next run
+    pub fn context(&self) -> &[Turn] {
+        &self.turns[self.turns.len().saturating_sub(CONTEXT_TURNS)..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context_keeps_recent_turns() {
+        let mut conversation = Conversation::new();
+        for index in 0..CONTEXT_TURNS + 2 {
+            conversation.turns.push(Turn {
+                question: format!("question {index}"),
+                sub_questions: Default::default(),
+                answer: String::new(),
+            });
+        }
+
+        let context = conversation.context();
+        assert_eq!(context.len(), CONTEXT_TURNS);
+        assert_eq!(context[0].question, "question 2");
+        assert_eq!(conversation.turns().len(), CONTEXT_TURNS + 2);
+    }
+}
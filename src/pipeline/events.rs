@@ -0,0 +1,59 @@
+use futures::channel::mpsc::UnboundedSender;
+use serde::{Deserialize, Serialize};
+
+use super::PipelineResult;
+use crate::agents::{SubAgentResult, map::SubQuestions};
+
+/// Progress of a pipeline run, in the order it happens
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PipelineEvent {
+    /// The map agent split the question into sub-questions
+    PlanProduced { sub_questions: SubQuestions },
+    /// A sub-agent was asked its sub-question
+    SubAgentStarted { agent: String, question: String },
+    /// A sub-agent issued a query
+    QueryIssued { agent: String, query: String },
+    /// A query finished; `rows` is the number of rows returned
+    QueryFinished {
+        agent: String,
+        query: String,
+        rows: usize,
+        error: Option<String>,
+    },
+    /// A sub-agent answered its sub-question
+    SubAgentDone { result: Box<SubAgentResult> },
+    /// The reduce agent started combining the sub-agent results
+    ReduceStarted,
+    /// A piece of the final answer, as streamed by the reduce model
+    AnswerDelta { text: String },
+    /// The run finished
+    Done { result: Box<PipelineResult> },
+    /// The run stopped because an agent failed
+    Failed { error: String },
+}
+
+/// Destination of the events of a run; a no-op for non-streaming runs
+#[derive(Clone, Default)]
+pub(crate) struct EventSink {
+    sender: Option<UnboundedSender<PipelineEvent>>,
+}
+
+impl EventSink {
+    pub(crate) fn new(sender: UnboundedSender<PipelineEvent>) -> Self {
+        Self {
+            sender: Some(sender),
+        }
+    }
+
+    pub(crate) fn is_active(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    pub(crate) fn emit(&self, event: PipelineEvent) {
+        if let Some(sender) = &self.sender {
+            // The receiver is gone when the consumer dropped the stream; the run just finishes quietly
+            let _ = sender.unbounded_send(event);
+        }
+    }
+}
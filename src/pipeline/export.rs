@@ -0,0 +1,346 @@
+//! Reports of a pipeline result as JSON, Markdown or CSV
+
+use std::error::Error as StdError;
+use std::fmt::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::PipelineResult;
+use crate::{agents::parse_json, join};
+
+/// Format of an exported pipeline result
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    /// The whole result, as serialized in run transcripts
+    Json,
+    /// A report of the question, plan, findings, answer and usage
+    Markdown,
+    /// The rows behind the answer (see [`PipelineResult::to_csv`])
+    Csv,
+}
+
+impl FromStr for ExportFormat {
+    type Err = ExportError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(ExportFormat::Json),
+            "markdown" | "md" => Ok(ExportFormat::Markdown),
+            "csv" => Ok(ExportFormat::Csv),
+            other => Err(ExportError::UnknownFormat(other.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Markdown => "markdown",
+            ExportFormat::Csv => "csv",
+        };
+        f.write_str(name)
+    }
+}
+
+impl ExportFormat {
+    /// Format of a file, from its extension
+    pub fn from_path(path: &Path) -> Result<Self, ExportError> {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .parse()
+    }
+}
+
+/// Error exporting a pipeline result
+#[derive(Debug)]
+pub enum ExportError {
+    Json(serde_json::Error),
+    Csv(csv::Error),
+    UnknownFormat(String),
+    /// Neither the answer nor the sub-agents have rows
+    NoRows,
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::Json(err) => write!(f, "Failed to serialize result: {err}"),
+            ExportError::Csv(err) => write!(f, "Failed to write CSV: {err}"),
+            ExportError::UnknownFormat(format) => {
+                write!(
+                    f,
+                    "Unknown export format `{format}`; expected json, markdown or csv"
+                )
+            }
+            ExportError::NoRows => write!(f, "The run has no rows to export as CSV"),
+        }
+    }
+}
+
+impl StdError for ExportError {}
+
+impl From<serde_json::Error> for ExportError {
+    fn from(err: serde_json::Error) -> Self {
+        ExportError::Json(err)
+    }
+}
+
+impl From<csv::Error> for ExportError {
+    fn from(err: csv::Error) -> Self {
+        ExportError::Csv(err)
+    }
+}
+
+impl PipelineResult {
+    /// Render the result in `format`
+    pub fn export(&self, format: ExportFormat) -> Result<String, ExportError> {
+        match format {
+            ExportFormat::Json => Ok(serde_json::to_string_pretty(self)?),
+            ExportFormat::Markdown => Ok(self.to_markdown()),
+            ExportFormat::Csv => self.to_csv(),
+        }
+    }
+
+    /// Render the result as a Markdown report
+    pub fn to_markdown(&self) -> String {
+        // Writing to a String cannot fail
+        let mut output = String::new();
+        let _ = self.write_markdown(&mut output);
+        output
+    }
+
+    fn write_markdown(&self, output: &mut String) -> fmt::Result {
+        // Headings end at the first line break
+        let question = self.question.split_whitespace().collect::<Vec<_>>();
+        writeln!(output, "# {}\n", question.join(" "))?;
+
+        if !self.history.is_empty() {
+            writeln!(output, "## Earlier Questions\n")?;
+            for turn in &self.history {
+                writeln!(output, "- {}", table_cell(&turn.question))?;
+            }
+            writeln!(output)?;
+        }
+
+        writeln!(output, "## Plan\n")?;
+        writeln!(output, "| Sub-agent | Sub-question |\n| --- | --- |")?;
+        for (agent, question) in &self.sub_questions {
+            writeln!(output, "| {agent} | {} |", table_cell(question))?;
+        }
+
+        writeln!(output, "\n## Findings")?;
+        for result in &self.sub_results {
+            writeln!(output, "\n### {}\n", result.agent)?;
+            writeln!(output, "> {}\n", result.question.trim())?;
+            writeln!(output, "{}\n", result.answer.trim())?;
+            writeln!(
+                output,
+                "Cited records: {}",
+                code_list(&result.citations.cited)
+            )?;
+            if !result.citations.unverified.is_empty() {
+                writeln!(
+                    output,
+                    "\nUnverified citations: {}",
+                    code_list(&result.citations.unverified)
+                )?;
+            }
+            if !result.queries_run.is_empty() {
+                writeln!(output, "\n```sql\n{}\n```", result.queries_run.join(";\n"))?;
+            }
+        }
+
+        if let Some(joined) = &self.joined {
+            writeln!(output, "\n## Joined Rows\n")?;
+            write!(output, "{}", joined.to_markdown())?;
+        }
+
+        writeln!(output, "\n## Answer\n\n{}\n", self.answer.trim())?;
+        writeln!(
+            output,
+            "Cited records: {}",
+            code_list(&self.citations.cited)
+        )?;
+        if !self.citations.unverified.is_empty() {
+            writeln!(
+                output,
+                "\nUnverified citations: {}",
+                code_list(&self.citations.unverified)
+            )?;
+        }
+
+        writeln!(output, "\n## Usage\n\n```text\n{}```", self.usage)?;
+        if let Some(run_id) = &self.run_id {
+            writeln!(output, "\nRun ID: `{run_id}`")?;
+        }
+
+        Ok(())
+    }
+
+    /// Render the rows behind the answer as CSV, with the union of their
+    /// fields as header
+    ///
+    /// The rows are those of the answer when it is a JSON array of objects,
+    /// optionally fenced as a code block; otherwise the joined rows, or the
+    /// rows returned to the sub-agents with an `agent` column, keeping only
+    /// the records the answer cites when it cites any.
+    pub fn to_csv(&self) -> Result<String, ExportError> {
+        let rows = self.csv_rows();
+        if rows.is_empty() {
+            return Err(ExportError::NoRows);
+        }
+
+        let columns = join::columns(&rows);
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.write_record(&columns)?;
+
+        for row in &rows {
+            writer.write_record(columns.iter().map(|column| match row.get(column) {
+                None | Some(Value::Null) => String::new(),
+                Some(Value::String(s)) => s.clone(),
+                Some(other) => other.to_string(),
+            }))?;
+        }
+
+        let bytes = writer
+            .into_inner()
+            .map_err(|e| ExportError::Csv(e.into_error().into()))?;
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+impl PipelineResult {
+    fn csv_rows(&self) -> Vec<Value> {
+        if let Ok(rows) = parse_json::<Vec<Value>>(&self.answer)
+            && !rows.is_empty()
+            && rows.iter().all(Value::is_object)
+        {
+            return rows;
+        }
+
+        if let Some(joined) = &self.joined {
+            return joined
+                .rows
+                .iter()
+                .map(|row| Value::Object(row.clone().into_iter().collect()))
+                .collect();
+        }
+
+        let cited = |row: &Value| {
+            self.citations.cited.is_empty()
+                || row["id"]
+                    .as_str()
+                    .is_some_and(|id| self.citations.cited.iter().any(|cited| cited == id))
+        };
+
+        self.sub_results
+            .iter()
+            .flat_map(|result| {
+                result.rows.iter().filter(|row| cited(row)).map(|row| {
+                    let mut fields = serde_json::Map::new();
+                    fields.insert("agent".to_string(), Value::String(result.agent.clone()));
+                    if let Value::Object(row) = row {
+                        fields.extend(row.clone());
+                    }
+                    Value::Object(fields)
+                })
+            })
+            .collect()
+    }
+}
+
+fn table_cell(text: &str) -> String {
+    text.trim().replace('|', "\\|").replace('\n', " ")
+}
+
+fn code_list(ids: &[String]) -> String {
+    if ids.is_empty() {
+        return "none".to_string();
+    }
+
+    ids.iter()
+        .map(|id| format!("`{id}`"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::PipelineResultBuilder;
+
+    fn result(answer: &str) -> PipelineResult {
+        PipelineResultBuilder::new("Who pays the most?")
+            .answer(answer)
+            .run_id("run-1")
+            .build()
+    }
+
+    #[test]
+    fn test_markdown_report() {
+        let report = result("Acme Corp pays the most.").to_markdown();
+
+        assert!(report.starts_with("# Who pays the most?\n"));
+        assert!(report.contains("| customers | Who has the most ARR? |"));
+        assert!(report.contains("Cited records: `customers:acme`"));
+        assert!(report.contains("## Answer\n\nAcme Corp pays the most.\n"));
+        assert!(report.contains("Run ID: `run-1`"));
+    }
+
+    #[test]
+    fn test_csv_of_structured_answer() {
+        let answer = json!([
+            { "feature": "SSO", "arr": 480000 },
+            { "feature": "Dark mode, maybe", "customers": ["hooli"] },
+        ]);
+        let csv = result(&format!("```json\n{answer}\n```")).to_csv().unwrap();
+
+        assert_eq!(
+            csv,
+            "feature,arr,customers\nSSO,480000,\n\"Dark mode, maybe\",,\"[\"\"hooli\"\"]\"\n"
+        );
+        assert!(matches!(
+            PipelineResultBuilder::new("Who pays the most?")
+                .rows(Vec::new())
+                .build()
+                .export(ExportFormat::Csv),
+            Err(ExportError::NoRows)
+        ));
+    }
+
+    #[test]
+    fn test_csv_of_prose_answer_exports_cited_rows() {
+        let result = PipelineResultBuilder::new("Who pays the most?")
+            .answer("Acme Corp (customers:acme) pays the most.")
+            .rows(vec![
+                json!({ "id": "customers:acme", "name": "Acme Corp", "arr": 250000 }),
+                json!({ "id": "customers:globex", "name": "Globex", "arr": 90000 }),
+            ])
+            .cited(&["customers:acme"])
+            .build();
+
+        let csv = result.to_csv().unwrap();
+
+        assert_eq!(
+            csv,
+            "agent,id,name,arr\ncustomers,customers:acme,Acme Corp,250000\n"
+        );
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(
+            ExportFormat::from_path(Path::new("report.md")).unwrap(),
+            ExportFormat::Markdown
+        );
+        assert!(ExportFormat::from_path(Path::new("report.txt")).is_err());
+    }
+}
@@ -0,0 +1,634 @@
+//! Map / query / reduce pipeline over a set of sub-agents
+
+mod conversation;
+mod events;
+mod export;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use std::time::Instant;
+
+use chrono::Utc;
+
+use futures::{Stream, StreamExt, channel::mpsc};
+
+use rig::{embeddings::EmbeddingModel, providers::xai::Client};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    agents::{
+        AgentError, SubAgentResult,
+        file::FileSubAgent,
+        map::{SubQuestions, map},
+        model::{Llm, Model},
+        query::{self, SurrealSubAgent},
+        reduce::{ReduceInput, ReduceOptions, reduce},
+        sub_agent::{SubAgent, SubAgentContext},
+    },
+    citations::CitationReport,
+    config::SurrealConfig,
+    join::{JoinSpec, JoinedTable},
+    mcp::client::McpConnections,
+    prompts::Prompts,
+    runs::{RunStore, RunTranscript, ToolCall},
+    surreal::{Embedder, LedgerEvent, QueryLedger},
+    tokens::Tokenizer,
+    usage::{PriceTable, UsageMeter, UsageReport},
+};
+
+pub use crate::agents::sub_agent::SubAgentConfig;
+pub use conversation::{CONTEXT_TURNS, Conversation, Turn};
+use events::EventSink;
+pub use events::PipelineEvent;
+pub use export::{ExportError, ExportFormat};
+
+/// Outcome of a pipeline run
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PipelineResult {
+    pub question: String,
+    pub sub_questions: SubQuestions,
+    pub sub_results: Vec<SubAgentResult>,
+    /// Rows of the joined sub-agents, when a join is configured
+    pub joined: Option<JoinedTable>,
+    pub answer: String,
+    /// Citations of the final answer, checked against every record returned during the run
+    pub citations: CitationReport,
+    /// Version of each prompt template used for the run
+    pub prompt_versions: BTreeMap<String, String>,
+    /// Tokens and cost of the LLM calls of the run
+    #[serde(default)]
+    pub usage: UsageReport,
+    /// ID of the stored transcript of the run, when a run store is configured
+    #[serde(default)]
+    pub run_id: Option<String>,
+    /// Earlier turns of the conversation the question followed up on
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<Turn>,
+}
+
+impl PipelineResult {
+    /// Cited record IDs that were never returned by a query, across all answers
+    pub fn unverified_citations(&self) -> BTreeSet<&str> {
+        self.sub_results
+            .iter()
+            .map(|sub_result| &sub_result.citations)
+            .chain(std::iter::once(&self.citations))
+            .flat_map(|report| report.unverified.iter().map(String::as_str))
+            .collect()
+    }
+}
+
+/// Multi-agent pipeline: map the question to sub-agents, query, then reduce
+pub struct Pipeline<M = rig::providers::xai::completion::CompletionModel> {
+    llm: Llm<M>,
+    surreal_config: SurrealConfig,
+    sub_agents: Vec<Box<dyn SubAgent<M>>>,
+    join: Option<JoinSpec>,
+    reduce_options: ReduceOptions,
+    max_turns: usize,
+    prompts: Prompts,
+    prices: PriceTable,
+    store: Option<RunStore>,
+    mcp: Arc<McpConnections>,
+    embedder: Option<Embedder>,
+}
+
+impl Pipeline {
+    /// Create a pipeline without sub-agents, using the default xAI model
+    pub fn new(client: Client, surreal_config: SurrealConfig) -> Self {
+        Self::with_llm(Llm::xai(&client), surreal_config)
+    }
+}
+
+impl<M: Model> Pipeline<M> {
+    /// Create a pipeline without sub-agents, using any completion model
+    pub fn with_llm(llm: Llm<M>, surreal_config: SurrealConfig) -> Self {
+        Self {
+            llm,
+            surreal_config,
+            sub_agents: Vec::new(),
+            join: None,
+            reduce_options: ReduceOptions {
+                tokenizer: Tokenizer::for_provider("xai"),
+                ..Default::default()
+            },
+            max_turns: query::MAX_TURNS,
+            prompts: Prompts::embedded(),
+            prices: PriceTable::default(),
+            store: None,
+            mcp: Arc::default(),
+            embedder: None,
+        }
+    }
+
+    /// Register a sub-agent answering from the SurrealDB database of the
+    /// pipeline, a local data file, or the tools of an MCP server
+    pub fn sub_agent(mut self, sub_agent: SubAgentConfig) -> Self {
+        let sub_agent: Box<dyn SubAgent<M>> = match sub_agent.file.clone() {
+            Some(path) => Box::new(FileSubAgent::new(sub_agent, path, self.mcp.clone())),
+            None => Box::new(SurrealSubAgent::new(
+                sub_agent,
+                self.surreal_config.clone(),
+                self.mcp.clone(),
+            )),
+        };
+        self.sub_agents.push(sub_agent);
+        self
+    }
+
+    /// Register a sub-agent answering from another data source
+    pub fn custom_sub_agent(mut self, sub_agent: impl SubAgent<M> + 'static) -> Self {
+        self.sub_agents.push(Box::new(sub_agent));
+        self
+    }
+
+    /// Give the sub-agents `surreal_vector_search`, embedding queries with
+    /// `model`, named `name`; it must be the model the rows were embedded with
+    pub fn embeddings(mut self, name: &str, model: impl EmbeddingModel + 'static) -> Self {
+        self.embedder = Some(Embedder::new(name, model));
+        self
+    }
+
+    /// Use a custom set of prompt templates
+    pub fn prompts(mut self, prompts: Prompts) -> Self {
+        self.prompts = prompts;
+        self
+    }
+
+    /// Price the usage of each run with a custom price table
+    pub fn prices(mut self, prices: PriceTable) -> Self {
+        self.prices = prices;
+        self
+    }
+
+    /// Store the transcript of every run
+    pub fn store(mut self, store: RunStore) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Join the rows of two sub-agents before reduce
+    ///
+    /// The joined sub-agents are asked to include their join key in every
+    /// row they query, and reduce receives the merged table.
+    pub fn join(mut self, join: JoinSpec) -> Self {
+        self.join = Some(join);
+        self
+    }
+
+    /// Pass the raw rows returned by each sub-agent to the reduce agent
+    pub fn include_rows(mut self, include_rows: bool) -> Self {
+        self.reduce_options.include_rows = include_rows;
+        self
+    }
+
+    /// Limit the tool-calling turns of each query sub-agent
+    pub fn max_turns(mut self, max_turns: usize) -> Self {
+        self.max_turns = max_turns;
+        self
+    }
+
+    /// Use the token estimate of another provider for the reduce model
+    pub fn tokenizer(mut self, tokenizer: Tokenizer) -> Self {
+        self.reduce_options.tokenizer = tokenizer;
+        self
+    }
+
+    /// Limit the estimated tokens of each reduce call
+    ///
+    /// Sub-agent results that do not fit are summarized in chunks, a few
+    /// at a time, and the summaries reduced recursively. A run fails if the
+    /// budget cannot hold the prompts, or if the summaries still do not fit.
+    pub fn token_budget(mut self, token_budget: usize) -> Self {
+        self.reduce_options.token_budget = Some(token_budget);
+        self
+    }
+
+    /// Run the pipeline for a question
+    pub async fn run(&self, question: &str) -> Result<PipelineResult, AgentError> {
+        self.execute(question, &[], &EventSink::default()).await
+    }
+
+    /// Run the pipeline for a question following up on the turns of
+    /// `conversation`, which the map and reduce agents see as context
+    pub async fn run_with_history(
+        &self,
+        question: &str,
+        conversation: &Conversation,
+    ) -> Result<PipelineResult, AgentError> {
+        self.execute(question, conversation.context(), &EventSink::default())
+            .await
+    }
+
+    /// Run the pipeline for a question, streaming its progress
+    ///
+    /// The stream ends with [`PipelineEvent::Done`] carrying the result of
+    /// the run, or [`PipelineEvent::Failed`] if an agent failed; the final
+    /// answer is streamed as [`PipelineEvent::AnswerDelta`]s.
+    pub fn stream<'a>(&'a self, question: &'a str) -> impl Stream<Item = PipelineEvent> + 'a {
+        self.stream_turn(question, &[])
+    }
+
+    /// Stream a run for a question following up on the turns of `conversation`
+    pub fn stream_with_history<'a>(
+        &'a self,
+        question: &'a str,
+        conversation: &'a Conversation,
+    ) -> impl Stream<Item = PipelineEvent> + 'a {
+        self.stream_turn(question, conversation.context())
+    }
+
+    fn stream_turn<'a>(
+        &'a self,
+        question: &'a str,
+        history: &'a [Turn],
+    ) -> impl Stream<Item = PipelineEvent> + 'a {
+        let (sender, receiver) = mpsc::unbounded();
+
+        let run = async move {
+            let events = EventSink::new(sender);
+            events.emit(match self.execute(question, history, &events).await {
+                Ok(result) => PipelineEvent::Done {
+                    result: Box::new(result),
+                },
+                Err(e) => PipelineEvent::Failed {
+                    error: e.to_string(),
+                },
+            });
+        };
+
+        // Drive the run while forwarding its events; the receiver ends once
+        // the run is done and every sender is dropped
+        let run = futures::stream::once(run).filter_map(|_| async { None });
+        futures::stream::select(receiver, run)
+    }
+
+    #[tracing::instrument(name = "pipeline_run", skip_all, fields(question = question.trim()))]
+    async fn execute(
+        &self,
+        question: &str,
+        history: &[Turn],
+        events: &EventSink,
+    ) -> Result<PipelineResult, AgentError> {
+        let llm = self.llm.clone().with_meter(UsageMeter::new());
+        let started_at = Utc::now();
+        let started = Instant::now();
+        let mut tool_calls = Vec::new();
+
+        let agents: BTreeMap<&str, &str> = self
+            .sub_agents
+            .iter()
+            .map(|sub_agent| (sub_agent.name(), sub_agent.description()))
+            .collect();
+
+        let sub_questions = map(&llm, &self.prompts, question, &agents, history).await?;
+
+        events.emit(PipelineEvent::PlanProduced {
+            sub_questions: sub_questions.clone(),
+        });
+
+        let mut sub_results = Vec::new();
+
+        for sub_agent in &self.sub_agents {
+            let name = sub_agent.name();
+            let Some(sub_question) = sub_questions.get(name) else {
+                continue;
+            };
+
+            let sub_question = match self.join_key(name) {
+                Some(key) => format!(
+                    "{sub_question}\nInclude the `id` and `{key}` fields of every row in your queries."
+                ),
+                None => sub_question.clone(),
+            };
+
+            events.emit(PipelineEvent::SubAgentStarted {
+                agent: name.to_string(),
+                question: sub_question.clone(),
+            });
+
+            let ledger = ledger_for(name, events);
+            let sub_result = sub_agent
+                .answer(
+                    &sub_question,
+                    SubAgentContext {
+                        llm: &llm,
+                        prompts: &self.prompts,
+                        ledger: ledger.clone(),
+                        max_turns: self.max_turns,
+                        embedder: self.embedder.as_ref(),
+                    },
+                )
+                .await?;
+
+            tool_calls.extend(ledger.records().into_iter().map(|record| ToolCall {
+                agent: name.to_string(),
+                record,
+            }));
+
+            events.emit(PipelineEvent::SubAgentDone {
+                result: Box::new(sub_result.clone()),
+            });
+
+            sub_results.push(sub_result);
+        }
+
+        let joined = self.join.as_ref().and_then(|join| {
+            let rows_of = |agent: &str| {
+                sub_results
+                    .iter()
+                    .find(|sub_result| sub_result.agent == agent)
+                    .map(|sub_result| sub_result.rows.as_slice())
+            };
+
+            Some(join.join(rows_of(&join.left)?, rows_of(&join.right)?))
+        });
+
+        events.emit(PipelineEvent::ReduceStarted);
+
+        let on_delta = |text: &str| {
+            events.emit(PipelineEvent::AnswerDelta {
+                text: text.to_string(),
+            })
+        };
+
+        let answer = reduce(
+            &llm,
+            &self.prompts,
+            ReduceInput {
+                question,
+                results: &sub_results,
+                joined: joined.as_ref(),
+                history,
+            },
+            self.reduce_options,
+            events.is_active().then_some(&on_delta as _),
+        )
+        .await?;
+
+        let seen: BTreeSet<String> = sub_results
+            .iter()
+            .flat_map(|sub_result| sub_result.rows_used.iter().map(ToString::to_string))
+            .collect();
+        let citations = CitationReport::verify(&answer, &seen);
+
+        let mut result = PipelineResult {
+            question: question.to_string(),
+            sub_questions,
+            sub_results,
+            joined,
+            answer,
+            citations,
+            prompt_versions: self.prompts.versions().clone(),
+            usage: llm.meter().report(&self.prices),
+            run_id: None,
+            history: history.to_vec(),
+        };
+
+        if let Some(store) = &self.store {
+            let id = RunTranscript::new_id();
+            result.run_id = Some(id.clone());
+
+            let transcript = RunTranscript {
+                id,
+                started_at,
+                duration_ms: started.elapsed().as_millis() as u64,
+                model: llm.name().to_string(),
+                result: result.clone(),
+                llm_calls: llm.meter().calls(),
+                tool_calls,
+            };
+
+            // A run is not lost because its transcript could not be stored
+            if let Err(e) = store.save(&transcript).await {
+                tracing::warn!(error = %e, "failed to store run transcript");
+                result.run_id = None;
+            }
+        }
+
+        Ok(result)
+    }
+
+    pub(crate) fn llm(&self) -> &Llm<M> {
+        &self.llm
+    }
+
+    pub(crate) fn surreal_config(&self) -> &SurrealConfig {
+        &self.surreal_config
+    }
+
+    pub(crate) fn templates(&self) -> &Prompts {
+        &self.prompts
+    }
+
+    pub(crate) fn price_table(&self) -> &PriceTable {
+        &self.prices
+    }
+
+    pub(crate) fn sub_agents(&self) -> impl Iterator<Item = &dyn SubAgent<M>> {
+        self.sub_agents.iter().map(AsRef::as_ref)
+    }
+
+    pub(crate) fn embedder(&self) -> Option<&Embedder> {
+        self.embedder.as_ref()
+    }
+
+    pub(crate) fn run_store(&self) -> Option<&RunStore> {
+        self.store.as_ref()
+    }
+
+    /// Join key of a sub-agent, if it takes part in the configured join
+    fn join_key(&self, agent: &str) -> Option<&str> {
+        let join = self.join.as_ref()?;
+
+        if join.left == agent {
+            Some(&join.left_key)
+        } else if join.right == agent {
+            Some(&join.right_key)
+        } else {
+            None
+        }
+    }
+}
+
+/// Ledger for a sub-agent, forwarding its query activity as pipeline events
+fn ledger_for(agent: &str, events: &EventSink) -> QueryLedger {
+    if !events.is_active() {
+        return QueryLedger::new();
+    }
+
+    let agent = agent.to_string();
+    let events = events.clone();
+
+    QueryLedger::new().with_listener(Arc::new(move |event| {
+        events.emit(match event {
+            LedgerEvent::QueryIssued { query } => PipelineEvent::QueryIssued {
+                agent: agent.clone(),
+                query: query.clone(),
+            },
+            LedgerEvent::QueryFinished(record) => PipelineEvent::QueryFinished {
+                agent: agent.clone(),
+                query: record.query.clone(),
+                rows: record.rows.len(),
+                error: record.error.clone(),
+            },
+        })
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use futures::future::BoxFuture;
+    use rig::{
+        OneOrMany,
+        completion::{
+            AssistantContent, CompletionError, CompletionModel, CompletionRequest,
+            CompletionResponse,
+        },
+        streaming::StreamingCompletionResponse,
+    };
+
+    use super::*;
+    use crate::agents::{
+        cassette::{CassetteModel, CassetteResponse},
+        mock::ScriptedModel,
+    };
+    use crate::testing::customers_sub_agent;
+
+    /// Live model stand-in answering each agent of the pipeline in turn
+    #[derive(Clone, Default)]
+    struct Stub {
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl CompletionModel for Stub {
+        type Response = CassetteResponse;
+        type StreamingResponse = CassetteResponse;
+
+        async fn completion(
+            &self,
+            request: CompletionRequest,
+        ) -> Result<CompletionResponse<Self::Response>, CompletionError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+
+            let preamble = request.preamble.unwrap_or_default();
+            let text = if preamble.contains("delagating sub-questions") {
+                r#"{"customers": "Which customers have the highest ARR?"}"#
+            } else if preamble.contains("from the customers table") {
+                "Acme Corp (customers:acme) has the highest ARR."
+            } else {
+                "Prioritize the requests of Acme Corp (customers:acme)."
+            };
+
+            Ok(CompletionResponse {
+                choice: OneOrMany::one(AssistantContent::text(text)),
+                raw_response: CassetteResponse::default(),
+            })
+        }
+
+        async fn stream(
+            &self,
+            _request: CompletionRequest,
+        ) -> Result<StreamingCompletionResponse<Self::StreamingResponse>, CompletionError> {
+            Err(CompletionError::ProviderError("not streaming".to_string()))
+        }
+    }
+
+    fn pipeline<M: Model>(model: M) -> Pipeline<M> {
+        let surreal_config = SurrealConfig {
+            host: "mem://".to_string(),
+            username: String::new(),
+            password: String::new(),
+            namespace: "test".to_string(),
+            database: "test".to_string(),
+        };
+
+        Pipeline::with_llm(Llm::new(model, "stub"), surreal_config).sub_agent(customers_sub_agent())
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_replays_offline() {
+        let dir = std::env::temp_dir().join(format!("pipeline-cassette-{}", std::process::id()));
+        let stub = Stub::default();
+
+        let recorded = pipeline(CassetteModel::record(stub.clone(), &dir))
+            .run("Which feature requests should I prioritize?")
+            .await
+            .unwrap();
+        let replayed = pipeline(CassetteModel::replay(&dir))
+            .run("Which feature requests should I prioritize?")
+            .await
+            .unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(stub.calls.load(Ordering::SeqCst), 3);
+        assert_eq!(replayed.sub_questions, recorded.sub_questions);
+        assert_eq!(
+            replayed.sub_results[0].answer,
+            recorded.sub_results[0].answer
+        );
+        assert_eq!(
+            replayed.answer,
+            "Prioritize the requests of Acme Corp (customers:acme)."
+        );
+        assert_eq!(replayed.usage.total.calls, 3);
+    }
+
+    /// Sub-agent answering from a fixed document instead of a database
+    struct Handbook;
+
+    impl<M: Model> SubAgent<M> for Handbook {
+        fn name(&self) -> &str {
+            "handbook"
+        }
+
+        fn description(&self) -> &str {
+            "Answers questions about the support policy."
+        }
+
+        fn answer<'a>(
+            &'a self,
+            question: &'a str,
+            context: SubAgentContext<'a, M>,
+        ) -> BoxFuture<'a, Result<SubAgentResult, AgentError>> {
+            Box::pin(async move {
+                let query = "handbook:support";
+                context.ledger.record_issued(query);
+                context.ledger.record_result(
+                    query,
+                    &serde_json::json!([{ "id": "handbook:support", "sla_hours": 4 }]),
+                );
+
+                Ok(SubAgentResult::from_ledger(
+                    "handbook",
+                    question,
+                    "Urgent tickets are answered within 4 hours (handbook:support).".to_string(),
+                    &context.ledger,
+                ))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_runs_custom_sub_agent() {
+        let model = ScriptedModel::new()
+            .text(r#"{"handbook": "How fast are urgent tickets answered?"}"#)
+            .text("Within 4 hours (handbook:support).");
+
+        let result = pipeline(model.clone())
+            .custom_sub_agent(Handbook)
+            .run("How fast do we answer urgent tickets?")
+            .await
+            .unwrap();
+
+        let plan = model.requests()[0].preamble.clone().unwrap();
+        assert!(plan.contains("Answers questions about the support policy."));
+        assert_eq!(result.sub_results.len(), 1);
+        assert_eq!(result.sub_results[0].agent, "handbook");
+        assert_eq!(result.sub_results[0].queries_run, ["handbook:support"]);
+        assert!(result.citations.unverified.is_empty());
+    }
+}
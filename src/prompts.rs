@@ -0,0 +1,245 @@
+//! Prompt templates for the map, query, reduce, summarize, judge and label agents
+//!
+//! Templates are [minijinja](https://docs.rs/minijinja) files. Defaults are
+//! embedded from the `prompts/` directory; a deployment can override any of
+//! them by placing a file with the same name in its own prompts directory.
+//!
+//! A template may declare its version in a leading comment:
+//!
+//! ```text
+//! {# version: 2 #}
+//! ```
+//!
+//! The version, together with a hash of the template source, is recorded
+//! with every pipeline result so answers can be traced back to the prompts
+//! that produced them.
+
+use std::collections::BTreeMap;
+use std::error::Error as StdError;
+use std::fmt;
+use std::path::Path;
+
+use minijinja::Environment;
+use serde::Serialize;
+
+/// Template for the map agent; variables: `sub_agents` (list of `name`, `description`)
+pub const MAP: &str = "map";
+/// Template for query sub-agents; variables: `table`, `table_context`,
+/// `mcp_tools` (list of `name`, `description`), `vector_search`, `clusters`
+/// (list of clustered fields)
+pub const QUERY: &str = "query";
+/// Template for the reduce agent; variables: `data`
+pub const REDUCE: &str = "reduce";
+/// Template for chunk summaries of the tree reduce; variables: `question`, `data`
+pub const SUMMARIZE: &str = "summarize";
+/// Template for the judge scoring answers in evaluations; variables: `question`, `answer`, `criteria`
+pub const JUDGE: &str = "judge";
+/// Template for the labels of clusters of similar rows; variables: `table`,
+/// `field`, `clusters` (list of lists of texts)
+pub const LABEL: &str = "label";
+
+const DEFAULTS: [(&str, &str); 6] = [
+    (MAP, include_str!("../prompts/map.j2")),
+    (QUERY, include_str!("../prompts/query.j2")),
+    (REDUCE, include_str!("../prompts/reduce.j2")),
+    (SUMMARIZE, include_str!("../prompts/summarize.j2")),
+    (JUDGE, include_str!("../prompts/judge.j2")),
+    (LABEL, include_str!("../prompts/label.j2")),
+];
+
+/// Error loading or rendering a prompt template
+#[derive(Debug)]
+pub enum TemplateError {
+    Io(std::io::Error),
+    Template(minijinja::Error),
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::Io(err) => write!(f, "Failed to read prompt template: {err}"),
+            TemplateError::Template(err) => write!(f, "Invalid prompt template: {err}"),
+        }
+    }
+}
+
+impl StdError for TemplateError {}
+
+impl From<std::io::Error> for TemplateError {
+    fn from(err: std::io::Error) -> Self {
+        TemplateError::Io(err)
+    }
+}
+
+impl From<minijinja::Error> for TemplateError {
+    fn from(err: minijinja::Error) -> Self {
+        TemplateError::Template(err)
+    }
+}
+
+/// The set of prompt templates used by a pipeline
+#[derive(Clone, Debug)]
+pub struct Prompts {
+    env: Environment<'static>,
+    versions: BTreeMap<String, String>,
+}
+
+impl Prompts {
+    /// The templates embedded in the binary
+    pub fn embedded() -> Self {
+        Self::from_sources(
+            DEFAULTS
+                .iter()
+                .map(|(name, source)| (*name, source.to_string())),
+        )
+        .expect("embedded prompt templates are valid")
+    }
+
+    /// Embedded templates, overridden by the `<name>.j2` files found in `dir`
+    pub fn from_dir(dir: impl AsRef<Path>) -> Result<Self, TemplateError> {
+        let dir = dir.as_ref();
+        let mut sources = Vec::new();
+
+        for (name, default) in DEFAULTS {
+            let path = dir.join(format!("{name}.j2"));
+            let source = if path.exists() {
+                std::fs::read_to_string(&path)?
+            } else {
+                default.to_string()
+            };
+            sources.push((name, source));
+        }
+
+        Self::from_sources(sources)
+    }
+
+    fn from_sources(
+        sources: impl IntoIterator<Item = (&'static str, String)>,
+    ) -> Result<Self, TemplateError> {
+        let mut env = Environment::new();
+        env.set_trim_blocks(true);
+        env.set_lstrip_blocks(true);
+
+        let mut versions = BTreeMap::new();
+
+        for (name, source) in sources {
+            versions.insert(name.to_string(), template_version(&source));
+            env.add_template_owned(name, source)?;
+        }
+
+        Ok(Self { env, versions })
+    }
+
+    /// Render a template with the given variables
+    pub fn render<S: Serialize>(&self, name: &str, ctx: S) -> Result<String, TemplateError> {
+        Ok(self.env.get_template(name)?.render(ctx)?)
+    }
+
+    /// Version of every template, as `<declared version>@<source hash>`
+    pub fn versions(&self) -> &BTreeMap<String, String> {
+        &self.versions
+    }
+}
+
+impl Default for Prompts {
+    fn default() -> Self {
+        Self::embedded()
+    }
+}
+
+/// Declared version of a template followed by a hash of its source
+fn template_version(source: &str) -> String {
+    let declared = source
+        .trim_start()
+        .strip_prefix("{#")
+        .and_then(|comment| comment.split_once("#}"))
+        .and_then(|(comment, _)| comment.trim().strip_prefix("version:"))
+        .map(|version| version.trim().to_string())
+        .unwrap_or_else(|| "unversioned".to_string());
+
+    format!("{declared}@{:08x}", fnv1a(source.as_bytes()) as u32)
+}
+
+/// FNV-1a hash, stable across Rust releases unlike `DefaultHasher`
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_render_map() {
+        let prompts = Prompts::embedded();
+        let preamble = prompts
+            .render(
+                MAP,
+                json!({ "sub_agents": [
+                    { "name": "customers", "description": "Finds customers." },
+                    { "name": "feature_requests", "description": "Finds feature requests." },
+                ] }),
+            )
+            .unwrap();
+
+        assert!(preamble.contains(
+            "- \"customers\": \"Finds customers.\"\n- \"feature_requests\": \"Finds feature requests.\"\n"
+        ));
+        assert!(!preamble.contains("version"));
+    }
+
+    #[test]
+    fn test_render_query() {
+        let preamble = Prompts::embedded()
+            .render(
+                QUERY,
+                json!({ "table": "customers", "table_context": "Lists customers." }),
+            )
+            .unwrap();
+
+        assert!(preamble.starts_with(
+            "You are a helpful assistant that can answer questions from the customers table.\nLists customers.\n"
+        ));
+    }
+
+    #[test]
+    fn test_embedded_versions() {
+        let prompts = Prompts::embedded();
+
+        assert_eq!(prompts.versions().len(), 6);
+        assert!(prompts.versions()[REDUCE].starts_with("2@"));
+    }
+
+    #[test]
+    fn test_from_dir_overrides() {
+        let dir = std::env::temp_dir().join(format!("prompts-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("reduce.j2"),
+            "{# version: 7 #}\nAnswer from: {{ data }}",
+        )
+        .unwrap();
+
+        let prompts = Prompts::from_dir(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            prompts.render(REDUCE, json!({ "data": "rows" })).unwrap(),
+            "Answer from: rows"
+        );
+        assert!(prompts.versions()[REDUCE].starts_with("7@"));
+        assert_eq!(prompts.versions()[MAP], Prompts::embedded().versions()[MAP]);
+    }
+
+    #[test]
+    fn test_template_version() {
+        assert!(template_version("no comment").starts_with("unversioned@"));
+        assert_ne!(
+            template_version("{# version: 1 #}\na"),
+            template_version("{# version: 1 #}\nb")
+        );
+    }
+}
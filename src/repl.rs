@@ -0,0 +1,324 @@
+//! Commands and state of the interactive mode
+//!
+//! Every line is either a question, answered as a follow-up of the earlier
+//! questions of the session, or a `:command` inspecting the last run.
+
+use std::error::Error as StdError;
+use std::fmt::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::{
+    agents::SubAgentResult,
+    pipeline::{Conversation, ExportError, ExportFormat, PipelineResult},
+};
+
+/// Usage of the interactive mode
+pub const HELP: &str = "\
+Type a question to ask it; follow-up questions see the earlier questions and answers.
+
+:plan            sub-questions of the last answer
+:sql [agent]     queries run for the last answer
+:rows [agent]    rows returned for the last answer
+:agent <name>    question, answer, citations and queries of one sub-agent
+:save <file>     write the last answer as a .json, .md or .csv report
+:history         questions asked so far
+:clear           forget the conversation
+:help            show this help
+:quit            leave";
+
+/// A line entered in the interactive mode
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReplCommand {
+    Ask(String),
+    Plan,
+    Sql(Option<String>),
+    Rows(Option<String>),
+    Agent(String),
+    Save(PathBuf),
+    History,
+    Clear,
+    Help,
+    Quit,
+}
+
+impl FromStr for ReplCommand {
+    type Err = ReplError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let line = s.trim();
+        let Some(command) = line.strip_prefix(':') else {
+            return Ok(ReplCommand::Ask(line.to_string()));
+        };
+
+        let (name, argument) = match command.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, Some(argument.trim().to_string())),
+            None => (command, None),
+        };
+        let required = |usage: &'static str| argument.clone().ok_or(ReplError::Usage(usage));
+
+        match name {
+            "plan" => Ok(ReplCommand::Plan),
+            "sql" => Ok(ReplCommand::Sql(argument)),
+            "rows" => Ok(ReplCommand::Rows(argument)),
+            "agent" => Ok(ReplCommand::Agent(required(":agent <name>")?)),
+            "save" => Ok(ReplCommand::Save(required(":save <file>")?.into())),
+            "history" => Ok(ReplCommand::History),
+            "clear" => Ok(ReplCommand::Clear),
+            "help" | "?" => Ok(ReplCommand::Help),
+            "quit" | "exit" | "q" => Ok(ReplCommand::Quit),
+            other => Err(ReplError::UnknownCommand(other.to_string())),
+        }
+    }
+}
+
+/// Error of an interactive command
+#[derive(Debug)]
+pub enum ReplError {
+    UnknownCommand(String),
+    /// A required argument is missing; holds the usage of the command
+    Usage(&'static str),
+    /// The command inspects the last answer, but nothing was asked yet
+    NoAnswer,
+    UnknownAgent(String),
+    Export(ExportError),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for ReplError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplError::UnknownCommand(name) => {
+                write!(f, "Unknown command :{name}; type :help for commands")
+            }
+            ReplError::Usage(usage) => write!(f, "Usage: {usage}"),
+            ReplError::NoAnswer => write!(f, "Nothing was asked yet"),
+            ReplError::UnknownAgent(name) => {
+                write!(f, "No sub-agent named {name} answered the last question")
+            }
+            ReplError::Export(err) => write!(f, "{err}"),
+            ReplError::Io(err) => write!(f, "Failed to write report: {err}"),
+        }
+    }
+}
+
+impl StdError for ReplError {}
+
+impl From<ExportError> for ReplError {
+    fn from(err: ExportError) -> Self {
+        ReplError::Export(err)
+    }
+}
+
+impl From<std::io::Error> for ReplError {
+    fn from(err: std::io::Error) -> Self {
+        ReplError::Io(err)
+    }
+}
+
+/// Conversation of an interactive session and the last answer in it
+#[derive(Debug, Default)]
+pub struct Session {
+    conversation: Conversation,
+    last: Option<PipelineResult>,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Conversation the next question follows up on
+    pub fn conversation(&self) -> &Conversation {
+        &self.conversation
+    }
+
+    /// Remember an answered question
+    pub fn record(&mut self, result: PipelineResult) {
+        self.conversation.push(&result);
+        self.last = Some(result);
+    }
+
+    /// Forget the conversation and the last answer
+    pub fn clear(&mut self) {
+        self.conversation.clear();
+        self.last = None;
+    }
+
+    pub fn last(&self) -> Option<&PipelineResult> {
+        self.last.as_ref()
+    }
+
+    /// Sub-question asked of each sub-agent
+    pub fn plan(&self) -> Result<String, ReplError> {
+        let last = self.last.as_ref().ok_or(ReplError::NoAnswer)?;
+
+        Ok(last
+            .sub_questions
+            .iter()
+            .map(|(agent, question)| format!("{agent}: {question}"))
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+
+    /// Queries run by every sub-agent, or by `agent`
+    pub fn sql(&self, agent: Option<&str>) -> Result<String, ReplError> {
+        let mut output = String::new();
+
+        for result in self.sub_results(agent)? {
+            let _ = writeln!(output, "-- {}", result.agent);
+            for query in &result.queries_run {
+                let _ = writeln!(output, "{};", query.trim().trim_end_matches(';'));
+            }
+        }
+
+        Ok(output.trim_end().to_string())
+    }
+
+    /// Rows returned to every sub-agent, or to `agent`, as JSON
+    pub fn rows(&self, agent: Option<&str>) -> Result<String, ReplError> {
+        let mut output = String::new();
+
+        for result in self.sub_results(agent)? {
+            let rows = serde_json::to_string_pretty(&result.rows).unwrap_or_default();
+            let _ = writeln!(
+                output,
+                "-- {} ({} rows)\n{rows}",
+                result.agent,
+                result.rows.len()
+            );
+        }
+
+        Ok(output.trim_end().to_string())
+    }
+
+    /// Everything one sub-agent contributed to the last answer
+    pub fn agent(&self, name: &str) -> Result<String, ReplError> {
+        let result = self.sub_results(Some(name))?[0];
+
+        let mut output = format!(
+            "Question: {}\n\n{}\n\nCited: {}",
+            result.question.trim(),
+            result.answer.trim(),
+            result.citations.cited.join(", ")
+        );
+        if !result.citations.unverified.is_empty() {
+            let _ = write!(
+                output,
+                "\nUnverified: {}",
+                result.citations.unverified.join(", ")
+            );
+        }
+        let _ = write!(output, "\n\n{}", self.sql(Some(name))?);
+
+        Ok(output)
+    }
+
+    /// Questions asked so far, oldest first
+    pub fn history(&self) -> String {
+        self.conversation
+            .turns()
+            .iter()
+            .enumerate()
+            .map(|(index, turn)| format!("{}. {}", index + 1, turn.question))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Write the last answer to `path` as a report in the format of its extension
+    pub fn save(&self, path: &Path) -> Result<(), ReplError> {
+        let last = self.last.as_ref().ok_or(ReplError::NoAnswer)?;
+        let report = last.export(ExportFormat::from_path(path)?)?;
+        std::fs::write(path, report)?;
+
+        Ok(())
+    }
+
+    fn sub_results(&self, agent: Option<&str>) -> Result<Vec<&SubAgentResult>, ReplError> {
+        let last = self.last.as_ref().ok_or(ReplError::NoAnswer)?;
+
+        let results = last
+            .sub_results
+            .iter()
+            .filter(|result| agent.is_none_or(|agent| result.agent == agent))
+            .collect::<Vec<_>>();
+
+        match agent {
+            Some(agent) if results.is_empty() => Err(ReplError::UnknownAgent(agent.to_string())),
+            _ => Ok(results),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::PipelineResultBuilder;
+
+    fn result(question: &str) -> PipelineResult {
+        PipelineResultBuilder::new(question).build()
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(
+            "  now only above $100k ".parse::<ReplCommand>().unwrap(),
+            ReplCommand::Ask("now only above $100k".to_string())
+        );
+        assert_eq!(
+            ":sql customers".parse::<ReplCommand>().unwrap(),
+            ReplCommand::Sql(Some("customers".to_string()))
+        );
+        assert_eq!(
+            ":save out/report.md".parse::<ReplCommand>().unwrap(),
+            ReplCommand::Save("out/report.md".into())
+        );
+        assert!(matches!(
+            ":agent".parse::<ReplCommand>(),
+            Err(ReplError::Usage(_))
+        ));
+        assert!(matches!(
+            ":frobnicate".parse::<ReplCommand>(),
+            Err(ReplError::UnknownCommand(_))
+        ));
+    }
+
+    #[test]
+    fn test_session_inspects_last_answer() {
+        let mut session = Session::new();
+        assert!(matches!(session.plan(), Err(ReplError::NoAnswer)));
+
+        session.record(result("Who pays the most?"));
+        session.record(result("And the least?"));
+
+        assert_eq!(session.plan().unwrap(), "customers: Who has the most ARR?");
+        assert_eq!(
+            session.sql(None).unwrap(),
+            "-- customers\nSELECT id, arr FROM customers;"
+        );
+        assert!(
+            session
+                .rows(Some("customers"))
+                .unwrap()
+                .contains("(1 rows)")
+        );
+        assert!(
+            session
+                .agent("customers")
+                .unwrap()
+                .contains("Cited: customers:acme")
+        );
+        assert!(matches!(
+            session.agent("orders"),
+            Err(ReplError::UnknownAgent(_))
+        ));
+        assert_eq!(
+            session.history(),
+            "1. Who pays the most?\n2. And the least?"
+        );
+        assert_eq!(session.conversation().context().len(), 2);
+
+        session.clear();
+        assert!(session.conversation().is_empty());
+    }
+}
@@ -0,0 +1,67 @@
+//! Transcripts of pipeline runs, stored for audit and replay
+//!
+//! A transcript holds everything needed to understand an answer after the
+//! fact: the plan, every message sent to and received from the model, every
+//! query the sub-agents ran with the rows it returned, and the versions of
+//! the model and prompts involved.
+
+mod store;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{pipeline::PipelineResult, surreal::ledger::QueryRecord, usage::LlmCall};
+
+pub use store::{RunStore, RunStoreError};
+
+/// A query run by a sub-agent
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub agent: String,
+    #[serde(flatten)]
+    pub record: QueryRecord,
+}
+
+/// Everything that happened during a pipeline run
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RunTranscript {
+    /// Unique, time-ordered ID of the run
+    pub id: String,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    /// Name of the model the agents were built from
+    pub model: String,
+    /// Plan, sub-agent results, final answer, prompt versions and usage
+    pub result: PipelineResult,
+    /// Every request to the model, in order
+    pub llm_calls: Vec<LlmCall>,
+    /// Every query run by the sub-agents, in order
+    pub tool_calls: Vec<ToolCall>,
+}
+
+impl RunTranscript {
+    /// A new, unique run ID; IDs sort in the order the runs started
+    pub fn new_id() -> String {
+        ulid::Ulid::new().to_string().to_lowercase()
+    }
+
+    pub fn summary(&self) -> RunSummary {
+        RunSummary {
+            id: self.id.clone(),
+            started_at: self.started_at,
+            duration_ms: self.duration_ms,
+            question: self.result.question.clone(),
+            cost_usd: self.result.usage.total.cost_usd,
+        }
+    }
+}
+
+/// Overview of a stored run, as listed by [`RunStore::list`]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RunSummary {
+    pub id: String,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    pub question: String,
+    pub cost_usd: f64,
+}
@@ -0,0 +1,267 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::path::PathBuf;
+
+use serde_json::{Value, json};
+
+use super::{RunSummary, RunTranscript};
+use crate::surreal::{SurrealDbConfig, SurrealError, execute_bound};
+
+/// Where run transcripts are kept
+#[derive(Clone, Debug)]
+pub enum RunStore {
+    /// The `agent_runs` table of a SurrealDB database
+    Surreal(SurrealDbConfig),
+    /// One `<id>.json` file per run in a local directory
+    Files(PathBuf),
+}
+
+/// Error saving or loading a run transcript
+#[derive(Debug)]
+pub enum RunStoreError {
+    Io(std::io::Error),
+    Serialization(serde_json::Error),
+    Surreal(SurrealError),
+    InvalidId(String),
+}
+
+impl fmt::Display for RunStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunStoreError::Io(err) => write!(f, "Failed to access run store: {err}"),
+            RunStoreError::Serialization(err) => write!(f, "Invalid run transcript: {err}"),
+            RunStoreError::Surreal(err) => write!(f, "Failed to access run store: {err}"),
+            RunStoreError::InvalidId(id) => write!(f, "Invalid run ID: {id}"),
+        }
+    }
+}
+
+impl StdError for RunStoreError {}
+
+impl From<std::io::Error> for RunStoreError {
+    fn from(err: std::io::Error) -> Self {
+        RunStoreError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for RunStoreError {
+    fn from(err: serde_json::Error) -> Self {
+        RunStoreError::Serialization(err)
+    }
+}
+
+impl From<SurrealError> for RunStoreError {
+    fn from(err: SurrealError) -> Self {
+        RunStoreError::Surreal(err)
+    }
+}
+
+const TABLE: &str = "agent_runs";
+
+impl RunStore {
+    /// Store a transcript
+    pub async fn save(&self, run: &RunTranscript) -> Result<(), RunStoreError> {
+        validate_id(&run.id)?;
+
+        match self {
+            RunStore::Surreal(config) => {
+                let mut content = serde_json::to_value(run)?;
+                // The ID is the key of the record, not one of its fields
+                if let Value::Object(fields) = &mut content {
+                    fields.remove("id");
+                }
+
+                execute_bound(
+                    config,
+                    "CREATE type::thing($table, $id) CONTENT $run",
+                    json!({ "table": TABLE, "id": run.id, "run": content }),
+                )
+                .await?;
+            }
+            RunStore::Files(dir) => {
+                tokio::fs::create_dir_all(dir).await?;
+                tokio::fs::write(
+                    dir.join(format!("{}.json", run.id)),
+                    serde_json::to_vec_pretty(run)?,
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The most recent runs, newest first
+    pub async fn list(&self, limit: usize) -> Result<Vec<RunSummary>, RunStoreError> {
+        match self {
+            RunStore::Surreal(config) => {
+                let rows = execute_bound(
+                    config,
+                    "SELECT meta::id(id) AS id, started_at, duration_ms, \
+                     result.question AS question, result.usage.total.cost_usd AS cost_usd \
+                     FROM type::table($table) ORDER BY id DESC LIMIT $limit",
+                    json!({ "table": TABLE, "limit": limit }),
+                )
+                .await?;
+
+                Ok(serde_json::from_value(rows)?)
+            }
+            RunStore::Files(dir) => {
+                let mut entries = match tokio::fs::read_dir(dir).await {
+                    Ok(entries) => entries,
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                        return Ok(Vec::new());
+                    }
+                    Err(err) => return Err(err.into()),
+                };
+
+                let mut ids = Vec::new();
+                while let Some(entry) = entries.next_entry().await? {
+                    let path = entry.path();
+                    if path
+                        .extension()
+                        .is_some_and(|extension| extension == "json")
+                        && let Some(id) = path.file_stem().and_then(|stem| stem.to_str())
+                    {
+                        ids.push(id.to_string());
+                    }
+                }
+                ids.sort_unstable_by(|a, b| b.cmp(a));
+
+                let mut summaries = Vec::new();
+                for id in ids.into_iter().take(limit) {
+                    if let Some(run) = self.load(&id).await? {
+                        summaries.push(run.summary());
+                    }
+                }
+
+                Ok(summaries)
+            }
+        }
+    }
+
+    /// Load a stored run, or `None` if there is no run with that ID
+    pub async fn load(&self, id: &str) -> Result<Option<RunTranscript>, RunStoreError> {
+        validate_id(id)?;
+
+        match self {
+            RunStore::Surreal(config) => {
+                let rows = execute_bound(
+                    config,
+                    "SELECT * OMIT id FROM type::thing($table, $id)",
+                    json!({ "table": TABLE, "id": id }),
+                )
+                .await?;
+
+                let Some(Value::Object(mut fields)) =
+                    rows.as_array().and_then(|rows| rows.first()).cloned()
+                else {
+                    return Ok(None);
+                };
+                fields.insert("id".to_string(), Value::String(id.to_string()));
+
+                Ok(Some(serde_json::from_value(Value::Object(fields))?))
+            }
+            RunStore::Files(dir) => match tokio::fs::read(dir.join(format!("{id}.json"))).await {
+                Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(err) => Err(err.into()),
+            },
+        }
+    }
+}
+
+/// Run IDs are used as file names and record keys; keep them to plain characters
+fn validate_id(id: &str) -> Result<(), RunStoreError> {
+    if id.is_empty()
+        || !id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(RunStoreError::InvalidId(id.to_string()));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{runs::ToolCall, surreal::ledger::QueryRecord, testing::PipelineResultBuilder};
+
+    fn transcript(id: &str, question: &str) -> RunTranscript {
+        RunTranscript {
+            id: id.to_string(),
+            started_at: chrono::Utc::now(),
+            duration_ms: 1_200,
+            model: "grok-3-mini".to_string(),
+            result: PipelineResultBuilder::new(question).run_id(id).build(),
+            llm_calls: Vec::new(),
+            tool_calls: vec![ToolCall {
+                agent: "customers".to_string(),
+                record: QueryRecord {
+                    query: "SELECT * FROM customers".to_string(),
+                    record_ids: vec!["customers:abc".to_string()],
+                    rows: vec![json!({ "id": "customers:abc", "name": "Acme Corp" })],
+                    error: None,
+                    duration_ms: Some(15),
+                },
+            }],
+        }
+    }
+
+    async fn assert_round_trip(store: RunStore) {
+        store.save(&transcript("01a", "first")).await.unwrap();
+        store.save(&transcript("01b", "second")).await.unwrap();
+
+        let summaries = store.list(10).await.unwrap();
+        assert_eq!(
+            summaries
+                .iter()
+                .map(|run| run.id.as_str())
+                .collect::<Vec<_>>(),
+            ["01b", "01a"]
+        );
+        assert_eq!(summaries[0].question, "second");
+        assert_eq!(store.list(1).await.unwrap().len(), 1);
+
+        let run = store.load("01a").await.unwrap().unwrap();
+        assert_eq!(run.id, "01a");
+        assert_eq!(run.result.question, "first");
+        assert_eq!(run.tool_calls[0].record.rows[0]["name"], "Acme Corp");
+
+        assert!(store.load("missing").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_file_store_round_trip() {
+        let dir = std::env::temp_dir().join(format!("runs-test-{}", std::process::id()));
+
+        assert_round_trip(RunStore::Files(dir.clone())).await;
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_surreal_store_round_trip() {
+        let config = SurrealDbConfig::new(
+            "mem://runs-test".to_string(),
+            String::new(),
+            String::new(),
+            "test".to_string(),
+            "test".to_string(),
+        );
+
+        assert_round_trip(RunStore::Surreal(config)).await;
+    }
+
+    #[tokio::test]
+    async fn test_rejects_path_ids() {
+        let store = RunStore::Files(std::env::temp_dir());
+
+        assert!(matches!(
+            store.load("../etc/passwd").await,
+            Err(RunStoreError::InvalidId(_))
+        ));
+    }
+}
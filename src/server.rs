@@ -0,0 +1,482 @@
+//! HTTP API exposing the pipeline
+//!
+//! | Route | Description |
+//! |-------|-------------|
+//! | `POST /ask` | Answer a question; returns the [`PipelineResult`] |
+//! | `POST /ask/stream` | Answer a question, streaming [`PipelineEvent`]s as server-sent events |
+//! | `GET /runs` | Most recent stored runs, newest first, at most [`MAX_RUN_LIMIT`] |
+//! | `GET /runs/{id}` | A stored run with its LLM and tool calls |
+//! | `GET /sub-agents` | Registered sub-agents |
+//! | `GET /tables` | Tables of the database |
+//! | `GET /health` | Liveness |
+//! | `GET /ready` | Readiness: whether SurrealDB answers queries |
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::pin::pin;
+use std::sync::Arc;
+
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+    routing::{get, post},
+};
+use futures::{Stream, StreamExt, channel::mpsc};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::net::{TcpListener, ToSocketAddrs};
+
+use crate::{
+    agents::{AgentError, model::Model},
+    pipeline::{CONTEXT_TURNS, Conversation, Pipeline, PipelineEvent, PipelineResult, Turn},
+    runs::{RunStoreError, RunSummary, RunTranscript},
+    surreal::{SurrealDbConfig, SurrealError, SurrealSchemaTool, execute_query},
+};
+
+/// Runs listed by `GET /runs` unless a `limit` is given
+const DEFAULT_RUN_LIMIT: usize = 20;
+
+/// Most runs `GET /runs` lists at once
+pub const MAX_RUN_LIMIT: usize = 100;
+
+/// Longest question, sub-question or answer accepted in an ask request, in
+/// characters
+pub const MAX_TEXT_CHARS: usize = 8_000;
+
+/// Body of `POST /ask` and `POST /ask/stream`
+#[derive(Clone, Debug, Deserialize)]
+pub struct AskRequest {
+    pub question: String,
+    /// Earlier turns of the conversation the question follows up on; only
+    /// the last [`CONTEXT_TURNS`] are kept
+    #[serde(default)]
+    pub history: Vec<Turn>,
+}
+
+impl AskRequest {
+    /// Conversation of the request, refusing questions and turns longer than
+    /// [`MAX_TEXT_CHARS`]
+    fn conversation(&mut self) -> Result<Conversation, ApiError> {
+        let skip = self.history.len().saturating_sub(CONTEXT_TURNS);
+        let history: Vec<Turn> = self.history.drain(..).skip(skip).collect();
+
+        let texts = history.iter().flat_map(|turn| {
+            [&turn.question, &turn.answer]
+                .into_iter()
+                .chain(turn.sub_questions.values())
+        });
+        for text in std::iter::once(&self.question).chain(texts) {
+            let chars = text.chars().count();
+            if chars > MAX_TEXT_CHARS {
+                return Err(ApiError::TooLong(chars));
+            }
+        }
+
+        Ok(Conversation::from(history))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ListRuns {
+    limit: Option<usize>,
+}
+
+/// Error of an API request, returned as `{"error": "<message>"}`
+#[derive(Debug)]
+pub enum ApiError {
+    Agent(AgentError),
+    RunStore(RunStoreError),
+    Surreal(SurrealError),
+    /// The pipeline has no run store, so runs cannot be fetched
+    NoRunStore,
+    RunNotFound(String),
+    /// A question or turn of the request has this many characters, more than
+    /// [`MAX_TEXT_CHARS`]
+    TooLong(usize),
+    /// `GET /runs` asked for this many runs, more than [`MAX_RUN_LIMIT`]
+    LimitTooHigh(usize),
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Agent(err) => write!(f, "{err}"),
+            ApiError::RunStore(err) => write!(f, "{err}"),
+            ApiError::Surreal(err) => write!(f, "{err}"),
+            ApiError::NoRunStore => write!(f, "No run store is configured"),
+            ApiError::RunNotFound(id) => write!(f, "No run with ID {id}"),
+            ApiError::TooLong(chars) => write!(
+                f,
+                "Text of {chars} characters exceeds the limit of {MAX_TEXT_CHARS}"
+            ),
+            ApiError::LimitTooHigh(limit) => {
+                write!(
+                    f,
+                    "Limit of {limit} runs exceeds the maximum of {MAX_RUN_LIMIT}"
+                )
+            }
+        }
+    }
+}
+
+impl StdError for ApiError {}
+
+impl From<AgentError> for ApiError {
+    fn from(err: AgentError) -> Self {
+        ApiError::Agent(err)
+    }
+}
+
+impl From<RunStoreError> for ApiError {
+    fn from(err: RunStoreError) -> Self {
+        ApiError::RunStore(err)
+    }
+}
+
+impl From<SurrealError> for ApiError {
+    fn from(err: SurrealError) -> Self {
+        ApiError::Surreal(err)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            // The model or the database behind it failed
+            ApiError::Agent(_) => StatusCode::BAD_GATEWAY,
+            ApiError::RunStore(RunStoreError::InvalidId(_))
+            | ApiError::TooLong(_)
+            | ApiError::LimitTooHigh(_) => StatusCode::BAD_REQUEST,
+            ApiError::RunStore(_) | ApiError::Surreal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::NoRunStore => StatusCode::NOT_IMPLEMENTED,
+            ApiError::RunNotFound(_) => StatusCode::NOT_FOUND,
+        };
+
+        (status, Json(json!({ "error": self.to_string() }))).into_response()
+    }
+}
+
+/// Routes of the API, answering with `pipeline`
+pub fn router<M: Model>(pipeline: Pipeline<M>) -> Router {
+    Router::new()
+        .route("/ask", post(ask::<M>))
+        .route("/ask/stream", post(ask_stream::<M>))
+        .route("/runs", get(list_runs::<M>))
+        .route("/runs/{id}", get(get_run::<M>))
+        .route("/sub-agents", get(sub_agents::<M>))
+        .route("/tables", get(tables::<M>))
+        .route("/health", get(health))
+        .route("/ready", get(ready::<M>))
+        .with_state(Arc::new(pipeline))
+}
+
+/// Serve the API on `addr` until the process stops
+pub async fn serve<M: Model>(
+    pipeline: Pipeline<M>,
+    addr: impl ToSocketAddrs,
+) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    tracing::info!(address = %listener.local_addr()?, "listening");
+
+    axum::serve(listener, router(pipeline)).await
+}
+
+async fn ask<M: Model>(
+    State(pipeline): State<Arc<Pipeline<M>>>,
+    Json(mut request): Json<AskRequest>,
+) -> Result<Json<PipelineResult>, ApiError> {
+    let conversation = request.conversation()?;
+    let result = pipeline
+        .run_with_history(&request.question, &conversation)
+        .await?;
+
+    Ok(Json(result))
+}
+
+/// Events are named after their `type`; the stream ends after `done` or `failed`
+async fn ask_stream<M: Model>(
+    State(pipeline): State<Arc<Pipeline<M>>>,
+    Json(mut request): Json<AskRequest>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, ApiError> {
+    let conversation = request.conversation()?;
+    let (sender, receiver) = mpsc::unbounded();
+
+    tokio::spawn(async move {
+        let mut events = pin!(pipeline.stream_with_history(&request.question, &conversation));
+
+        while let Some(event) = events.next().await {
+            // The client disconnected; dropping the stream stops the run
+            if sender.unbounded_send(event).is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok(Sse::new(receiver.map(|event: PipelineEvent| {
+        let data = serde_json::to_value(&event).map_err(axum::Error::new)?;
+        let name = data["type"].as_str().unwrap_or("event").to_string();
+        Event::default().event(name).json_data(data)
+    }))
+    .keep_alive(KeepAlive::default()))
+}
+
+async fn list_runs<M: Model>(
+    State(pipeline): State<Arc<Pipeline<M>>>,
+    Query(query): Query<ListRuns>,
+) -> Result<Json<Vec<RunSummary>>, ApiError> {
+    let limit = query.limit.unwrap_or(DEFAULT_RUN_LIMIT);
+    if limit > MAX_RUN_LIMIT {
+        return Err(ApiError::LimitTooHigh(limit));
+    }
+
+    let store = pipeline.run_store().ok_or(ApiError::NoRunStore)?;
+    let runs = store.list(limit).await?;
+
+    Ok(Json(runs))
+}
+
+async fn get_run<M: Model>(
+    State(pipeline): State<Arc<Pipeline<M>>>,
+    Path(id): Path<String>,
+) -> Result<Json<RunTranscript>, ApiError> {
+    let store = pipeline.run_store().ok_or(ApiError::NoRunStore)?;
+
+    match store.load(&id).await? {
+        Some(run) => Ok(Json(run)),
+        None => Err(ApiError::RunNotFound(id)),
+    }
+}
+
+async fn sub_agents<M: Model>(State(pipeline): State<Arc<Pipeline<M>>>) -> Json<Vec<Value>> {
+    Json(
+        pipeline
+            .sub_agents()
+            .map(|sub_agent| {
+                json!({ "name": sub_agent.name(), "description": sub_agent.description() })
+            })
+            .collect(),
+    )
+}
+
+async fn tables<M: Model>(
+    State(pipeline): State<Arc<Pipeline<M>>>,
+) -> Result<Json<Vec<String>>, ApiError> {
+    let tool = SurrealSchemaTool::new(SurrealDbConfig::from(pipeline.surreal_config()));
+
+    Ok(Json(tool.list_tables().await?))
+}
+
+async fn health() -> Json<Value> {
+    Json(json!({ "status": "ok" }))
+}
+
+async fn ready<M: Model>(State(pipeline): State<Arc<Pipeline<M>>>) -> Response {
+    let config = SurrealDbConfig::from(pipeline.surreal_config());
+
+    match execute_query(&config, "RETURN true").await {
+        Ok(_) => Json(json!({ "status": "ready" })).into_response(),
+        Err(e) => {
+            tracing::warn!(error = %e, "readiness check failed");
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(json!({ "status": "unavailable", "error": e.to_string() })),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::{Body, to_bytes},
+        http::Request,
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        agents::{mock::ScriptedModel, model::Llm},
+        runs::RunStore,
+        testing::{customers_sub_agent, one_query_script, seeded_db},
+    };
+
+    async fn send(router: &Router, request: Request<Body>) -> (StatusCode, String) {
+        let response = router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    fn get(uri: &str) -> Request<Body> {
+        Request::get(uri).body(Body::empty()).unwrap()
+    }
+
+    fn post_json(uri: &str, body: Value) -> Request<Body> {
+        Request::post(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_ask_and_fetch_run() {
+        let dir = std::env::temp_dir().join(format!("server-runs-{}", std::process::id()));
+        let pipeline = Pipeline::with_llm(
+            Llm::new(one_query_script(), "mock"),
+            seeded_db("server-ask").await,
+        )
+        .sub_agent(customers_sub_agent())
+        .store(RunStore::Files(dir.clone()));
+        let router = router(pipeline);
+
+        let (status, body) = send(
+            &router,
+            post_json("/ask", json!({ "question": "Who pays the most?" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let result: PipelineResult = serde_json::from_str(&body).unwrap();
+        assert_eq!(result.answer, "Acme Corp (customers:acme) pays the most.");
+
+        let run_id = result.run_id.unwrap();
+        let (status, body) = send(&router, get(&format!("/runs/{run_id}"))).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let run: RunTranscript = serde_json::from_str(&body).unwrap();
+        assert_eq!(run.tool_calls.len(), 1);
+        assert_eq!(run.llm_calls.len(), 4);
+
+        let (status, _) = send(&router, get("/runs/01unknown")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, body) = send(&router, get(&format!("/runs?limit={MAX_RUN_LIMIT}"))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains(&run_id));
+
+        let (status, body) = send(&router, get("/runs?limit=1000000")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("exceeds the maximum of 100"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ask_stream_sends_events() {
+        let pipeline = Pipeline::with_llm(
+            Llm::new(one_query_script(), "mock"),
+            seeded_db("server-stream").await,
+        )
+        .sub_agent(customers_sub_agent());
+
+        let (status, body) = send(
+            &router(pipeline),
+            post_json("/ask/stream", json!({ "question": "Who pays the most?" })),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("event: plan_produced\n"));
+        assert!(body.contains("event: query_finished\n"));
+        assert!(
+            body.trim_end()
+                .rsplit("\n\n")
+                .next()
+                .unwrap()
+                .starts_with("event: done\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_metadata_routes() {
+        let pipeline = Pipeline::with_llm(
+            Llm::new(ScriptedModel::new(), "mock"),
+            seeded_db("server-metadata").await,
+        )
+        .sub_agent(customers_sub_agent());
+        let router = router(pipeline);
+
+        assert_eq!(send(&router, get("/health")).await.0, StatusCode::OK);
+        assert_eq!(send(&router, get("/ready")).await.0, StatusCode::OK);
+
+        let (_, body) = send(&router, get("/tables")).await;
+        assert_eq!(body, r#"["customers","feature_requests"]"#);
+
+        let (_, body) = send(&router, get("/sub-agents")).await;
+        assert!(body.contains(r#""name":"customers""#));
+
+        let (status, body) = send(&router, get("/runs")).await;
+        assert_eq!(status, StatusCode::NOT_IMPLEMENTED);
+        assert_eq!(body, r#"{"error":"No run store is configured"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_ask_refuses_long_text() {
+        let pipeline = Pipeline::with_llm(
+            Llm::new(ScriptedModel::new(), "mock"),
+            seeded_db("server-long").await,
+        )
+        .sub_agent(customers_sub_agent());
+        let router = router(pipeline);
+        let long = "x".repeat(MAX_TEXT_CHARS + 1);
+
+        let (status, _) = send(&router, post_json("/ask", json!({ "question": long }))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let history =
+            json!([{ "question": "Who pays the most?", "sub_questions": {}, "answer": long }]);
+        let (status, body) = send(
+            &router,
+            post_json(
+                "/ask/stream",
+                json!({ "question": "And the least?", "history": history }),
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("exceeds the limit"));
+    }
+
+    #[test]
+    fn test_ask_keeps_recent_history() {
+        let turn = |index: usize| Turn {
+            question: format!("question {index}"),
+            sub_questions: Default::default(),
+            answer: String::new(),
+        };
+        let mut request = AskRequest {
+            question: "And now?".to_string(),
+            history: (0..CONTEXT_TURNS + 3).map(turn).collect(),
+        };
+
+        let conversation = request.conversation().unwrap();
+
+        assert_eq!(conversation.turns().len(), CONTEXT_TURNS);
+        assert_eq!(conversation.turns()[0].question, "question 3");
+    }
+
+    #[tokio::test]
+    async fn test_ask_reports_model_errors() {
+        let pipeline = Pipeline::with_llm(
+            Llm::new(ScriptedModel::new().error("rate limited"), "mock"),
+            seeded_db("server-error").await,
+        )
+        .sub_agent(customers_sub_agent());
+
+        let (status, body) = send(
+            &router(pipeline),
+            post_json("/ask", json!({ "question": "Who pays the most?" })),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert!(body.contains("rate limited"));
+    }
+}
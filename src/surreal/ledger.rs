@@ -0,0 +1,265 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A single query executed by the select tool
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QueryRecord {
+    /// The query text as submitted by the agent
+    pub query: String,
+    /// IDs of the records returned by the query
+    pub record_ids: Vec<String>,
+    /// Rows returned by the query
+    #[serde(default)]
+    pub rows: Vec<Value>,
+    /// Error message if the query failed
+    pub error: Option<String>,
+    /// Time from [`QueryLedger::record_issued`] to the recorded outcome
+    #[serde(default)]
+    pub duration_ms: Option<u64>,
+}
+
+/// Activity reported to a ledger listener
+#[derive(Clone, Debug)]
+pub enum LedgerEvent {
+    /// A query is about to be executed
+    QueryIssued { query: String },
+    /// A query finished, successfully or not
+    QueryFinished(QueryRecord),
+}
+
+/// Callback notified of every query issued to and recorded by a ledger
+pub type LedgerListener = Arc<dyn Fn(&LedgerEvent) + Send + Sync>;
+
+/// Shared log of the queries executed by a select tool and the records they returned
+///
+/// Clones share the same underlying log, so a ledger can be handed to a tool
+/// that is moved into an agent and read back once the agent is done.
+#[derive(Clone, Default)]
+pub struct QueryLedger {
+    records: Arc<Mutex<Vec<QueryRecord>>>,
+    /// Queries issued and not yet recorded, with the time they were issued
+    pending: Arc<Mutex<Vec<(String, Instant)>>>,
+    listener: Option<LedgerListener>,
+}
+
+impl fmt::Debug for QueryLedger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QueryLedger")
+            .field("records", &self.records)
+            .field("listener", &self.listener.is_some())
+            .finish()
+    }
+}
+
+impl QueryLedger {
+    /// Create a new empty ledger
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Notify `listener` of every query issued and recorded
+    pub fn with_listener(mut self, listener: LedgerListener) -> Self {
+        self.listener = Some(listener);
+        self
+    }
+
+    /// Report that a query is about to be executed
+    pub fn record_issued(&self, query: &str) {
+        self.pending
+            .lock()
+            .expect("ledger lock poisoned")
+            .push((query.to_string(), Instant::now()));
+
+        if let Some(listener) = &self.listener {
+            listener(&LedgerEvent::QueryIssued {
+                query: query.to_string(),
+            });
+        }
+    }
+
+    /// Record a successful query and the rows it returned
+    pub fn record_result(&self, query: &str, result: &Value) {
+        let mut record_ids = Vec::new();
+        collect_record_ids(result, &mut record_ids);
+
+        let rows = match result {
+            Value::Array(arr) => arr.clone(),
+            Value::Null => Vec::new(),
+            other => vec![other.clone()],
+        };
+
+        self.push(QueryRecord {
+            query: query.to_string(),
+            record_ids,
+            rows,
+            error: None,
+            duration_ms: None,
+        });
+    }
+
+    /// Record a query that failed
+    pub fn record_error(&self, query: &str, error: &str) {
+        self.push(QueryRecord {
+            query: query.to_string(),
+            record_ids: Vec::new(),
+            rows: Vec::new(),
+            error: Some(error.to_string()),
+            duration_ms: None,
+        });
+    }
+
+    /// All queries recorded so far, in execution order
+    pub fn records(&self) -> Vec<QueryRecord> {
+        self.records.lock().expect("ledger lock poisoned").clone()
+    }
+
+    /// Every record ID returned by any recorded query
+    pub fn record_ids(&self) -> BTreeSet<String> {
+        self.records
+            .lock()
+            .expect("ledger lock poisoned")
+            .iter()
+            .flat_map(|record| record.record_ids.iter().cloned())
+            .collect()
+    }
+
+    /// Every row returned by any recorded query, keeping only the first copy of a record
+    pub fn rows(&self) -> Vec<Value> {
+        let mut seen = BTreeSet::new();
+
+        self.records
+            .lock()
+            .expect("ledger lock poisoned")
+            .iter()
+            .flat_map(|record| record.rows.iter())
+            .filter(|row| match row.get("id").and_then(Value::as_str) {
+                Some(id) => seen.insert(id.to_string()),
+                None => true,
+            })
+            .cloned()
+            .collect()
+    }
+
+    fn push(&self, mut record: QueryRecord) {
+        let mut pending = self.pending.lock().expect("ledger lock poisoned");
+        if let Some(index) = pending.iter().position(|(query, _)| *query == record.query) {
+            let (_, issued_at) = pending.remove(index);
+            record.duration_ms = Some(issued_at.elapsed().as_millis() as u64);
+        }
+        drop(pending);
+
+        if let Some(listener) = &self.listener {
+            listener(&LedgerEvent::QueryFinished(record.clone()));
+        }
+
+        self.records
+            .lock()
+            .expect("ledger lock poisoned")
+            .push(record);
+    }
+}
+
+/// Collect the `id` of every record (including nested, fetched records) in a query result
+fn collect_record_ids(value: &Value, ids: &mut Vec<String>) {
+    match value {
+        Value::Array(arr) => {
+            for item in arr {
+                collect_record_ids(item, ids);
+            }
+        }
+        Value::Object(obj) => {
+            for (key, field) in obj {
+                match field {
+                    Value::String(s) if key == "id" && s.contains(':') => {
+                        if !ids.contains(s) {
+                            ids.push(s.clone());
+                        }
+                    }
+                    _ => collect_record_ids(field, ids),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_result_collects_ids() {
+        let ledger = QueryLedger::new();
+        let result = serde_json::json!([
+            { "id": "customers:abc", "name": "Acme Corp" },
+            { "id": "customers:def", "owner": { "id": "users:1", "name": "Jane" } },
+            { "count": 2 }
+        ]);
+
+        ledger.record_result("SELECT * FROM customers", &result);
+
+        let records = ledger.records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(
+            records[0].record_ids,
+            vec!["customers:abc", "customers:def", "users:1"]
+        );
+        assert!(ledger.record_ids().contains("users:1"));
+    }
+
+    #[test]
+    fn test_rows_deduplicates_records() {
+        let ledger = QueryLedger::new();
+        ledger.record_result(
+            "SELECT * FROM customers",
+            &serde_json::json!([{ "id": "customers:abc" }, { "id": "customers:def" }]),
+        );
+        ledger.record_result(
+            "SELECT * FROM customers:abc",
+            &serde_json::json!({ "id": "customers:abc" }),
+        );
+
+        assert_eq!(ledger.rows().len(), 2);
+    }
+
+    #[test]
+    fn test_listener_is_notified() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let ledger = QueryLedger::new().with_listener(Arc::new(move |event| {
+            sink.lock().unwrap().push(event.clone());
+        }));
+
+        ledger.record_issued("SELECT * FROM customers");
+        ledger.record_result(
+            "SELECT * FROM customers",
+            &serde_json::json!([{ "id": "customers:abc" }]),
+        );
+
+        assert!(ledger.records()[0].duration_ms.is_some());
+
+        let events = events.lock().unwrap();
+        assert!(
+            matches!(&events[0], LedgerEvent::QueryIssued { query } if query == "SELECT * FROM customers")
+        );
+        assert!(matches!(&events[1], LedgerEvent::QueryFinished(record) if record.rows.len() == 1));
+    }
+
+    #[test]
+    fn test_clones_share_records() {
+        let ledger = QueryLedger::new();
+        let clone = ledger.clone();
+
+        clone.record_error("SELECT * FROM", "Parse error");
+
+        let records = ledger.records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].error.as_deref(), Some("Parse error"));
+        assert!(ledger.record_ids().is_empty());
+    }
+}
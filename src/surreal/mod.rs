@@ -1,13 +1,21 @@
 //! SurrealDB tools and utilities for the rig framework
 //!
 //! This module provides tools for interacting with SurrealDB databases,
-//! including schema inspection and query execution capabilities.
+//! including schema inspection, query execution, full-text search and
+//! vector similarity search capabilities.
 
+use std::collections::HashMap;
 use std::error::Error as StdError;
 use std::fmt;
+use std::sync::{Mutex, OnceLock};
 
+use surrealdb::{Surreal, engine::any::Any};
+
+pub mod ledger;
 pub mod schema;
+pub mod search;
 pub mod select;
+pub mod vector;
 
 /// Configuration for SurrealDB connection
 #[derive(Clone, Debug)]
@@ -32,6 +40,18 @@ impl SurrealDbConfig {
     }
 }
 
+impl From<&crate::config::SurrealConfig> for SurrealDbConfig {
+    fn from(config: &crate::config::SurrealConfig) -> Self {
+        Self::new(
+            config.host.clone(),
+            config.username.clone(),
+            config.password.clone(),
+            config.database.clone(),
+            config.namespace.clone(),
+        )
+    }
+}
+
 /// Common error type for SurrealDB operations
 #[derive(Debug)]
 pub enum SurrealError {
@@ -39,6 +59,7 @@ pub enum SurrealError {
     QueryError(String),
     SerializationError(serde_json::Error),
     InvalidInput(String),
+    EmbeddingError(String),
 }
 
 impl fmt::Display for SurrealError {
@@ -48,6 +69,7 @@ impl fmt::Display for SurrealError {
             SurrealError::QueryError(msg) => write!(f, "Query error: {msg}"),
             SurrealError::SerializationError(err) => write!(f, "Serialization error: {err}"),
             SurrealError::InvalidInput(msg) => write!(f, "Invalid input: {msg}"),
+            SurrealError::EmbeddingError(msg) => write!(f, "Embedding error: {msg}"),
         }
     }
 }
@@ -60,15 +82,75 @@ impl From<serde_json::Error> for SurrealError {
     }
 }
 
-/// Common database connection and query execution functionality
-pub(crate) async fn execute_query(
-    config: &SurrealDbConfig,
-    query: &str,
-) -> Result<serde_json::Value, SurrealError> {
-    use surrealdb::{Surreal, engine::remote::ws::Wss};
+/// Embedded databases of this process, by URL, namespace and database
+type MemoryKey = (String, String, String);
+
+fn memory() -> &'static Mutex<HashMap<MemoryKey, Surreal<Any>>> {
+    static MEMORY: OnceLock<Mutex<HashMap<MemoryKey, Surreal<Any>>>> = OnceLock::new();
+    MEMORY.get_or_init(Default::default)
+}
+
+fn memory_key(config: &SurrealDbConfig) -> MemoryKey {
+    (
+        config.url.clone(),
+        config.namespace.clone(),
+        config.db.clone(),
+    )
+}
+
+/// Connect to the configured database
+///
+/// URLs without a scheme are SurrealDB Cloud hosts reached over `wss://`.
+/// `mem://` databases live in this process: every connection with the same
+/// URL, namespace and database shares one embedded database, so data
+/// written by one query is seen by the next, until [`release`] drops it.
+/// An embedded database dies with the tokio runtime that opened it; the
+/// next connection from another runtime opens an empty one.
+pub(crate) async fn connect(config: &SurrealDbConfig) -> Result<Surreal<Any>, SurrealError> {
+    if config.url.starts_with("mem://") {
+        let key = memory_key(config);
+
+        let cached = memory()
+            .lock()
+            .expect("memory databases lock poisoned")
+            .get(&key)
+            .cloned();
+        if let Some(db) = &cached {
+            if db.health().await.is_ok() {
+                return Ok(db.clone());
+            }
+            tracing::debug!(url = %config.url, "replacing embedded database of a stopped runtime");
+        }
+
+        // Embedded databases have no users to sign in as
+        let db = surrealdb::engine::any::connect("mem://")
+            .await
+            .map_err(|e| SurrealError::ConnectionError(e.to_string()))?;
+        db.use_ns(&config.namespace)
+            .use_db(&config.db)
+            .await
+            .map_err(|e| SurrealError::ConnectionError(e.to_string()))?;
+
+        let mut memory = memory().lock().expect("memory databases lock poisoned");
+        // A concurrent connection may have opened the database first
+        let db = match memory.get(&key) {
+            Some(existing) if cached.is_none() => existing.clone(),
+            _ => {
+                memory.insert(key, db.clone());
+                db
+            }
+        };
+        return Ok(db);
+    }
+
+    let url = if config.url.contains("://") {
+        config.url.clone()
+    } else {
+        format!("wss://{}", config.url)
+    };
 
     // Connect to SurrealDB
-    let db = Surreal::new::<Wss>(&config.url)
+    let db = surrealdb::engine::any::connect(url)
         .await
         .map_err(|e| SurrealError::ConnectionError(e.to_string()))?;
 
@@ -86,9 +168,40 @@ pub(crate) async fn execute_query(
         .await
         .map_err(|e| SurrealError::ConnectionError(e.to_string()))?;
 
+    Ok(db)
+}
+
+/// Drop the embedded database of a `mem://` configuration, freeing its data
+/// once the connections still using it are gone; other URLs are left alone
+pub fn release(config: &SurrealDbConfig) {
+    if config.url.starts_with("mem://") {
+        memory()
+            .lock()
+            .expect("memory databases lock poisoned")
+            .remove(&memory_key(config));
+    }
+}
+
+/// Execute `query` and return the result of its first statement as JSON
+pub async fn execute_query(
+    config: &SurrealDbConfig,
+    query: &str,
+) -> Result<serde_json::Value, SurrealError> {
+    execute_bound(config, query, serde_json::json!({})).await
+}
+
+/// Execute a query with `bindings`, an object of `$variables`
+pub(crate) async fn execute_bound(
+    config: &SurrealDbConfig,
+    query: &str,
+    bindings: serde_json::Value,
+) -> Result<serde_json::Value, SurrealError> {
+    let db = connect(config).await?;
+
     // Execute the query
     let mut result = db
         .query(query)
+        .bind(bindings)
         .await
         .map_err(|e| SurrealError::QueryError(e.to_string()))?;
 
@@ -97,13 +210,106 @@ pub(crate) async fn execute_query(
         .take(0)
         .map_err(|e| SurrealError::QueryError(e.to_string()))?;
 
-    // Convert to serde_json::Value
-    let json_value =
-        serde_json::to_value(query_result).map_err(SurrealError::SerializationError)?;
+    // Convert to plain JSON so record IDs render as `table:id` strings
+    Ok(query_result.into_inner().into_json())
+}
+
+/// Execute every statement of `query` with `bindings`, failing if any of them fails
+pub(crate) async fn execute_all(
+    config: &SurrealDbConfig,
+    query: &str,
+    bindings: impl serde::Serialize + 'static,
+) -> Result<(), SurrealError> {
+    let response = connect(config)
+        .await?
+        .query(query)
+        .bind(bindings)
+        .await
+        .map_err(|e| SurrealError::QueryError(e.to_string()))?;
+
+    response
+        .check()
+        .map_err(|e| SurrealError::QueryError(e.to_string()))?;
+
+    Ok(())
+}
+
+/// A SurrealQL identifier, escaped with backticks unless it is plain
+pub(crate) fn escape(ident: &str) -> String {
+    let plain = ident
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && ident.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
 
-    Ok(json_value)
+    if plain {
+        ident.to_string()
+    } else {
+        format!("`{}`", ident.replace('\\', "\\\\").replace('`', "\\`"))
+    }
 }
 
 // Re-export the tools for convenience
+pub use ledger::{LedgerEvent, LedgerListener, QueryLedger};
 pub use schema::SurrealSchemaTool;
+pub use search::{SearchField, SurrealSearchTool, define_search_index};
 pub use select::SurrealSelectTool;
+pub use vector::{Embedder, SurrealVectorSearchTool, embed_field};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory_config(name: &str, db: &str) -> SurrealDbConfig {
+        SurrealDbConfig::new(
+            format!("mem://{name}"),
+            String::new(),
+            String::new(),
+            db.to_string(),
+            "test".to_string(),
+        )
+    }
+
+    async fn count(config: &SurrealDbConfig) -> usize {
+        execute_query(config, "SELECT * FROM notes")
+            .await
+            .unwrap()
+            .as_array()
+            .map_or(0, Vec::len)
+    }
+
+    #[tokio::test]
+    async fn test_memory_databases_are_keyed_by_database() {
+        let first = memory_config("connect-keyed", "first");
+        let second = memory_config("connect-keyed", "second");
+
+        execute_query(&first, "CREATE notes:a").await.unwrap();
+
+        assert_eq!(count(&first).await, 1);
+        assert_eq!(count(&second).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_release_drops_memory_database() {
+        let config = memory_config("connect-release", "test");
+        execute_query(&config, "CREATE notes:a").await.unwrap();
+
+        release(&config);
+
+        assert_eq!(count(&config).await, 0);
+    }
+
+    #[test]
+    fn test_memory_database_dies_with_its_runtime() {
+        let config = memory_config("connect-runtime", "test");
+        let runtime = || tokio::runtime::Runtime::new().unwrap();
+
+        runtime().block_on(async {
+            execute_query(&config, "CREATE notes:a").await.unwrap();
+        });
+
+        runtime().block_on(async {
+            assert_eq!(count(&config).await, 0);
+        });
+    }
+}
@@ -1,8 +1,9 @@
 use rig::{completion::ToolDefinition, tool::Tool};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use tracing::field;
 
-use super::{SurrealDbConfig, SurrealError, execute_query};
+use super::{SurrealDbConfig, SurrealError, escape, execute_query};
 
 /// Arguments for the SurrealDB schema tool
 #[derive(Deserialize, Serialize)]
@@ -59,6 +60,49 @@ impl SurrealSchemaTool {
         Ok(schema)
     }
 
+    /// Names of the tables of the database
+    pub async fn list_tables(&self) -> Result<Vec<String>, SurrealError> {
+        let info = execute_query(&self.config, "INFO FOR DB").await?;
+
+        Ok(info
+            .get("tables")
+            .and_then(Value::as_object)
+            .map(|tables| tables.keys().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    /// SurrealQL statements defining a table and its fields, indexes and events
+    pub async fn definitions(&self, table_name: &str) -> Result<Vec<String>, SurrealError> {
+        let db_info = execute_query(&self.config, "INFO FOR DB").await?;
+        let table = db_info
+            .get("tables")
+            .and_then(|tables| tables.get(table_name))
+            .and_then(Value::as_str)
+            .ok_or_else(|| {
+                SurrealError::InvalidInput(format!("Table {table_name} does not exist"))
+            })?;
+
+        let table_info = execute_query(
+            &self.config,
+            &format!("INFO FOR TABLE {}", escape(table_name)),
+        )
+        .await?;
+
+        let mut definitions = vec![table.to_string()];
+        for section in ["fields", "indexes", "events"] {
+            let statements = table_info.get(section).and_then(Value::as_object);
+            definitions.extend(
+                statements
+                    .into_iter()
+                    .flat_map(|statements| statements.values())
+                    .filter_map(Value::as_str)
+                    .map(str::to_string),
+            );
+        }
+
+        Ok(definitions)
+    }
+
     /// Parse SurrealDB INFO TABLE response into structured column information
     fn _parse_table_info(
         &self,
@@ -156,6 +200,11 @@ impl Tool for SurrealSchemaTool {
         }
     }
 
+    #[tracing::instrument(
+        name = "tool_call",
+        skip_all,
+        fields(tool = Self::NAME, table = %args.table_name, error = field::Empty)
+    )]
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
         // Validate table name
         if args.table_name.trim().is_empty() {
@@ -164,13 +213,15 @@ impl Tool for SurrealSchemaTool {
             ));
         }
 
-        println!("Getting schema information for table: {}", args.table_name);
-
         // Construct the INFO TABLE query
         let query = format!("INFO FOR TABLE {}", args.table_name);
 
         // Execute the query using the shared function
-        let result = execute_query(&self.config, &query).await?;
+        let result = execute_query(&self.config, &query).await.inspect_err(|e| {
+            tracing::Span::current().record("error", field::display(e));
+            tracing::warn!(error = %e, "schema lookup failed");
+        })?;
+        tracing::info!("schema retrieved");
 
         // For now, just return the raw JSON result
         // TODO: Uncomment and fix the parsing logic below when needed
@@ -236,4 +287,22 @@ mod tests {
         assert!(result.is_err());
         assert!(matches!(result.unwrap_err(), SurrealError::InvalidInput(_)));
     }
+
+    #[tokio::test]
+    async fn test_list_tables_and_definitions() {
+        let config = SurrealDbConfig::from(&crate::testing::seeded_db("schema-definitions").await);
+        let tool = SurrealSchemaTool::new(config);
+
+        assert_eq!(
+            tool.list_tables().await.unwrap(),
+            ["customers", "feature_requests"]
+        );
+
+        let definitions = tool.definitions("customers").await.unwrap();
+        assert!(definitions[0].starts_with("DEFINE TABLE customers"));
+        assert!(matches!(
+            tool.definitions("missing").await,
+            Err(SurrealError::InvalidInput(_))
+        ));
+    }
 }
@@ -0,0 +1,325 @@
+//! Full-text search over text fields with BM25 ranking
+//!
+//! [`define_search_index`] provisions the analyzer and a `SEARCH` index for
+//! a field; [`SurrealSearchTool`] matches terms against an indexed field
+//! with the `@@` operator, ranking the rows by `search::score` and marking
+//! the matched terms with `search::highlight`.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Instant;
+
+use rig::{completion::ToolDefinition, tool::Tool};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, json};
+use tracing::field;
+
+use super::{
+    QueryLedger, SurrealDbConfig, SurrealError, SurrealSelectTool, escape, execute_all,
+    execute_bound,
+};
+
+/// Analyzer of every search index: words are split on class changes,
+/// lowercased, stripped of accents and stemmed, so `Exports` matches `export`
+pub const SEARCH_ANALYZER: &str = "text_search";
+
+/// Rows returned by a search unless the model asks for another limit
+pub const DEFAULT_SEARCH_LIMIT: usize = 10;
+
+/// Most rows a search returns, whatever limit the model asks for
+pub const MAX_SEARCH_LIMIT: usize = 100;
+
+/// A text field to index for full-text search, written `table.field`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchField {
+    pub table: String,
+    pub field: String,
+}
+
+impl SearchField {
+    /// Statements defining the analyzer and the search index of the field
+    pub fn define_statements(&self) -> Vec<String> {
+        vec![
+            format!(
+                "DEFINE ANALYZER IF NOT EXISTS {SEARCH_ANALYZER} TOKENIZERS class FILTERS lowercase, ascii, snowball(english);"
+            ),
+            format!(
+                "DEFINE INDEX IF NOT EXISTS {} ON TABLE {} FIELDS {} SEARCH ANALYZER {SEARCH_ANALYZER} BM25 HIGHLIGHTS;",
+                escape(&format!("{}_{}_search", self.table, self.field)),
+                escape(&self.table),
+                escape(&self.field),
+            ),
+        ]
+    }
+}
+
+impl FromStr for SearchField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().split_once('.') {
+            Some((table, field)) if !table.is_empty() && !field.is_empty() => Ok(Self {
+                table: table.to_string(),
+                field: field.to_string(),
+            }),
+            _ => Err(format!("expected `table.field`, found `{s}`")),
+        }
+    }
+}
+
+impl fmt::Display for SearchField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.table, self.field)
+    }
+}
+
+/// Define the analyzer and the search index of `field`, indexing the rows
+/// it already has
+pub async fn define_search_index(
+    config: &SurrealDbConfig,
+    field: &SearchField,
+) -> Result<(), SurrealError> {
+    execute_all(config, &field.define_statements().join("\n"), Map::new()).await?;
+    tracing::info!(%field, "search index defined");
+
+    Ok(())
+}
+
+/// Arguments for the SurrealDB search tool
+#[derive(Deserialize, Serialize)]
+pub struct SurrealSearchArgs {
+    /// Table to search
+    pub table: String,
+    /// Text field with a search index
+    pub field: String,
+    /// Words to look for
+    pub terms: String,
+    /// Maximum number of rows to return
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+impl SurrealSearchArgs {
+    /// The `SELECT` statement matching the field against `$terms`
+    fn statement(&self) -> String {
+        format!(
+            "SELECT *, search::score(0) AS score, search::highlight('**', '**', 0) AS highlight FROM {} WHERE {} @0@ $terms ORDER BY score DESC LIMIT {}",
+            escape(&self.table),
+            escape(&self.field),
+            self.limit
+                .unwrap_or(DEFAULT_SEARCH_LIMIT)
+                .clamp(1, MAX_SEARCH_LIMIT),
+        )
+    }
+}
+
+/// SurrealDB Search Tool ranking the rows of a table by how well a text
+/// field matches search terms
+#[derive(Clone)]
+pub struct SurrealSearchTool {
+    config: SurrealDbConfig,
+    ledger: Option<QueryLedger>,
+}
+
+impl SurrealSearchTool {
+    pub fn new(config: SurrealDbConfig) -> Self {
+        Self {
+            config,
+            ledger: None,
+        }
+    }
+
+    /// Record every search and the record IDs it returned in the given ledger
+    pub fn with_ledger(mut self, ledger: QueryLedger) -> Self {
+        self.ledger = Some(ledger);
+        self
+    }
+}
+
+impl Tool for SurrealSearchTool {
+    const NAME: &'static str = "surreal_search";
+
+    type Error = SurrealError;
+    type Args = SurrealSearchArgs;
+    type Output = String;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "Full-text search of a text field with a search index (see the indexes in surreal_schema). Matches words regardless of case and word endings, and returns the matching rows ranked by relevance, with a `score` and a `highlight` of the field marking the matched words in **bold**. Prefer it to CONTAINS for finding rows by what their text is about; search for synonyms and abbreviations separately (e.g. `2FA`, then `two-factor authentication`).".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "table": {
+                        "type": "string",
+                        "description": "Table to search"
+                    },
+                    "field": {
+                        "type": "string",
+                        "description": "Text field with a search index"
+                    },
+                    "terms": {
+                        "type": "string",
+                        "description": "Words to look for; every word must match"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": format!("Maximum number of rows to return (default {DEFAULT_SEARCH_LIMIT}, at most {MAX_SEARCH_LIMIT})")
+                    }
+                },
+                "required": ["table", "field", "terms"]
+            }),
+        }
+    }
+
+    #[tracing::instrument(
+        name = "tool_call",
+        skip_all,
+        fields(
+            tool = Self::NAME,
+            query = field::Empty,
+            rows = field::Empty,
+            duration_ms = field::Empty,
+            error = field::Empty,
+        )
+    )]
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let span = tracing::Span::current();
+
+        if args.terms.trim().is_empty() {
+            return Ok("Search validation error: terms cannot be empty".to_string());
+        }
+
+        let statement = args.statement();
+        span.record("query", &statement);
+
+        // The ledger shows the search as the statement and the terms it matched
+        let query = format!("{statement} -- $terms: {}", args.terms);
+        if let Some(ledger) = &self.ledger {
+            ledger.record_issued(&query);
+        }
+
+        let started = Instant::now();
+        let result = execute_bound(&self.config, &statement, json!({ "terms": args.terms })).await;
+        span.record("duration_ms", started.elapsed().as_millis() as u64);
+
+        match result {
+            Ok(result) => {
+                let rows = result.as_array().map_or(0, Vec::len);
+                span.record("rows", rows);
+                tracing::info!(rows, "search finished");
+
+                if let Some(ledger) = &self.ledger {
+                    ledger.record_result(&query, &result);
+                }
+
+                Ok(SurrealSelectTool::new(self.config.clone()).format_result(&result))
+            }
+            Err(e) => {
+                span.record("error", field::display(&e));
+                tracing::warn!(error = %e, "search failed");
+
+                if let Some(ledger) = &self.ledger {
+                    ledger.record_error(&query, &e.to_string());
+                }
+
+                // Returned as a successful response so the LLM can pick another field
+                Ok(format!(
+                    "Search error: {e}\n\nCheck that the field has a search index with surreal_schema, or use surreal_select."
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::seeded_db;
+
+    async fn search(db: &str, terms: &str) -> (String, QueryLedger) {
+        let config = SurrealDbConfig::from(&seeded_db(db).await);
+        let field = "feature_requests.message".parse::<SearchField>().unwrap();
+        define_search_index(&config, &field).await.unwrap();
+
+        let ledger = QueryLedger::new();
+        let output = SurrealSearchTool::new(config)
+            .with_ledger(ledger.clone())
+            .call(SurrealSearchArgs {
+                table: "feature_requests".to_string(),
+                field: "message".to_string(),
+                terms: terms.to_string(),
+                limit: None,
+            })
+            .await
+            .unwrap();
+
+        (output, ledger)
+    }
+
+    #[test]
+    fn test_parse_search_field() {
+        assert_eq!(
+            "customers.notes"
+                .parse::<SearchField>()
+                .unwrap()
+                .to_string(),
+            "customers.notes"
+        );
+        assert!("customers".parse::<SearchField>().is_err());
+        assert!(".notes".parse::<SearchField>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_search_ranks_and_highlights_matches() {
+        let (output, ledger) = search("search-match", "Exports").await;
+
+        assert!(output.contains("Found 1 record(s)"));
+        assert!(output.contains("**export**"));
+        assert!(output.contains("score:"));
+        assert_eq!(
+            ledger.record_ids(),
+            ["feature_requests:export".to_string()].into()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_binds_terms() {
+        let (output, ledger) = search("search-bound", "export' OR true OR '").await;
+
+        assert!(output.starts_with("No results found."));
+        assert!(ledger.record_ids().is_empty());
+    }
+
+    #[test]
+    fn test_search_clamps_limit() {
+        let args = SurrealSearchArgs {
+            table: "feature_requests".to_string(),
+            field: "message".to_string(),
+            terms: "export".to_string(),
+            limit: Some(1_000_000),
+        };
+
+        assert!(
+            args.statement()
+                .ends_with(&format!("LIMIT {MAX_SEARCH_LIMIT}"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_reports_unindexed_fields() {
+        let config = SurrealDbConfig::from(&seeded_db("search-unindexed").await);
+
+        let output = SurrealSearchTool::new(config)
+            .call(SurrealSearchArgs {
+                table: "customers".to_string(),
+                field: "name".to_string(),
+                terms: "acme".to_string(),
+                limit: Some(1),
+            })
+            .await
+            .unwrap();
+
+        assert!(output.starts_with("Search error"));
+    }
+}
@@ -1,8 +1,11 @@
+use std::time::Instant;
+
 use rig::{completion::ToolDefinition, tool::Tool};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use tracing::field;
 
-use super::{SurrealDbConfig, SurrealError, execute_query};
+use super::{QueryLedger, SurrealDbConfig, SurrealError, execute_query};
 
 /// Arguments for the SurrealDB select tool
 #[derive(Deserialize, Serialize)]
@@ -15,16 +18,26 @@ pub struct SurrealSelectArgs {
 #[derive(Clone)]
 pub struct SurrealSelectTool {
     config: SurrealDbConfig,
+    ledger: Option<QueryLedger>,
 }
 
 impl SurrealSelectTool {
     /// Create a new SurrealDB select tool with the provided configuration
     pub fn new(config: SurrealDbConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            ledger: None,
+        }
+    }
+
+    /// Record every executed query and the record IDs it returned in the given ledger
+    pub fn with_ledger(mut self, ledger: QueryLedger) -> Self {
+        self.ledger = Some(ledger);
+        self
     }
 
     /// Format the query result as a readable text output
-    fn format_result(&self, result: &Value) -> String {
+    pub(super) fn format_result(&self, result: &Value) -> String {
         match result {
             Value::Array(arr) => {
                 if arr.is_empty() {
@@ -107,38 +120,119 @@ impl SurrealSelectTool {
         }
     }
 
-    /// Validate that the query is a SELECT statement
-    fn validate_query(&self, query: &str) -> Result<(), SurrealError> {
-        let trimmed = query.trim().to_lowercase();
-
-        if trimmed.is_empty() {
+    /// Validate that the query is a single read-only SELECT statement
+    ///
+    /// Keywords are matched as whole words outside strings, escaped
+    /// identifiers and comments, so fields such as `created_at` pass.
+    pub fn validate_query(&self, query: &str) -> Result<(), SurrealError> {
+        if query.trim().is_empty() {
             return Err(SurrealError::InvalidInput(
                 "Query cannot be empty".to_string(),
             ));
         }
 
-        if !trimmed.starts_with("select") {
+        let tokens = tokens(query);
+        if tokens.first().map(String::as_str) != Some("select") {
             return Err(SurrealError::InvalidInput(
                 "Only SELECT statements are allowed".to_string(),
             ));
         }
 
         // Basic validation to prevent dangerous operations
-        let dangerous_keywords = [
-            "drop", "delete", "update", "insert", "create", "alter", "truncate",
-        ];
-        for keyword in dangerous_keywords {
-            if trimmed.contains(keyword) {
+        for keyword in FORBIDDEN_KEYWORDS {
+            if tokens.iter().any(|token| token == keyword) {
                 return Err(SurrealError::InvalidInput(format!(
                     "Query contains forbidden keyword: {keyword}"
                 )));
             }
         }
 
+        // Only separators may follow the first one
+        if let Some(end) = tokens.iter().position(|token| token == ";")
+            && tokens[end..].iter().any(|token| token != ";")
+        {
+            return Err(SurrealError::InvalidInput(
+                "Only a single statement is allowed".to_string(),
+            ));
+        }
+
         Ok(())
     }
 }
 
+/// Words a read-only query may not contain: statements that write or change
+/// the session, and functions with side effects
+const FORBIDDEN_KEYWORDS: &[&str] = &[
+    "alter", "begin", "cancel", "commit", "create", "define", "delete", "drop", "http", "insert",
+    "kill", "let", "live", "rebuild", "relate", "remove", "sleep", "truncate", "update", "upsert",
+    "use",
+];
+
+/// Lowercased words and `;` separators of a query, skipping strings,
+/// escaped identifiers and comments
+fn tokens(query: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' | '`' => {
+                while let Some(next) = chars.next() {
+                    match next {
+                        '\\' => {
+                            chars.next();
+                        }
+                        next if next == c => break,
+                        _ => {}
+                    }
+                }
+            }
+            '⟨' => {
+                for next in chars.by_ref() {
+                    if next == '⟩' {
+                        break;
+                    }
+                }
+            }
+            '#' => skip_line(&mut chars),
+            '-' | '/' if chars.peek() == Some(&c) => skip_line(&mut chars),
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for next in chars.by_ref() {
+                    if previous == '*' && next == '/' {
+                        break;
+                    }
+                    previous = next;
+                }
+            }
+            ';' => tokens.push(";".to_string()),
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut word = c.to_lowercase().to_string();
+                while let Some(&next) = chars.peek() {
+                    if !(next.is_alphanumeric() || next == '_') {
+                        break;
+                    }
+                    word.extend(next.to_lowercase());
+                    chars.next();
+                }
+                tokens.push(word);
+            }
+            _ => {}
+        }
+    }
+
+    tokens
+}
+
+fn skip_line(chars: &mut impl Iterator<Item = char>) {
+    for next in chars.by_ref() {
+        if next == '\n' {
+            break;
+        }
+    }
+}
+
 impl Tool for SurrealSelectTool {
     const NAME: &'static str = "surreal_select";
 
@@ -167,22 +261,62 @@ impl Tool for SurrealSelectTool {
         }
     }
 
+    #[tracing::instrument(
+        name = "tool_call",
+        skip_all,
+        fields(
+            tool = Self::NAME,
+            query = %args.query,
+            rows = field::Empty,
+            duration_ms = field::Empty,
+            error = field::Empty,
+        )
+    )]
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let span = tracing::Span::current();
+
         // Validate the query
         if let Err(e) = self.validate_query(&args.query) {
+            span.record("error", field::display(&e));
+            tracing::warn!(error = %e, "query rejected");
             return Ok(format!("Query validation error: {e}"));
         }
 
-        println!("query: {}", args.query);
+        if let Some(ledger) = &self.ledger {
+            ledger.record_issued(&args.query);
+        }
 
         // Execute the query using the shared function
-        match execute_query(&self.config, &args.query).await {
+        let started = Instant::now();
+        let result = execute_query(&self.config, &args.query).await;
+        span.record("duration_ms", started.elapsed().as_millis() as u64);
+
+        match result {
             Ok(result) => {
+                let rows = match &result {
+                    Value::Array(rows) => rows.len(),
+                    Value::Null => 0,
+                    _ => 1,
+                };
+                span.record("rows", rows);
+                tracing::info!(rows, "query finished");
+
+                if let Some(ledger) = &self.ledger {
+                    ledger.record_result(&args.query, &result);
+                }
+
                 // Format and return the result as text
                 let formatted_output = self.format_result(&result);
                 Ok(formatted_output)
             }
             Err(e) => {
+                span.record("error", field::display(&e));
+                tracing::warn!(error = %e, "query failed");
+
+                if let Some(ledger) = &self.ledger {
+                    ledger.record_error(&args.query, &e.to_string());
+                }
+
                 // Return query errors as successful responses so the LLM can see them and correct the query
                 Ok(format!(
                     "Query execution error: {e}\n\nPlease check your SQL syntax and try again."
@@ -294,6 +428,44 @@ mod tests {
         assert!(response.contains("Query contains forbidden keyword"));
     }
 
+    #[test]
+    fn test_validate_query_matches_whole_statements() {
+        let tool = SurrealSelectTool::new(SurrealDbConfig::new(
+            "ws://localhost:8000".to_string(),
+            "root".to_string(),
+            "root".to_string(),
+            "test".to_string(),
+            "test".to_string(),
+        ));
+        let error = |query: &str| tool.validate_query(query).unwrap_err().to_string();
+
+        assert!(
+            tool.validate_query("SELECT id, created_at FROM feature_requests ORDER BY created_at;")
+                .is_ok()
+        );
+        assert!(
+            tool.validate_query(
+                "SELECT * FROM feature_requests WHERE message CONTAINS 'please delete; remove it' -- update"
+            )
+            .is_ok()
+        );
+        assert!(error("SELECT 1; SELECT 2").contains("Only a single statement is allowed"));
+        assert!(error("SELECT 1; REMOVE TABLE customers").contains("forbidden keyword: remove"));
+        assert!(
+            error("select 1; DEFINE USER x ON ROOT PASSWORD 'p' ROLES OWNER")
+                .contains("forbidden keyword: define")
+        );
+        assert!(
+            error("SELECT * FROM (UPSERT customers:evil SET arr = 0)")
+                .contains("forbidden keyword: upsert")
+        );
+        assert!(
+            error("SELECT http::post('https://example.com', 1) FROM ONLY 1")
+                .contains("forbidden keyword: http")
+        );
+        assert!(error("/* SELECT */ REMOVE TABLE customers").contains("Only SELECT"));
+    }
+
     #[test]
     fn test_format_value() {
         let config = SurrealDbConfig::new(
@@ -0,0 +1,511 @@
+//! Vector similarity search over embeddings of text fields
+//!
+//! [`embed_field`] stores an embedding of a text field next to it in every
+//! row, re-embedding only the rows whose text changed since the last run,
+//! and defines an HNSW index on the embeddings. [`SurrealVectorSearchTool`]
+//! embeds a query with the same model and returns the nearest rows with
+//! their cosine similarity.
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use rig::{
+    completion::ToolDefinition,
+    embeddings::{EmbeddingModel, embedding::EmbeddingModelDyn},
+    tool::Tool,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tracing::field;
+
+use super::{
+    QueryLedger, SearchField, SurrealDbConfig, SurrealError, SurrealSelectTool, escape,
+    execute_all, execute_bound, execute_query,
+};
+
+/// Rows returned by a vector search unless the model asks for another number
+pub const DEFAULT_NEIGHBOURS: usize = 5;
+
+/// Candidates the HNSW index considers per search
+const SEARCH_EF: usize = 40;
+
+/// Embedding model used to embed both the rows and the queries
+#[derive(Clone)]
+pub struct Embedder {
+    name: String,
+    model: Arc<dyn EmbeddingModelDyn>,
+}
+
+impl Embedder {
+    /// Embed with `model`, named `name`; rows embedded under another name
+    /// are embedded again
+    pub fn new(name: impl Into<String>, model: impl EmbeddingModel + 'static) -> Self {
+        Self {
+            name: name.into(),
+            model: Arc::new(model),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f64>, SurrealError> {
+        let model = self.model.clone();
+        let text = text.to_string();
+
+        // Tools must return `Sync` futures, which the boxed future of the
+        // model is not; its task handle is
+        let embedding = tokio::spawn(async move { model.embed_text(&text).await })
+            .await
+            .map_err(|e| SurrealError::EmbeddingError(e.to_string()))?
+            .map_err(|e| SurrealError::EmbeddingError(e.to_string()))?;
+
+        Ok(embedding.vec)
+    }
+
+    async fn embed_all(&self, texts: Vec<String>) -> Result<Vec<Vec<f64>>, SurrealError> {
+        let mut vectors = Vec::with_capacity(texts.len());
+
+        for chunk in texts.chunks(self.model.max_documents().max(1)) {
+            let embeddings = self
+                .model
+                .embed_texts(chunk.to_vec())
+                .await
+                .map_err(|e| SurrealError::EmbeddingError(e.to_string()))?;
+            vectors.extend(embeddings.into_iter().map(|embedding| embedding.vec));
+        }
+
+        Ok(vectors)
+    }
+}
+
+/// Field holding the embedding of `field`
+pub(crate) fn embedding_field(field: &str) -> String {
+    format!("{field}_embedding")
+}
+
+/// Field holding a hash of the model and the text the embedding of `field`
+/// was computed from
+fn hash_field(field: &str) -> String {
+    format!("{field}_embedding_hash")
+}
+
+/// Name of the HNSW index on the embeddings of `field`
+fn index_name(field: &SearchField) -> String {
+    format!("{}_{}_vector", field.table, field.field)
+}
+
+/// FNV-1a hash of a text, stable across runs and Rust versions
+fn text_hash(text: &str) -> String {
+    let hash = text.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    });
+
+    format!("{hash:016x}")
+}
+
+/// Statements defining the embedding fields of `field` and their HNSW index
+pub fn vector_index_statements(field: &SearchField, dimensions: usize) -> Vec<String> {
+    let table = escape(&field.table);
+    let embedding = embedding_field(&field.field);
+
+    vec![
+        format!(
+            "DEFINE FIELD IF NOT EXISTS {} ON TABLE {table} TYPE option<array<float>>;",
+            escape(&embedding)
+        ),
+        format!(
+            "DEFINE FIELD IF NOT EXISTS {} ON TABLE {table} TYPE option<string>;",
+            escape(&hash_field(&field.field))
+        ),
+        format!(
+            "DEFINE INDEX IF NOT EXISTS {} ON TABLE {table} FIELDS {} HNSW DIMENSION {dimensions} DIST COSINE;",
+            escape(&index_name(field)),
+            escape(&embedding)
+        ),
+    ]
+}
+
+/// What [`embed_field`] did
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EmbedReport {
+    pub field: SearchField,
+    /// Rows with a text in the field
+    pub rows: usize,
+    /// Rows whose text was new or changed, and was embedded
+    pub embedded: usize,
+}
+
+/// Embed the text of `field` in every row that has none, or whose text
+/// changed since it was embedded, and index the embeddings
+#[tracing::instrument(name = "embed", skip_all, fields(field = %field))]
+pub async fn embed_field(
+    config: &SurrealDbConfig,
+    embedder: &Embedder,
+    field: &SearchField,
+) -> Result<EmbedReport, SurrealError> {
+    let query = format!(
+        "SELECT id, {text} AS text, {hash} AS hash FROM {table} WHERE type::is::string({text})",
+        text = escape(&field.field),
+        hash = escape(&hash_field(&field.field)),
+        table = escape(&field.table),
+    );
+    let rows = execute_query(config, &query).await?;
+    let rows = rows.as_array().map(Vec::as_slice).unwrap_or_default();
+
+    let stale: Vec<(&Value, &str, String)> = rows
+        .iter()
+        .filter_map(|row| {
+            let text = row["text"].as_str()?;
+            // A model of another name gives other embeddings of the same text
+            let hash = text_hash(&format!("{}\0{text}", embedder.name()));
+            (row["hash"].as_str() != Some(hash.as_str())).then_some((&row["id"], text, hash))
+        })
+        .collect();
+
+    let report = EmbedReport {
+        field: field.clone(),
+        rows: rows.len(),
+        embedded: stale.len(),
+    };
+
+    if stale.is_empty() {
+        tracing::info!(rows = report.rows, "embeddings up to date");
+        return Ok(report);
+    }
+
+    let vectors = embedder
+        .embed_all(stale.iter().map(|(_, text, _)| text.to_string()).collect())
+        .await?;
+    let dimensions = vectors.first().map_or(0, Vec::len);
+    if vectors.iter().any(|vector| vector.len() != dimensions) {
+        return Err(SurrealError::EmbeddingError(format!(
+            "model {} returned embeddings of different dimensions",
+            embedder.name()
+        )));
+    }
+    if let Some(indexed) = index_dimension(config, field).await?
+        && indexed != dimensions
+    {
+        return Err(SurrealError::InvalidInput(format!(
+            "the index {index} of {field} holds embeddings of {indexed} dimensions, but model {model} returns {dimensions}; remove it with `REMOVE INDEX {index} ON TABLE {table}` and embed again",
+            index = index_name(field),
+            model = embedder.name(),
+            table = field.table,
+        )));
+    }
+
+    execute_all(
+        config,
+        &vector_index_statements(field, dimensions).join("\n"),
+        serde_json::Map::new(),
+    )
+    .await?;
+
+    let updates = stale
+        .iter()
+        .zip(vectors)
+        .map(|((id, _, hash), vector)| json!({ "id": id, "hash": hash, "embedding": vector }))
+        .collect::<Vec<_>>();
+
+    execute_all(
+        config,
+        &format!(
+            "FOR $row IN $rows {{ UPDATE type::record($row.id) SET {} = $row.embedding, {} = $row.hash; }}",
+            escape(&embedding_field(&field.field)),
+            escape(&hash_field(&field.field)),
+        ),
+        json!({ "rows": updates }),
+    )
+    .await?;
+    tracing::info!(
+        rows = report.rows,
+        embedded = report.embedded,
+        dimensions,
+        "embeddings stored"
+    );
+
+    Ok(report)
+}
+
+/// Dimension of the HNSW index on the embeddings of `field`, when it exists
+async fn index_dimension(
+    config: &SurrealDbConfig,
+    field: &SearchField,
+) -> Result<Option<usize>, SurrealError> {
+    let info = execute_query(config, &format!("INFO FOR TABLE {}", escape(&field.table))).await?;
+
+    let dimension = info["indexes"][index_name(field)]
+        .as_str()
+        .and_then(|definition| definition.split_once(" DIMENSION "))
+        .and_then(|(_, rest)| rest.split_whitespace().next())
+        .and_then(|dimension| dimension.parse().ok());
+
+    Ok(dimension)
+}
+
+/// Arguments for the SurrealDB vector search tool
+#[derive(Deserialize, Serialize)]
+pub struct SurrealVectorSearchArgs {
+    /// Table to search
+    pub table: String,
+    /// Text field whose embeddings are searched
+    pub field: String,
+    /// What the rows should be about
+    pub query: String,
+    /// Number of rows to return, at most the candidates the index considers
+    #[serde(default)]
+    pub k: Option<usize>,
+}
+
+impl SurrealVectorSearchArgs {
+    /// The `SELECT` statement finding the nearest rows to `$vector`
+    fn statement(&self) -> String {
+        let embedding = escape(&embedding_field(&self.field));
+
+        format!(
+            "SELECT *, vector::similarity::cosine({embedding}, $vector) AS similarity OMIT {embedding}, {} FROM {} WHERE {embedding} <|{},{SEARCH_EF}|> $vector ORDER BY similarity DESC",
+            escape(&hash_field(&self.field)),
+            escape(&self.table),
+            self.k.unwrap_or(DEFAULT_NEIGHBOURS).clamp(1, SEARCH_EF),
+        )
+    }
+}
+
+/// SurrealDB Vector Search Tool returning the rows of a table whose text is
+/// closest in meaning to a query
+#[derive(Clone)]
+pub struct SurrealVectorSearchTool {
+    config: SurrealDbConfig,
+    embedder: Embedder,
+    ledger: Option<QueryLedger>,
+}
+
+impl SurrealVectorSearchTool {
+    pub fn new(config: SurrealDbConfig, embedder: Embedder) -> Self {
+        Self {
+            config,
+            embedder,
+            ledger: None,
+        }
+    }
+
+    /// Record every search and the record IDs it returned in the given ledger
+    pub fn with_ledger(mut self, ledger: QueryLedger) -> Self {
+        self.ledger = Some(ledger);
+        self
+    }
+}
+
+impl Tool for SurrealVectorSearchTool {
+    const NAME: &'static str = "surreal_vector_search";
+
+    type Error = SurrealError;
+    type Args = SurrealVectorSearchArgs;
+    type Output = String;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "Semantic search of a text field with embeddings (fields with an `<field>_embedding` field and an HNSW index in surreal_schema). Returns the rows whose text is closest in meaning to the query, with their cosine `similarity` (1 is identical), even when they share no words with it. Use it for questions about a topic, e.g. 'requests related to security'.".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "table": {
+                        "type": "string",
+                        "description": "Table to search"
+                    },
+                    "field": {
+                        "type": "string",
+                        "description": "Text field whose embeddings are searched, e.g. `message`"
+                    },
+                    "query": {
+                        "type": "string",
+                        "description": "What the rows should be about"
+                    },
+                    "k": {
+                        "type": "integer",
+                        "description": format!("Number of rows to return (default {DEFAULT_NEIGHBOURS}, at most {SEARCH_EF})")
+                    }
+                },
+                "required": ["table", "field", "query"]
+            }),
+        }
+    }
+
+    #[tracing::instrument(
+        name = "tool_call",
+        skip_all,
+        fields(
+            tool = Self::NAME,
+            query = field::Empty,
+            rows = field::Empty,
+            duration_ms = field::Empty,
+            error = field::Empty,
+        )
+    )]
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let span = tracing::Span::current();
+
+        if args.query.trim().is_empty() {
+            return Ok("Search validation error: query cannot be empty".to_string());
+        }
+
+        let statement = args.statement();
+        span.record("query", &statement);
+
+        // The ledger shows the search as the statement and the text it embedded
+        let query = format!("{statement} -- $vector: {}", args.query);
+        if let Some(ledger) = &self.ledger {
+            ledger.record_issued(&query);
+        }
+
+        let started = Instant::now();
+        let result = match self.embedder.embed(&args.query).await {
+            Ok(vector) => {
+                execute_bound(&self.config, &statement, json!({ "vector": vector })).await
+            }
+            Err(e) => Err(e),
+        };
+        span.record("duration_ms", started.elapsed().as_millis() as u64);
+
+        match result {
+            Ok(result) => {
+                let rows = result.as_array().map_or(0, Vec::len);
+                span.record("rows", rows);
+                tracing::info!(rows, "vector search finished");
+
+                if let Some(ledger) = &self.ledger {
+                    ledger.record_result(&query, &result);
+                }
+
+                Ok(SurrealSelectTool::new(self.config.clone()).format_result(&result))
+            }
+            Err(e) => {
+                span.record("error", field::display(&e));
+                tracing::warn!(error = %e, "vector search failed");
+
+                if let Some(ledger) = &self.ledger {
+                    ledger.record_error(&query, &e.to_string());
+                }
+
+                // Returned as a successful response so the LLM can fall back to other tools
+                Ok(format!(
+                    "Vector search error: {e}\n\nCheck that the field has embeddings with surreal_schema, or use surreal_search or surreal_select."
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{agents::mock::TopicEmbedding, testing::seeded_db};
+
+    fn topic_model() -> TopicEmbedding {
+        TopicEmbedding::new(&[
+            &["sso", "security", "login", "password"],
+            &["export", "csv", "report"],
+            &["dark", "theme", "color"],
+        ])
+    }
+
+    fn topics() -> Embedder {
+        Embedder::new("topics", topic_model())
+    }
+
+    fn message() -> SearchField {
+        "feature_requests.message".parse().unwrap()
+    }
+
+    #[test]
+    fn test_text_hash_is_stable() {
+        assert_eq!(text_hash(""), "cbf29ce484222325");
+        assert_ne!(text_hash("SSO"), text_hash("sso"));
+    }
+
+    #[tokio::test]
+    async fn test_embed_field_is_incremental() {
+        let config = SurrealDbConfig::from(&seeded_db("vector-embed").await);
+        let embedder = topics();
+
+        let first = embed_field(&config, &embedder, &message()).await.unwrap();
+        let second = embed_field(&config, &embedder, &message()).await.unwrap();
+        execute_query(
+            &config,
+            "UPDATE feature_requests:dark_mode SET message = 'Please add a dark theme.'",
+        )
+        .await
+        .unwrap();
+        let third = embed_field(&config, &embedder, &message()).await.unwrap();
+
+        assert_eq!((first.rows, first.embedded), (3, 3));
+        assert_eq!(second.embedded, 0);
+        assert_eq!(third.embedded, 1);
+    }
+
+    #[tokio::test]
+    async fn test_embed_field_reembeds_for_another_model() {
+        let config = SurrealDbConfig::from(&seeded_db("vector-model").await);
+        embed_field(&config, &topics(), &message()).await.unwrap();
+
+        let renamed = Embedder::new("topics-v2", topic_model());
+        let report = embed_field(&config, &renamed, &message()).await.unwrap();
+        assert_eq!(report.embedded, 3);
+
+        let wider = Embedder::new(
+            "wider",
+            TopicEmbedding::new(&[&["sso"], &["export"], &["dark"], &["api"]]),
+        );
+        let err = embed_field(&config, &wider, &message()).await.unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("holds embeddings of 3 dimensions, but model wider returns 4")
+        );
+    }
+
+    #[test]
+    fn test_vector_search_clamps_k() {
+        let args = SurrealVectorSearchArgs {
+            table: "feature_requests".to_string(),
+            field: "message".to_string(),
+            query: "security".to_string(),
+            k: Some(1_000),
+        };
+
+        assert!(
+            args.statement()
+                .contains(&format!("<|{SEARCH_EF},{SEARCH_EF}|>"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_vector_search_finds_rows_by_topic() {
+        let config = SurrealDbConfig::from(&seeded_db("vector-search").await);
+        let embedder = topics();
+        embed_field(&config, &embedder, &message()).await.unwrap();
+
+        let ledger = QueryLedger::new();
+        let output = SurrealVectorSearchTool::new(config, embedder)
+            .with_ledger(ledger.clone())
+            .call(SurrealVectorSearchArgs {
+                table: "feature_requests".to_string(),
+                field: "message".to_string(),
+                query: "requests related to security and login".to_string(),
+                k: Some(1),
+            })
+            .await
+            .unwrap();
+
+        assert!(output.contains("Found 1 record(s)"));
+        assert!(output.contains("SSO"));
+        assert!(output.contains("similarity:"));
+        assert!(!output.contains("message_embedding"));
+        assert_eq!(
+            ledger.record_ids(),
+            ["feature_requests:sso".to_string()].into()
+        );
+    }
+}
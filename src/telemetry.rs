@@ -0,0 +1,185 @@
+//! Tracing subscriber setup
+//!
+//! Logs go to stderr, either human-readable or as JSON lines, so stdout
+//! stays reserved for the final answer. With the `otel` feature, spans can
+//! also be exported to an OpenTelemetry collector over OTLP/HTTP.
+//!
+//! The log level is read from `RUST_LOG` and defaults to `info` for this
+//! crate and `warn` for its dependencies.
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::str::FromStr;
+
+use tracing_subscriber::{EnvFilter, Layer, layer::SubscriberExt, util::SubscriberInitExt};
+
+const DEFAULT_FILTER: &str = "warn,rig_tutorial=info";
+
+/// Format of the logs written to stderr
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Multi-line, human-readable output
+    #[default]
+    Pretty,
+    /// One JSON object per line
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = TelemetryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "pretty" => Ok(LogFormat::Pretty),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(TelemetryError::InvalidFormat(s.to_string())),
+        }
+    }
+}
+
+/// Error setting up the tracing subscriber
+#[derive(Debug)]
+pub enum TelemetryError {
+    InvalidFormat(String),
+    Init(tracing_subscriber::util::TryInitError),
+    /// An OTLP endpoint was configured but the `otel` feature is disabled
+    OtlpUnsupported,
+    #[cfg(feature = "otel")]
+    Otlp(opentelemetry_otlp::ExporterBuildError),
+}
+
+impl fmt::Display for TelemetryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TelemetryError::InvalidFormat(format) => {
+                write!(
+                    f,
+                    "Unknown log format '{format}', expected 'pretty' or 'json'"
+                )
+            }
+            TelemetryError::Init(err) => write!(f, "Failed to install tracing subscriber: {err}"),
+            TelemetryError::OtlpUnsupported => {
+                write!(f, "OTLP export requires building with the 'otel' feature")
+            }
+            #[cfg(feature = "otel")]
+            TelemetryError::Otlp(err) => write!(f, "Failed to create OTLP exporter: {err}"),
+        }
+    }
+}
+
+impl StdError for TelemetryError {}
+
+impl From<tracing_subscriber::util::TryInitError> for TelemetryError {
+    fn from(err: tracing_subscriber::util::TryInitError) -> Self {
+        TelemetryError::Init(err)
+    }
+}
+
+#[cfg(feature = "otel")]
+impl From<opentelemetry_otlp::ExporterBuildError> for TelemetryError {
+    fn from(err: opentelemetry_otlp::ExporterBuildError) -> Self {
+        TelemetryError::Otlp(err)
+    }
+}
+
+/// Flushes exported spans when dropped; keep it alive until the program exits
+#[must_use = "spans are only flushed when the guard is dropped"]
+#[derive(Debug, Default)]
+pub struct TelemetryGuard {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = self.provider.take()
+            && let Err(err) = provider.shutdown()
+        {
+            eprintln!("Failed to flush OTLP spans: {err}");
+        }
+    }
+}
+
+/// Install the global tracing subscriber
+///
+/// `otlp_endpoint` is the base URL of an OTLP/HTTP collector, e.g.
+/// `http://localhost:4318`.
+pub fn init(
+    format: LogFormat,
+    otlp_endpoint: Option<&str>,
+) -> Result<TelemetryGuard, TelemetryError> {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+
+    let logs = match format {
+        LogFormat::Pretty => tracing_subscriber::fmt::layer()
+            .pretty()
+            .with_writer(std::io::stderr)
+            .boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_writer(std::io::stderr)
+            .boxed(),
+    };
+
+    let registry = tracing_subscriber::registry().with(logs).with(filter);
+
+    match otlp_endpoint {
+        #[cfg(feature = "otel")]
+        Some(endpoint) => {
+            let provider = otlp_provider(endpoint)?;
+            let tracer = opentelemetry::trace::TracerProvider::tracer(&provider, "rig-tutorial");
+
+            registry
+                .with(tracing_opentelemetry::layer().with_tracer(tracer))
+                .try_init()?;
+
+            Ok(TelemetryGuard {
+                provider: Some(provider),
+            })
+        }
+        #[cfg(not(feature = "otel"))]
+        Some(_) => Err(TelemetryError::OtlpUnsupported),
+        None => {
+            registry.try_init()?;
+            Ok(TelemetryGuard::default())
+        }
+    }
+}
+
+#[cfg(feature = "otel")]
+fn otlp_provider(
+    endpoint: &str,
+) -> Result<opentelemetry_sdk::trace::SdkTracerProvider, TelemetryError> {
+    use opentelemetry_otlp::WithExportConfig;
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+        .build()?;
+
+    Ok(opentelemetry_sdk::trace::SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(
+            opentelemetry_sdk::Resource::builder()
+                .with_service_name("rig-tutorial")
+                .build(),
+        )
+        .build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_log_format() {
+        assert_eq!("pretty".parse::<LogFormat>().unwrap(), LogFormat::Pretty);
+        assert_eq!(" JSON ".parse::<LogFormat>().unwrap(), LogFormat::Json);
+        assert!(matches!(
+            "xml".parse::<LogFormat>(),
+            Err(TelemetryError::InvalidFormat(_))
+        ));
+    }
+}
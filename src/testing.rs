@@ -0,0 +1,124 @@
+//! Helpers shared by the unit tests
+
+use serde_json::{Value, json};
+
+use crate::{
+    agents::{SubAgentResult, mock::ScriptedModel},
+    citations::CitationReport,
+    config::SurrealConfig,
+    pipeline::{PipelineResult, SubAgentConfig},
+    surreal::{self, SurrealDbConfig},
+};
+
+/// Configuration of an embedded database named `name`, seeded with the
+/// records of `fixtures/test.surql`
+///
+/// Use a distinct name per test so tests running in parallel do not share
+/// data.
+pub(crate) async fn seeded_db(name: &str) -> SurrealConfig {
+    let config = SurrealConfig {
+        host: format!("mem://{name}"),
+        username: String::new(),
+        password: String::new(),
+        namespace: "test".to_string(),
+        database: "test".to_string(),
+    };
+
+    surreal::connect(&SurrealDbConfig::from(&config))
+        .await
+        .unwrap()
+        .query(include_str!("../fixtures/test.surql"))
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+    config
+}
+
+/// Sub-agent answering from the `customers` table of [`seeded_db`]
+pub(crate) fn customers_sub_agent() -> SubAgentConfig {
+    SubAgentConfig {
+        name: "customers".to_string(),
+        description: "Finds customers and their ARR.".to_string(),
+        table: "customers".to_string(),
+        table_context: "This table lists customers.".to_string(),
+        mcp: None,
+        file: None,
+    }
+}
+
+/// Replies of a pipeline with [`customers_sub_agent`] answering "Who pays
+/// the most?" with one query
+pub(crate) fn one_query_script() -> ScriptedModel {
+    ScriptedModel::new()
+        .text(r#"{"customers": "Which customer has the highest ARR?"}"#)
+        .tool_call(
+            "surreal_select",
+            json!({ "query": "SELECT id, name, arr FROM customers ORDER BY arr DESC LIMIT 1" }),
+        )
+        .text("Acme Corp (customers:acme) has the highest ARR.")
+        .text("Acme Corp (customers:acme) pays the most.")
+}
+
+/// Builder of a [`PipelineResult`] in which a `customers` sub-agent answered
+/// from a single row, citing `customers:acme`
+pub(crate) struct PipelineResultBuilder {
+    result: PipelineResult,
+}
+
+impl PipelineResultBuilder {
+    pub(crate) fn new(question: &str) -> Self {
+        Self {
+            result: PipelineResult {
+                question: question.to_string(),
+                sub_questions: [("customers".to_string(), "Who has the most ARR?".to_string())]
+                    .into(),
+                sub_results: vec![SubAgentResult {
+                    agent: "customers".to_string(),
+                    question: "Who has the most ARR?".to_string(),
+                    answer: "Acme Corp (customers:acme).".to_string(),
+                    rows_used: vec!["customers:acme".to_string().into()],
+                    queries_run: vec!["SELECT id, arr FROM customers;".to_string()],
+                    rows: vec![json!({ "id": "customers:acme", "arr": 250000 })],
+                    citations: CitationReport {
+                        cited: vec!["customers:acme".to_string()],
+                        ..Default::default()
+                    },
+                }],
+                joined: None,
+                answer: "Acme Corp.".to_string(),
+                citations: CitationReport::default(),
+                prompt_versions: Default::default(),
+                usage: Default::default(),
+                run_id: None,
+                history: Vec::new(),
+            },
+        }
+    }
+
+    pub(crate) fn answer(mut self, answer: &str) -> Self {
+        self.result.answer = answer.to_string();
+        self
+    }
+
+    /// Rows returned to the `customers` sub-agent
+    pub(crate) fn rows(mut self, rows: Vec<Value>) -> Self {
+        self.result.sub_results[0].rows = rows;
+        self
+    }
+
+    pub(crate) fn cited(mut self, ids: &[&str]) -> Self {
+        self.result.citations.cited = ids.iter().map(|id| id.to_string()).collect();
+        self
+    }
+
+    pub(crate) fn run_id(mut self, run_id: &str) -> Self {
+        self.result.run_id = Some(run_id.to_string());
+        self
+    }
+
+    pub(crate) fn build(self) -> PipelineResult {
+        self.result
+    }
+}
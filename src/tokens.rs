@@ -0,0 +1,71 @@
+//! Token count estimates used to keep prompts within a model's context window
+
+/// Estimates the number of tokens a provider's tokenizer produces for a text
+///
+/// Counting exactly would need each provider's tokenizer. For budgeting it is
+/// enough to know the average number of characters per token, which is
+/// stable for English text and JSON within a model family.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tokenizer {
+    chars_per_token: f64,
+}
+
+impl Tokenizer {
+    /// Create a tokenizer estimate from an average number of characters per token
+    pub fn new(chars_per_token: f64) -> Self {
+        Self {
+            chars_per_token: chars_per_token.max(1.0),
+        }
+    }
+
+    /// Tokenizer estimate for a provider, e.g. `xai`, `openai` or `anthropic`
+    ///
+    /// Unknown providers get a conservative estimate that overcounts rather
+    /// than overflows the context window.
+    pub fn for_provider(provider: &str) -> Self {
+        match provider.to_lowercase().as_str() {
+            "xai" | "openai" | "azure" | "deepseek" | "groq" | "together" | "openrouter" => {
+                Self::new(4.0)
+            }
+            "gemini" => Self::new(4.0),
+            "anthropic" => Self::new(3.5),
+            "cohere" | "mistral" => Self::new(3.8),
+            _ => Self::new(3.0),
+        }
+    }
+
+    /// Estimated number of tokens in `text`
+    pub fn estimate(&self, text: &str) -> usize {
+        (text.chars().count() as f64 / self.chars_per_token).ceil() as usize
+    }
+}
+
+impl Default for Tokenizer {
+    fn default() -> Self {
+        Self::for_provider("")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate() {
+        let tokenizer = Tokenizer::for_provider("xai");
+
+        assert_eq!(tokenizer.estimate(""), 0);
+        assert_eq!(tokenizer.estimate("abcd"), 1);
+        assert_eq!(tokenizer.estimate("abcde"), 2);
+    }
+
+    #[test]
+    fn test_unknown_provider_overcounts() {
+        let text = "Which feature requests should I prioritize?";
+
+        assert!(
+            Tokenizer::for_provider("unknown").estimate(text)
+                > Tokenizer::for_provider("openai").estimate(text)
+        );
+    }
+}
@@ -0,0 +1,408 @@
+//! Token usage and cost accounting
+//!
+//! Every LLM call of a run is recorded in a [`UsageMeter`] with the token
+//! counts reported by the provider. A [`PriceTable`] turns the calls into a
+//! [`UsageReport`] aggregated per stage, sub-agent and model.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::AddAssign;
+use std::sync::{Arc, Mutex};
+
+use rig::providers::{openai, xai};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Prompt and completion tokens of one or more LLM calls
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenCount {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl TokenCount {
+    pub fn total(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+impl AddAssign for TokenCount {
+    fn add_assign(&mut self, other: Self) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+    }
+}
+
+/// Raw provider response reporting the tokens it used
+pub trait TokenUsage {
+    /// Token counts, or `None` when the provider did not report them
+    fn token_usage(&self) -> Option<TokenCount>;
+}
+
+/// Responses of models that never report their usage
+impl TokenUsage for () {
+    fn token_usage(&self) -> Option<TokenCount> {
+        None
+    }
+}
+
+impl TokenUsage for xai::completion::xai_api_types::CompletionResponse {
+    fn token_usage(&self) -> Option<TokenCount> {
+        Some(TokenCount {
+            prompt_tokens: self.usage.prompt_tokens.max(0) as u64,
+            completion_tokens: self.usage.completion_tokens.max(0) as u64,
+        })
+    }
+}
+
+impl TokenUsage for openai::CompletionResponse {
+    fn token_usage(&self) -> Option<TokenCount> {
+        self.usage.as_ref().map(|usage| TokenCount {
+            prompt_tokens: usage.prompt_tokens as u64,
+            completion_tokens: usage.total_tokens.saturating_sub(usage.prompt_tokens) as u64,
+        })
+    }
+}
+
+impl TokenUsage for openai::StreamingCompletionResponse {
+    fn token_usage(&self) -> Option<TokenCount> {
+        // Streams that never received a usage chunk report zeros
+        (self.usage.total_tokens > 0).then(|| TokenCount {
+            prompt_tokens: self.usage.prompt_tokens as u64,
+            completion_tokens: self
+                .usage
+                .total_tokens
+                .saturating_sub(self.usage.prompt_tokens) as u64,
+        })
+    }
+}
+
+/// Stage of the pipeline an LLM call belongs to
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    #[default]
+    Map,
+    Query,
+    Summarize,
+    Reduce,
+    /// Scoring of answers during an evaluation
+    Judge,
+    /// Labelling of clusters of similar rows
+    Label,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Stage::Map => "map",
+            Stage::Query => "query",
+            Stage::Summarize => "summarize",
+            Stage::Reduce => "reduce",
+            Stage::Judge => "judge",
+            Stage::Label => "label",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A single request to a model, with its messages and response
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LlmCall {
+    pub stage: Stage,
+    /// Sub-agent that made the call, for the query stage
+    pub agent: Option<String>,
+    pub model: String,
+    /// `None` when the provider did not report usage
+    pub tokens: Option<TokenCount>,
+    /// Time until the response was complete
+    #[serde(default)]
+    pub duration_ms: u64,
+    #[serde(default)]
+    pub preamble: Option<String>,
+    /// Chat history sent with the request, ending with the prompt
+    #[serde(default)]
+    pub messages: Vec<Value>,
+    /// Text and tool calls of the response
+    #[serde(default)]
+    pub response: Vec<Value>,
+    /// Error message if the request failed
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// Shared, append-only log of the LLM calls of a run
+#[derive(Clone, Debug, Default)]
+pub struct UsageMeter {
+    calls: Arc<Mutex<Vec<LlmCall>>>,
+}
+
+impl UsageMeter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, call: LlmCall) {
+        self.calls.lock().unwrap().push(call);
+    }
+
+    /// Every call recorded so far, in order
+    pub fn calls(&self) -> Vec<LlmCall> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Aggregate the recorded calls, priced with `prices`
+    pub fn report(&self, prices: &PriceTable) -> UsageReport {
+        UsageReport::new(&self.calls(), prices)
+    }
+}
+
+/// Price of a model in USD per million tokens
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input_per_million: f64,
+    pub output_per_million: f64,
+}
+
+impl ModelPrice {
+    pub fn cost(&self, tokens: TokenCount) -> f64 {
+        (tokens.prompt_tokens as f64 * self.input_per_million
+            + tokens.completion_tokens as f64 * self.output_per_million)
+            / 1_000_000.0
+    }
+}
+
+/// Prices of the models a pipeline may call, keyed by model name
+///
+/// Loaded from JSON as `{ "<model>": { "input_per_million": .., "output_per_million": .. } }`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PriceTable {
+    models: BTreeMap<String, ModelPrice>,
+}
+
+impl PriceTable {
+    /// A table without any prices
+    pub fn empty() -> Self {
+        Self {
+            models: BTreeMap::new(),
+        }
+    }
+
+    /// Parse a price table from JSON
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// Set the price of a model
+    pub fn price(
+        mut self,
+        model: impl Into<String>,
+        input_per_million: f64,
+        output_per_million: f64,
+    ) -> Self {
+        self.models.insert(
+            model.into(),
+            ModelPrice {
+                input_per_million,
+                output_per_million,
+            },
+        );
+        self
+    }
+
+    pub fn get(&self, model: &str) -> Option<&ModelPrice> {
+        self.models.get(model)
+    }
+}
+
+impl Default for PriceTable {
+    /// List prices of the xAI models at the time of writing
+    fn default() -> Self {
+        Self::empty()
+            .price("grok-3-mini", 0.30, 0.50)
+            .price("grok-3", 3.00, 15.00)
+            .price("grok-4", 3.00, 15.00)
+    }
+}
+
+/// Tokens and cost of a group of LLM calls
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageTotals {
+    pub calls: usize,
+    pub tokens: TokenCount,
+    /// Cost of the calls with a known price and reported usage
+    pub cost_usd: f64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, tokens: TokenCount, cost: f64) {
+        self.calls += 1;
+        self.tokens += tokens;
+        self.cost_usd += cost;
+    }
+}
+
+/// Token usage and cost of a run
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageReport {
+    pub total: UsageTotals,
+    pub by_stage: BTreeMap<Stage, UsageTotals>,
+    /// Query stage calls, per sub-agent
+    pub by_agent: BTreeMap<String, UsageTotals>,
+    pub by_model: BTreeMap<String, UsageTotals>,
+    /// Models missing from the price table; their calls are not costed
+    pub unpriced_models: BTreeSet<String>,
+    /// Successful calls for which the provider reported no usage
+    pub unreported_calls: usize,
+}
+
+impl UsageReport {
+    pub fn new(calls: &[LlmCall], prices: &PriceTable) -> Self {
+        let mut report = Self::default();
+
+        for call in calls {
+            let tokens = call.tokens.unwrap_or_default();
+            if call.tokens.is_none() && call.error.is_none() {
+                report.unreported_calls += 1;
+            }
+
+            let cost = match prices.get(&call.model) {
+                Some(price) => price.cost(tokens),
+                None => {
+                    report.unpriced_models.insert(call.model.clone());
+                    0.0
+                }
+            };
+
+            report.total.add(tokens, cost);
+            report
+                .by_stage
+                .entry(call.stage)
+                .or_default()
+                .add(tokens, cost);
+            report
+                .by_model
+                .entry(call.model.clone())
+                .or_default()
+                .add(tokens, cost);
+
+            if let Some(agent) = &call.agent {
+                report
+                    .by_agent
+                    .entry(agent.clone())
+                    .or_default()
+                    .add(tokens, cost);
+            }
+        }
+
+        report
+    }
+}
+
+impl fmt::Display for UsageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let row = |f: &mut fmt::Formatter<'_>, label: &str, totals: &UsageTotals| {
+            writeln!(
+                f,
+                "{:<24} {:>6} {:>10} {:>11} {:>10.4}",
+                label,
+                totals.calls,
+                totals.tokens.prompt_tokens,
+                totals.tokens.completion_tokens,
+                totals.cost_usd
+            )
+        };
+
+        writeln!(
+            f,
+            "{:<24} {:>6} {:>10} {:>11} {:>10}",
+            "stage", "calls", "prompt", "completion", "cost (USD)"
+        )?;
+        for (stage, totals) in &self.by_stage {
+            row(f, &stage.to_string(), totals)?;
+
+            // Sub-agents are listed under the query stage they belong to
+            if *stage == Stage::Query {
+                for (agent, totals) in &self.by_agent {
+                    row(f, &format!("  {agent}"), totals)?;
+                }
+            }
+        }
+        row(f, "total", &self.total)?;
+
+        if !self.unpriced_models.is_empty() {
+            let models = self.unpriced_models.iter().cloned().collect::<Vec<_>>();
+            writeln!(f, "no price for: {}", models.join(", "))?;
+        }
+        if self.unreported_calls > 0 {
+            writeln!(f, "{} call(s) reported no usage", self.unreported_calls)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(stage: Stage, agent: Option<&str>, model: &str, tokens: Option<(u64, u64)>) -> LlmCall {
+        LlmCall {
+            stage,
+            agent: agent.map(str::to_string),
+            model: model.to_string(),
+            tokens: tokens.map(|(prompt_tokens, completion_tokens)| TokenCount {
+                prompt_tokens,
+                completion_tokens,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_report_aggregates() {
+        let prices = PriceTable::empty().price("cheap", 1.0, 2.0);
+        let calls = [
+            call(Stage::Map, None, "cheap", Some((1_000, 100))),
+            call(Stage::Query, Some("customers"), "cheap", Some((2_000, 200))),
+            call(Stage::Query, Some("customers"), "cheap", Some((3_000, 300))),
+            call(Stage::Query, Some("feature_requests"), "cheap", None),
+            call(Stage::Reduce, None, "unknown", Some((500, 50))),
+        ];
+
+        let report = UsageReport::new(&calls, &prices);
+
+        assert_eq!(report.total.calls, 5);
+        assert_eq!(report.total.tokens.prompt_tokens, 6_500);
+        assert_eq!(report.by_stage[&Stage::Query].calls, 3);
+        assert_eq!(report.by_agent["customers"].tokens.total(), 5_500);
+        assert_eq!(report.by_agent["feature_requests"].tokens.total(), 0);
+        assert!(!report.by_agent.contains_key("map"));
+        assert_eq!(report.unreported_calls, 1);
+        assert_eq!(
+            report.unpriced_models,
+            BTreeSet::from(["unknown".to_string()])
+        );
+
+        // 6_000 prompt tokens at $1/M and 600 completion tokens at $2/M
+        assert!((report.total.cost_usd - 0.0072).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_price_table_from_json() {
+        let prices = PriceTable::from_json(
+            r#"{ "grok-3-mini": { "input_per_million": 0.3, "output_per_million": 0.5 } }"#,
+        )
+        .unwrap();
+
+        let cost = prices.get("grok-3-mini").unwrap().cost(TokenCount {
+            prompt_tokens: 1_000_000,
+            completion_tokens: 1_000_000,
+        });
+        assert!((cost - 0.8).abs() < 1e-9);
+        assert!(prices.get("grok-4").is_none());
+    }
+}